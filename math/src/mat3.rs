@@ -32,6 +32,7 @@ impl Mat3 {
         },
     };
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         m00: f32,
         m01: f32,
@@ -259,6 +260,7 @@ impl std::ops::Div<f32> for Mat3 {
 impl std::ops::Div<Mat3> for Mat3 {
     type Output = Mat3;
 
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn div(self, b: Mat3) -> Mat3 {
         self * b.inverse().unwrap()
     }
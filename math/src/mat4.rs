@@ -1,5 +1,6 @@
 use crate::Vec4;
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Mat4 {
     pub c0: Vec4,
     pub c1: Vec4,
@@ -1,5 +1,6 @@
 use crate::Mat4;
 
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Vec4 {
     pub x: f32,
     pub y: f32,
@@ -7,7 +8,22 @@ pub struct Vec4 {
     pub w: f32,
 }
 
-impl Vec4 {}
+impl Vec4 {
+    pub const ZERO: Vec4 = Vec4 {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+        w: 0.0,
+    };
+
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Vec4 {
+        Self { x, y, z, w }
+    }
+
+    pub fn dot(a: Vec4, b: Vec4) -> f32 {
+        a.x * b.x + a.y * b.y + a.z * b.z + a.w * b.w
+    }
+}
 
 impl std::ops::Add<Vec4> for Vec4 {
     type Output = Vec4;
@@ -0,0 +1,254 @@
+use math::Vec4;
+
+/// Scales a term of the blend equation. `Src` is the fragment color, `Dst`
+/// the color already in the target and `Constant` the blend state's constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendFactor {
+    Zero,
+    One,
+    Src,
+    OneMinusSrc,
+    SrcAlpha,
+    OneMinusSrcAlpha,
+    Dst,
+    OneMinusDst,
+    DstAlpha,
+    OneMinusDstAlpha,
+    /// `min(src.a, 1 - dst.a)` for color, one for alpha.
+    SrcAlphaSaturated,
+    Constant,
+    OneMinusConstant,
+}
+
+impl BlendFactor {
+    fn value(self, src: Vec4, dst: Vec4, constant: Vec4) -> Vec4 {
+        let splat = |v: f32| Vec4::new(v, v, v, v);
+        let one = splat(1.0);
+
+        match self {
+            BlendFactor::Zero => Vec4::ZERO,
+            BlendFactor::One => one,
+            BlendFactor::Src => src,
+            BlendFactor::OneMinusSrc => one - src,
+            BlendFactor::SrcAlpha => splat(src.w),
+            BlendFactor::OneMinusSrcAlpha => splat(1.0 - src.w),
+            BlendFactor::Dst => dst,
+            BlendFactor::OneMinusDst => one - dst,
+            BlendFactor::DstAlpha => splat(dst.w),
+            BlendFactor::OneMinusDstAlpha => splat(1.0 - dst.w),
+            BlendFactor::SrcAlphaSaturated => {
+                let f = src.w.min(1.0 - dst.w);
+                Vec4::new(f, f, f, 1.0)
+            }
+            BlendFactor::Constant => constant,
+            BlendFactor::OneMinusConstant => one - constant,
+        }
+    }
+}
+
+/// How the scaled source and destination terms are combined. `Min` and `Max`
+/// ignore the factors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlendOperation {
+    #[default]
+    Add,
+    /// `src - dst`
+    Subtract,
+    /// `dst - src`
+    ReverseSubtract,
+    Min,
+    Max,
+}
+
+impl BlendOperation {
+    fn apply(self, src: f32, dst: f32, src_factor: f32, dst_factor: f32) -> f32 {
+        match self {
+            BlendOperation::Add => src * src_factor + dst * dst_factor,
+            BlendOperation::Subtract => src * src_factor - dst * dst_factor,
+            BlendOperation::ReverseSubtract => dst * dst_factor - src * src_factor,
+            BlendOperation::Min => src.min(dst),
+            BlendOperation::Max => src.max(dst),
+        }
+    }
+}
+
+/// The blend equation for either the color or the alpha channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlendComponent {
+    pub src_factor: BlendFactor,
+    pub dst_factor: BlendFactor,
+    pub operation:  BlendOperation,
+}
+
+impl BlendComponent {
+    /// Writes the source and discards the destination.
+    pub const REPLACE: Self = Self {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::Zero,
+        operation:  BlendOperation::Add,
+    };
+
+    /// `src * src.a + dst * (1 - src.a)`
+    pub const OVER: Self = Self {
+        src_factor: BlendFactor::SrcAlpha,
+        dst_factor: BlendFactor::OneMinusSrcAlpha,
+        operation:  BlendOperation::Add,
+    };
+
+    /// `src + dst * (1 - src.a)`, for premultiplied sources.
+    pub const PREMULTIPLIED_OVER: Self = Self {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::OneMinusSrcAlpha,
+        operation:  BlendOperation::Add,
+    };
+
+    /// `src + dst`
+    pub const ADDITIVE: Self = Self {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::One,
+        operation:  BlendOperation::Add,
+    };
+}
+
+impl Default for BlendComponent {
+    fn default() -> Self {
+        Self::REPLACE
+    }
+}
+
+/// Combines a fragment's color with the color already in the render target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlendState {
+    pub color:    BlendComponent,
+    pub alpha:    BlendComponent,
+    /// Read by the `Constant` factors.
+    pub constant: Vec4,
+}
+
+impl BlendState {
+    pub const REPLACE: Self = Self::new(BlendComponent::REPLACE, BlendComponent::REPLACE);
+
+    /// Straight-alpha "over" compositing.
+    pub const ALPHA_BLENDING: Self =
+        Self::new(BlendComponent::OVER, BlendComponent::PREMULTIPLIED_OVER);
+
+    /// "Over" compositing of premultiplied colors.
+    pub const PREMULTIPLIED_ALPHA_BLENDING: Self = Self::new(
+        BlendComponent::PREMULTIPLIED_OVER,
+        BlendComponent::PREMULTIPLIED_OVER,
+    );
+
+    pub const ADDITIVE: Self = Self::new(BlendComponent::ADDITIVE, BlendComponent::ADDITIVE);
+
+    pub const fn new(color: BlendComponent, alpha: BlendComponent) -> Self {
+        Self {
+            color,
+            alpha,
+            constant: Vec4::ZERO,
+        }
+    }
+
+    pub fn blend(&self, src: Vec4, dst: Vec4) -> Vec4 {
+        let factors = |component: &BlendComponent| {
+            (
+                component.src_factor.value(src, dst, self.constant),
+                component.dst_factor.value(src, dst, self.constant),
+            )
+        };
+
+        let (src_color, dst_color) = factors(&self.color);
+        let (src_alpha, dst_alpha) = factors(&self.alpha);
+        let color = |s: f32, d: f32, sf: f32, df: f32| self.color.operation.apply(s, d, sf, df);
+
+        Vec4::new(
+            color(src.x, dst.x, src_color.x, dst_color.x),
+            color(src.y, dst.y, src_color.y, dst_color.y),
+            color(src.z, dst.z, src_color.z, dst_color.z),
+            self.alpha
+                .operation
+                .apply(src.w, dst.w, src_alpha.w, dst_alpha.w),
+        )
+    }
+}
+
+impl Default for BlendState {
+    fn default() -> Self {
+        Self::REPLACE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_composite_over_destination() {
+        let src = Vec4::new(1.0, 0.0, 0.0, 0.5);
+        let dst = Vec4::new(0.0, 0.0, 1.0, 1.0);
+
+        assert_eq!(BlendState::REPLACE.blend(src, dst), src);
+        assert_eq!(
+            BlendState::ALPHA_BLENDING.blend(src, dst),
+            Vec4::new(0.5, 0.0, 0.5, 1.0)
+        );
+        assert_eq!(
+            BlendState::PREMULTIPLIED_ALPHA_BLENDING.blend(src, dst),
+            Vec4::new(1.0, 0.0, 0.5, 1.0)
+        );
+        assert_eq!(
+            BlendState::ADDITIVE.blend(src, dst),
+            Vec4::new(1.0, 0.0, 1.0, 1.5)
+        );
+    }
+
+    #[test]
+    fn operations_and_factors_apply_per_component() {
+        let src = Vec4::new(0.25, 0.5, 1.0, 0.75);
+        let dst = Vec4::new(0.5, 0.5, 0.5, 0.5);
+        let component = |operation, src_factor, dst_factor| BlendComponent {
+            src_factor,
+            dst_factor,
+            operation,
+        };
+        let one = |operation| component(operation, BlendFactor::One, BlendFactor::One);
+
+        let subtract = BlendState::new(
+            one(BlendOperation::Subtract),
+            one(BlendOperation::ReverseSubtract),
+        );
+        assert_eq!(subtract.blend(src, dst), Vec4::new(-0.25, 0.0, 0.5, -0.25));
+
+        // Min and Max ignore the factors.
+        let extremes = BlendState::new(
+            component(BlendOperation::Min, BlendFactor::Zero, BlendFactor::Zero),
+            component(BlendOperation::Max, BlendFactor::Zero, BlendFactor::Zero),
+        );
+        assert_eq!(extremes.blend(src, dst), Vec4::new(0.25, 0.5, 0.5, 0.75));
+
+        let mut constant = BlendState::new(
+            component(
+                BlendOperation::Add,
+                BlendFactor::Constant,
+                BlendFactor::OneMinusConstant,
+            ),
+            BlendComponent::REPLACE,
+        );
+        constant.constant = Vec4::new(1.0, 0.5, 0.0, 0.0);
+        assert_eq!(constant.blend(src, dst), Vec4::new(0.25, 0.5, 0.5, 0.75));
+
+        // Saturated alpha leaves the alpha factor at one.
+        let saturated = BlendState::new(
+            component(
+                BlendOperation::Add,
+                BlendFactor::SrcAlphaSaturated,
+                BlendFactor::Zero,
+            ),
+            component(
+                BlendOperation::Add,
+                BlendFactor::SrcAlphaSaturated,
+                BlendFactor::Zero,
+            ),
+        );
+        assert_eq!(saturated.blend(src, dst), Vec4::new(0.125, 0.25, 0.5, 0.75));
+    }
+}
@@ -0,0 +1,377 @@
+use math::Vec4;
+
+use crate::{
+    blend::BlendState,
+    buffer::{
+        ops::{ToArray, ToArrayMut},
+        Buffer, BufferError, FrameBuffer, Rect,
+    },
+    color,
+    dither::Quantizer,
+    resample::{self, Kernel},
+};
+
+/// How `FrameBuffer::blit` combines source pixels with the destination.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum BlitMode {
+    /// Overwrites the destination.
+    #[default]
+    Replace,
+    /// Overwrites the destination except where the source is exactly this
+    /// color, which is left transparent.
+    ColorKey(u32),
+    /// Blends the source over the destination, e.g. with
+    /// `BlendState::ALPHA_BLENDING`.
+    Blend(BlendState),
+}
+
+/// A clockwise rotation by a multiple of 90°.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl FrameBuffer {
+    /// Copies `src_rect` of `src` so its top-left corner lands on `(x, y)`.
+    /// The copy is clipped to this buffer, so it may hang over any edge.
+    /// Multisampled sources are read from their first sample and every
+    /// destination sample is written. Pixels are copied as stored between
+    /// buffers with the same encoding, and otherwise go through linear light
+    /// and the destination's dither, as they do when blending. Color keys
+    /// match the source's stored pixels.
+    pub fn blit(
+        &mut self,
+        src: &FrameBuffer,
+        src_rect: Rect,
+        x: isize,
+        y: isize,
+        mode: BlitMode,
+    ) -> Result<(), BufferError> {
+        let source = src.view(src_rect)?;
+
+        let (left, top) = (x.min(0).unsigned_abs(), y.min(0).unsigned_abs());
+        let (x, y) = (x.max(0) as usize, y.max(0) as usize);
+        let width = src_rect
+            .width
+            .saturating_sub(left)
+            .min(self.width.saturating_sub(x));
+        let height = src_rect
+            .height
+            .saturating_sub(top)
+            .min(self.height.saturating_sub(y));
+
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        let source = source.view(Rect::new(left, top, width, height))?;
+        let (src_samples, dst_samples) = (src.samples.count(), self.samples.count());
+        let (srgb, dither) = (self.srgb, self.dither);
+        let mut target = self.view_mut(Rect::new(x, y, width, height))?;
+
+        for (row, (src_row, dst_row)) in source.rows().zip(target.rows_mut()).enumerate() {
+            let pixels = src_row
+                .chunks_exact(src_samples)
+                .zip(dst_row.chunks_exact_mut(dst_samples));
+
+            for (column, (texel, samples)) in pixels.enumerate() {
+                let color = texel[0];
+                let converted = || {
+                    if src.srgb == srgb {
+                        color
+                    } else {
+                        let linear = color::decode(color, src.srgb);
+                        dither.encode(linear, srgb, x + column, y + row)
+                    }
+                };
+
+                match mode {
+                    BlitMode::Replace => samples.fill(converted()),
+                    BlitMode::ColorKey(key) => {
+                        if color != key {
+                            samples.fill(converted());
+                        }
+                    }
+                    BlitMode::Blend(state) => {
+                        let color = color::decode(color, src.srgb);
+                        for sample in samples {
+                            let blended = state.blend(color, color::decode(*sample, srgb));
+                            *sample = dither.encode(blended, srgb, x + column, y + row);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Mirrors the buffer left to right.
+    pub fn flip_horizontal(&mut self) {
+        let (width, samples) = (self.width, self.samples.count());
+        if width == 0 {
+            return;
+        }
+
+        for row in self
+            .to_array_mut()
+            .unwrap()
+            .chunks_exact_mut(width * samples)
+        {
+            for x in 0..width / 2 {
+                let mirror = width - 1 - x;
+                for i in 0..samples {
+                    row.swap(x * samples + i, mirror * samples + i);
+                }
+            }
+        }
+    }
+
+    /// Mirrors the buffer top to bottom.
+    pub fn flip_vertical(&mut self) {
+        let height = self.height;
+        let stride = self.width * self.samples.count();
+        let pixels = self.to_array_mut().unwrap();
+
+        for y in 0..height / 2 {
+            let (top, bottom) = pixels.split_at_mut((height - 1 - y) * stride);
+            top[y * stride..(y + 1) * stride].swap_with_slice(&mut bottom[..stride]);
+        }
+    }
+
+    /// A rotated copy of the buffer. Quarter turns swap its width and height.
+    pub fn rotate(&self, rotation: Rotation) -> FrameBuffer {
+        let (width, height) = (self.width, self.height);
+        let samples = self.samples.count();
+
+        let mut rotated = match rotation {
+            Rotation::Rotate180 => FrameBuffer::multisampled(width, height, self.samples),
+            Rotation::Rotate90 | Rotation::Rotate270 => {
+                FrameBuffer::multisampled(height, width, self.samples)
+            }
+        };
+        rotated.srgb = self.srgb;
+        rotated.dither = self.dither;
+        let rotated_width = rotated.width;
+
+        let src = self.to_array().unwrap();
+        let dst = rotated.to_array_mut().unwrap();
+
+        for y in 0..height {
+            for x in 0..width {
+                let (dx, dy) = match rotation {
+                    Rotation::Rotate90 => (height - 1 - y, x),
+                    Rotation::Rotate180 => (width - 1 - x, height - 1 - y),
+                    Rotation::Rotate270 => (y, width - 1 - x),
+                };
+
+                let from = (y * width + x) * samples;
+                let to = (dy * rotated_width + dx) * samples;
+                dst[to..to + samples].copy_from_slice(&src[from..from + samples]);
+            }
+        }
+
+        rotated
+    }
+
+    /// A single-sampled copy resized to `width`×`height` with `kernel`.
+    /// Other than with `Kernel::Nearest`, shrinking widens the kernel so
+    /// every source pixel contributes. Multisampled buffers are resolved
+    /// first. Filtering happens in linear light, and the copy keeps the
+    /// `srgb` flag and the dither it's rounded with.
+    pub fn resize(&self, width: usize, height: usize, kernel: Kernel) -> FrameBuffer {
+        let mut resized = FrameBuffer::new(width, height);
+        resized.srgb = self.srgb;
+        resized.dither = self.dither;
+        if self.width == 0 || self.height == 0 {
+            return resized;
+        }
+
+        let samples = self.samples.count();
+        let scale = 1.0 / samples as f32;
+        let texels: Vec<Vec4> = self
+            .to_array()
+            .unwrap()
+            .chunks_exact(samples)
+            .map(|pixel| {
+                pixel
+                    .iter()
+                    .fold(Vec4::ZERO, |sum, &sample| sum + self.decode(sample))
+                    * scale
+            })
+            .collect();
+
+        let colors = resample::resample(&texels, self.width, self.height, width, height, kernel);
+        Quantizer::new(self.dither)
+            .write(&colors, &mut resized)
+            .unwrap();
+
+        resized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffer::ops::{GetPixel, SetPixel},
+        msaa::SampleCount,
+    };
+
+    /// A 4×3 image whose pixels count up in row-major order.
+    fn numbered() -> FrameBuffer {
+        let mut image = FrameBuffer::new(4, 3);
+        for y in 0..3 {
+            for x in 0..4 {
+                image
+                    .set_pixel(x, y, 0xff000000 | (y * 4 + x) as u32)
+                    .unwrap();
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn blits_clip_to_the_destination() {
+        let mut src = numbered();
+        src.set_pixel(1, 1, 0x00ff00ff).unwrap();
+        let mut dst = FrameBuffer::multisampled(5, 5, SampleCount::X2);
+
+        dst.blit(
+            &src,
+            Rect::new(0, 0, 4, 3),
+            -1,
+            3,
+            BlitMode::ColorKey(0x00ff00ff),
+        )
+        .unwrap();
+        assert_eq!(dst.get_pixel(0, 3).unwrap(), 0xff000001);
+        assert_eq!(dst.get_pixel(2, 4).unwrap(), 0xff000007);
+        // The keyed pixel is skipped.
+        assert_eq!(dst.get_pixel(0, 4).unwrap(), 0);
+        assert!(dst.to_array().unwrap()[..3 * 5 * 2].iter().all(|&p| p == 0));
+
+        // Entirely off the destination.
+        dst.blit(&src, Rect::new(0, 0, 4, 3), 9, 0, BlitMode::Replace)
+            .unwrap();
+        assert_eq!(
+            dst.blit(&src, Rect::new(2, 0, 4, 1), 0, 0, BlitMode::Replace),
+            Err(BufferError::OutOfBounds)
+        );
+    }
+
+    #[test]
+    fn blended_blits_composite_in_linear_light() {
+        let mut src = FrameBuffer::new(1, 1);
+        src.set_pixel(0, 0, color::pack(Vec4::new(1.0, 0.0, 0.0, 0.5)))
+            .unwrap();
+        let mut dst = FrameBuffer::new(1, 1);
+        dst.srgb = true;
+        dst.set_pixel(0, 0, color::encode(Vec4::new(0.0, 0.0, 1.0, 1.0), true))
+            .unwrap();
+
+        dst.blit(
+            &src,
+            Rect::new(0, 0, 1, 1),
+            0,
+            0,
+            BlitMode::Blend(BlendState::ALPHA_BLENDING),
+        )
+        .unwrap();
+        let blended = color::decode(dst.get_pixel(0, 0).unwrap(), true);
+        assert!((blended.x - 0.5).abs() < 0.01 && (blended.z - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn copies_convert_between_encodings() {
+        let mut src = FrameBuffer::new(2, 1);
+        src.srgb = true;
+        src.set_pixel(0, 0, 0xff808080).unwrap();
+        src.set_pixel(1, 0, 0xffff0000).unwrap();
+
+        // sRGB 128 is 55 in linear light.
+        for mode in [BlitMode::Replace, BlitMode::ColorKey(0xffff0000)] {
+            let mut linear = FrameBuffer::new(2, 1);
+            linear
+                .blit(&src, Rect::new(0, 0, 2, 1), 0, 0, mode)
+                .unwrap();
+            assert_eq!(linear.get_pixel(0, 0).unwrap(), 0xff373737, "{mode:?}");
+
+            let mut back = FrameBuffer::new(1, 1);
+            back.srgb = true;
+            back.blit(&linear, Rect::new(0, 0, 1, 1), 0, 0, mode)
+                .unwrap();
+            assert_eq!(back.get_pixel(0, 0).unwrap(), 0xff808080, "{mode:?}");
+        }
+
+        // Keys match the source's own encoding.
+        let mut keyed = FrameBuffer::new(2, 1);
+        keyed
+            .blit(
+                &src,
+                Rect::new(0, 0, 2, 1),
+                0,
+                0,
+                BlitMode::ColorKey(0xff808080),
+            )
+            .unwrap();
+        assert_eq!(keyed.get_pixel(0, 0).unwrap(), 0);
+        assert_eq!(keyed.get_pixel(1, 0).unwrap(), 0xffff0000);
+    }
+
+    #[test]
+    fn rotations_and_flips_move_corners() {
+        let src = numbered();
+        let corner = 0xff000000;
+
+        let rotated = src.rotate(Rotation::Rotate90);
+        assert_eq!((rotated.width, rotated.height), (3, 4));
+        assert_eq!(rotated.get_pixel(2, 0).unwrap(), corner);
+        assert_eq!(rotated.get_pixel(0, 3).unwrap(), 0xff00000b);
+        assert_eq!(
+            src.rotate(Rotation::Rotate270).get_pixel(0, 3).unwrap(),
+            corner
+        );
+        assert_eq!(
+            src.rotate(Rotation::Rotate180).get_pixel(3, 2).unwrap(),
+            corner
+        );
+
+        let mut flipped = src.clone();
+        flipped.flip_horizontal();
+        assert_eq!(flipped.get_pixel(3, 0).unwrap(), corner);
+        flipped.flip_vertical();
+        assert_eq!(flipped.get_pixel(3, 2).unwrap(), corner);
+        assert_eq!(
+            flipped.to_array().unwrap(),
+            src.rotate(Rotation::Rotate180).to_array().unwrap()
+        );
+    }
+
+    #[test]
+    fn resizing_filters_in_linear_light() {
+        let mut image = FrameBuffer::new(2, 1);
+        image.set_pixel(0, 0, 0xff000000).unwrap();
+        image.set_pixel(1, 0, 0xffffffff).unwrap();
+
+        let enlarged = image.resize(8, 1, Kernel::Nearest);
+        let blue: Vec<u32> = (0..8)
+            .map(|x| enlarged.get_pixel(x, 0).unwrap() & 0xff)
+            .collect();
+        assert_eq!(blue, [0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+
+        let enlarged = image.resize(8, 1, Kernel::Bilinear);
+        let blue: Vec<u32> = (0..8)
+            .map(|x| enlarged.get_pixel(x, 0).unwrap() & 0xff)
+            .collect();
+        assert!(blue.windows(2).all(|pair| pair[0] <= pair[1]), "{blue:?}");
+        assert_eq!((blue[0], blue[7]), (0, 0xff));
+
+        image.srgb = true;
+        let shrunk = image.resize(1, 1, Kernel::Box);
+        assert!(shrunk.srgb);
+        assert_eq!(shrunk.get_pixel(0, 0).unwrap() & 0xff, 0xbc);
+    }
+}
@@ -1,23 +1,70 @@
+use math::Vec4;
+
+use crate::{
+    blend::BlendState,
+    color,
+    dither::{Dither, Quantizer},
+    hiz::DepthHierarchy,
+    msaa::SampleCount,
+    raster::Bounds,
+    view::{View, ViewMut},
+};
+
 type Result<T, BufferError> = std::result::Result<T, BufferError>;
 
-#[derive(Debug, Clone, Copy)]
-enum BufferError {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferError {
     OutOfBounds,
+    SizeMismatch,
+    SampleCountMismatch,
+    TooManyAttachments,
 }
 
 impl std::fmt::Display for BufferError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "")
+        match self {
+            BufferError::OutOfBounds => write!(f, "pixel coordinates out of bounds"),
+            BufferError::SizeMismatch => write!(f, "buffer dimensions do not match"),
+            BufferError::SampleCountMismatch => write!(f, "buffer sample counts do not match"),
+            BufferError::TooManyAttachments => {
+                write!(f, "render target has too many color attachments")
+            }
+        }
     }
 }
 
+impl std::error::Error for BufferError {}
+
+/// A rectangle of pixels with its top-left corner at `(x, y)`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x:      usize,
+    pub y:      usize,
+    pub width:  usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+/// Multisampled buffers store every sample of a pixel next to each other.
+/// Pixel operations write all samples of a pixel and read its first sample;
+/// `to_array` exposes the samples.
 pub trait Buffer {
     fn new(width: usize, height: usize) -> Self;
     fn clear(&mut self);
 }
 
-mod ops {
-    use super::BufferError;
+pub mod ops {
+    use super::{BlendState, BufferError};
 
     pub trait Fill<T> {
         fn fill(&mut self, color: T);
@@ -27,6 +74,17 @@ mod ops {
         fn set_pixel(&mut self, x: usize, y: usize, color: T) -> Result<(), BufferError>;
     }
 
+    /// Combines `color` with the stored pixel instead of overwriting it.
+    pub trait BlendPixel<T> {
+        fn blend_pixel(
+            &mut self,
+            x: usize,
+            y: usize,
+            color: T,
+            state: &BlendState,
+        ) -> Result<(), BufferError>;
+    }
+
     pub trait GetPixel<T> {
         fn get_pixel(&self, x: usize, y: usize) -> Result<T, BufferError>;
     }
@@ -34,24 +92,109 @@ mod ops {
     pub trait ToArray<T> {
         fn to_array(&self) -> Result<&[T], BufferError>;
     }
+
+    pub trait ToArrayMut<T> {
+        fn to_array_mut(&mut self) -> Result<&mut [T], BufferError>;
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FrameBuffer {
-    pub width:  usize,
-    pub height: usize,
-    buffer:     Vec<u32>,
+    pub width:   usize,
+    pub height:  usize,
+    pub samples: SampleCount,
+    /// Whether the color channels are stored sRGB encoded. Rendering,
+    /// blending, resolving and sampling then convert to and from linear
+    /// light, so shading math stays linear.
+    pub srgb:    bool,
+    /// How colors are rounded to 8 bits as they're written by draws,
+    /// blending, resolves, resizes and tone mapping.
+    pub dither:  Dither,
+    buffer:      Vec<u32>,
 }
 
-impl Buffer for FrameBuffer {
-    fn new(width: usize, height: usize) -> Self {
+impl FrameBuffer {
+    pub fn multisampled(width: usize, height: usize, samples: SampleCount) -> Self {
         Self {
             width,
             height,
-            buffer: vec![0; width * height],
+            samples,
+            srgb: false,
+            dither: Dither::None,
+            buffer: vec![0; width * height * samples.count()],
         }
     }
 
+    /// Converts a stored pixel to linear color.
+    pub fn decode(&self, color: u32) -> Vec4 {
+        color::decode(color, self.srgb)
+    }
+
+    /// Converts a linear color to how this buffer stores it.
+    pub fn encode(&self, color: Vec4) -> u32 {
+        color::encode(color, self.srgb)
+    }
+
+    /// Averages the samples of every pixel into `target`, which must be a
+    /// single-sampled buffer of the same size. Samples are averaged in
+    /// linear light, then rounded with the target's dither.
+    pub fn resolve(&self, target: &mut FrameBuffer) -> Result<(), BufferError> {
+        if target.width != self.width || target.height != self.height {
+            return Err(BufferError::SizeMismatch);
+        }
+        if target.samples != SampleCount::X1 {
+            return Err(BufferError::SampleCountMismatch);
+        }
+
+        let count = self.samples.count();
+        let scale = 1.0 / count as f32;
+        let colors: Vec<Vec4> = self
+            .buffer
+            .chunks_exact(count)
+            .map(|samples| {
+                samples
+                    .iter()
+                    .fold(Vec4::ZERO, |sum, &sample| sum + self.decode(sample))
+                    * scale
+            })
+            .collect();
+
+        Quantizer::new(target.dither).write(&colors, target)
+    }
+
+    /// Borrows the pixels inside `rect` without copying them.
+    pub fn view(&self, rect: Rect) -> Result<View<'_, u32>, BufferError> {
+        View::new(
+            &self.buffer,
+            self.width,
+            self.height,
+            self.samples.count(),
+            rect,
+        )
+    }
+
+    pub fn view_mut(&mut self, rect: Rect) -> Result<ViewMut<'_, u32>, BufferError> {
+        ViewMut::new(
+            &mut self.buffer,
+            self.width,
+            self.height,
+            self.samples.count(),
+            rect,
+        )
+    }
+
+    fn samples_mut(&mut self, x: usize, y: usize) -> &mut [u32] {
+        let count = self.samples.count();
+        let i = (y * self.width + x) * count;
+        &mut self.buffer[i..i + count]
+    }
+}
+
+impl Buffer for FrameBuffer {
+    fn new(width: usize, height: usize) -> Self {
+        Self::multisampled(width, height, SampleCount::X1)
+    }
+
     fn clear(&mut self) {
         self.buffer.fill(0);
     }
@@ -66,7 +209,28 @@ impl ops::Fill<u32> for FrameBuffer {
 impl ops::SetPixel<u32> for FrameBuffer {
     fn set_pixel(&mut self, x: usize, y: usize, color: u32) -> Result<(), BufferError> {
         if x < self.width && y < self.height {
-            self.buffer[y * self.width + x] = color;
+            self.samples_mut(x, y).fill(color);
+            Ok(())
+        } else {
+            Err(BufferError::OutOfBounds)
+        }
+    }
+}
+
+impl ops::BlendPixel<Vec4> for FrameBuffer {
+    fn blend_pixel(
+        &mut self,
+        x: usize,
+        y: usize,
+        color: Vec4,
+        state: &BlendState,
+    ) -> Result<(), BufferError> {
+        if x < self.width && y < self.height {
+            let (srgb, dither) = (self.srgb, self.dither);
+            for sample in self.samples_mut(x, y) {
+                let blended = state.blend(color, color::decode(*sample, srgb));
+                *sample = dither.encode(blended, srgb, x, y);
+            }
             Ok(())
         } else {
             Err(BufferError::OutOfBounds)
@@ -77,7 +241,7 @@ impl ops::SetPixel<u32> for FrameBuffer {
 impl ops::GetPixel<u32> for FrameBuffer {
     fn get_pixel(&self, x: usize, y: usize) -> std::result::Result<u32, BufferError> {
         if x < self.width && y < self.height {
-            Ok(self.buffer[y * self.width + x])
+            Ok(self.buffer[(y * self.width + x) * self.samples.count()])
         } else {
             Err(BufferError::OutOfBounds)
         }
@@ -90,31 +254,132 @@ impl ops::ToArray<u32> for FrameBuffer {
     }
 }
 
-#[derive(Debug)]
+impl ops::ToArrayMut<u32> for FrameBuffer {
+    fn to_array_mut(&mut self) -> std::result::Result<&mut [u32], BufferError> {
+        Ok(&mut self.buffer)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct DepthBuffer {
-    pub width:  usize,
-    pub height: usize,
-    buffer:     Vec<f32>,
+    pub width:            usize,
+    pub height:           usize,
+    pub samples:          SampleCount,
+    buffer:               Vec<f32>,
+    pub(crate) hierarchy: Option<DepthHierarchy>,
 }
 
-impl Buffer for DepthBuffer {
-    fn new(width: usize, height: usize) -> Self {
+impl DepthBuffer {
+    pub fn multisampled(width: usize, height: usize, samples: SampleCount) -> Self {
         Self {
             width,
             height,
-            buffer: vec![f32::INFINITY; width * height],
+            samples,
+            buffer: vec![f32::INFINITY; width * height * samples.count()],
+            hierarchy: None,
         }
     }
 
+    /// Starts maintaining a min/max depth pyramid alongside the buffer, which
+    /// the pipeline uses to reject occluded triangles before rasterizing them.
+    pub fn enable_hierarchy(&mut self) {
+        let mut hierarchy = DepthHierarchy::new(self.width, self.height, f32::INFINITY);
+        hierarchy.invalidate();
+        hierarchy.sync(&self.buffer, self.width, self.height, self.samples);
+        self.hierarchy = Some(hierarchy);
+    }
+
+    pub fn disable_hierarchy(&mut self) {
+        self.hierarchy = None;
+    }
+
+    /// The depth pyramid, as of the last draw or direct write.
+    pub fn hierarchy(&self) -> Option<&DepthHierarchy> {
+        self.hierarchy.as_ref()
+    }
+
+    /// Borrows the pixels inside `rect` without copying them.
+    pub fn view(&self, rect: Rect) -> Result<View<'_, f32>, BufferError> {
+        View::new(
+            &self.buffer,
+            self.width,
+            self.height,
+            self.samples.count(),
+            rect,
+        )
+    }
+
+    pub fn view_mut(&mut self, rect: Rect) -> Result<ViewMut<'_, f32>, BufferError> {
+        // Writes through the view aren't tracked, so the pyramid is rebuilt
+        // before the next draw.
+        if let Some(hierarchy) = &mut self.hierarchy {
+            hierarchy.invalidate();
+        }
+
+        ViewMut::new(
+            &mut self.buffer,
+            self.width,
+            self.height,
+            self.samples.count(),
+            rect,
+        )
+    }
+
+    /// Brings the hierarchy up to date after writes through `to_array_mut`
+    /// or `view_mut`.
+    pub(crate) fn sync_hierarchy(&mut self) {
+        if let Some(hierarchy) = &mut self.hierarchy {
+            hierarchy.sync(&self.buffer, self.width, self.height, self.samples);
+        }
+    }
+
+    /// Mutable access for the rasterizer, which updates the hierarchy itself.
+    pub(crate) fn pixels_mut(&mut self) -> &mut [f32] {
+        &mut self.buffer
+    }
+
+    pub(crate) fn pixels(&self) -> &[f32] {
+        &self.buffer
+    }
+}
+
+impl Buffer for DepthBuffer {
+    fn new(width: usize, height: usize) -> Self {
+        Self::multisampled(width, height, SampleCount::X1)
+    }
+
     fn clear(&mut self) {
-        self.buffer.fill(f32::INFINITY);
+        ops::Fill::fill(self, f32::INFINITY);
+    }
+}
+
+impl ops::Fill<f32> for DepthBuffer {
+    fn fill(&mut self, depth: f32) {
+        self.buffer.fill(depth);
+
+        if let Some(hierarchy) = &mut self.hierarchy {
+            hierarchy.fill(depth);
+        }
     }
 }
 
 impl ops::SetPixel<f32> for DepthBuffer {
     fn set_pixel(&mut self, x: usize, y: usize, depth: f32) -> Result<(), BufferError> {
         if x < self.width && y < self.height {
-            self.buffer[y * self.width + x] = depth;
+            let count = self.samples.count();
+            let i = (y * self.width + x) * count;
+            self.buffer[i..i + count].fill(depth);
+
+            if let Some(hierarchy) = &mut self.hierarchy {
+                let bounds = Bounds {
+                    min_x: x,
+                    min_y: y,
+                    max_x: x + 1,
+                    max_y: y + 1,
+                };
+                hierarchy.update(&self.buffer, self.width, self.samples, bounds);
+            }
+
             Ok(())
         } else {
             Err(BufferError::OutOfBounds)
@@ -125,7 +390,266 @@ impl ops::SetPixel<f32> for DepthBuffer {
 impl ops::GetPixel<f32> for DepthBuffer {
     fn get_pixel(&self, x: usize, y: usize) -> std::result::Result<f32, BufferError> {
         if x < self.width && y < self.height {
-            Ok(self.buffer[y * self.width + x])
+            Ok(self.buffer[(y * self.width + x) * self.samples.count()])
+        } else {
+            Err(BufferError::OutOfBounds)
+        }
+    }
+}
+
+impl ops::ToArray<f32> for DepthBuffer {
+    fn to_array(&self) -> std::result::Result<&[f32], BufferError> {
+        Ok(&self.buffer)
+    }
+}
+
+impl ops::ToArrayMut<f32> for DepthBuffer {
+    fn to_array_mut(&mut self) -> std::result::Result<&mut [f32], BufferError> {
+        if let Some(hierarchy) = &mut self.hierarchy {
+            hierarchy.invalidate();
+        }
+
+        Ok(&mut self.buffer)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StencilBuffer {
+    pub width:   usize,
+    pub height:  usize,
+    pub samples: SampleCount,
+    buffer:      Vec<u8>,
+}
+
+impl StencilBuffer {
+    pub fn multisampled(width: usize, height: usize, samples: SampleCount) -> Self {
+        Self {
+            width,
+            height,
+            samples,
+            buffer: vec![0; width * height * samples.count()],
+        }
+    }
+
+    /// Borrows the pixels inside `rect` without copying them.
+    pub fn view(&self, rect: Rect) -> Result<View<'_, u8>, BufferError> {
+        View::new(
+            &self.buffer,
+            self.width,
+            self.height,
+            self.samples.count(),
+            rect,
+        )
+    }
+
+    pub fn view_mut(&mut self, rect: Rect) -> Result<ViewMut<'_, u8>, BufferError> {
+        ViewMut::new(
+            &mut self.buffer,
+            self.width,
+            self.height,
+            self.samples.count(),
+            rect,
+        )
+    }
+}
+
+impl Buffer for StencilBuffer {
+    fn new(width: usize, height: usize) -> Self {
+        Self::multisampled(width, height, SampleCount::X1)
+    }
+
+    fn clear(&mut self) {
+        self.buffer.fill(0);
+    }
+}
+
+impl ops::Fill<u8> for StencilBuffer {
+    fn fill(&mut self, value: u8) {
+        self.buffer.fill(value);
+    }
+}
+
+impl ops::SetPixel<u8> for StencilBuffer {
+    fn set_pixel(&mut self, x: usize, y: usize, value: u8) -> Result<(), BufferError> {
+        if x < self.width && y < self.height {
+            let count = self.samples.count();
+            let i = (y * self.width + x) * count;
+            self.buffer[i..i + count].fill(value);
+            Ok(())
+        } else {
+            Err(BufferError::OutOfBounds)
+        }
+    }
+}
+
+impl ops::GetPixel<u8> for StencilBuffer {
+    fn get_pixel(&self, x: usize, y: usize) -> std::result::Result<u8, BufferError> {
+        if x < self.width && y < self.height {
+            Ok(self.buffer[(y * self.width + x) * self.samples.count()])
+        } else {
+            Err(BufferError::OutOfBounds)
+        }
+    }
+}
+
+impl ops::ToArray<u8> for StencilBuffer {
+    fn to_array(&self) -> std::result::Result<&[u8], BufferError> {
+        Ok(&self.buffer)
+    }
+}
+
+impl ops::ToArrayMut<u8> for StencilBuffer {
+    fn to_array_mut(&mut self) -> std::result::Result<&mut [u8], BufferError> {
+        Ok(&mut self.buffer)
+    }
+}
+
+/// Storage format of an [`HdrBuffer`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HdrFormat {
+    /// A 32-bit float per channel.
+    #[default]
+    Rgba32F,
+    /// A half-precision float per channel, in half the memory.
+    Rgba16F,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum HdrTexels {
+    Rgba32F(Vec<Vec4>),
+    Rgba16F(Vec<[u16; 4]>),
+}
+
+impl HdrTexels {
+    fn get(&self, i: usize) -> Vec4 {
+        match self {
+            HdrTexels::Rgba32F(texels) => texels[i],
+            HdrTexels::Rgba16F(texels) => color::unpack_half(texels[i]),
+        }
+    }
+
+    fn set(&mut self, i: usize, color: Vec4) {
+        match self {
+            HdrTexels::Rgba32F(texels) => texels[i] = color,
+            HdrTexels::Rgba16F(texels) => texels[i] = color::pack_half(color),
+        }
+    }
+}
+
+/// Linear RGBA color that isn't clamped to `[0, 1]`, so lighting brighter
+/// than the display can show survives until it's tone mapped.
+#[derive(Debug, Clone)]
+pub struct HdrBuffer {
+    pub width:         usize,
+    pub height:        usize,
+    pub samples:       SampleCount,
+    pub(crate) texels: HdrTexels,
+}
+
+impl HdrBuffer {
+    pub fn with_format(width: usize, height: usize, format: HdrFormat) -> Self {
+        Self::multisampled(width, height, SampleCount::X1, format)
+    }
+
+    pub fn multisampled(
+        width: usize,
+        height: usize,
+        samples: SampleCount,
+        format: HdrFormat,
+    ) -> Self {
+        let len = width * height * samples.count();
+
+        Self {
+            width,
+            height,
+            samples,
+            texels: match format {
+                HdrFormat::Rgba32F => HdrTexels::Rgba32F(vec![Vec4::ZERO; len]),
+                HdrFormat::Rgba16F => HdrTexels::Rgba16F(vec![[0; 4]; len]),
+            },
+        }
+    }
+
+    pub fn format(&self) -> HdrFormat {
+        match self.texels {
+            HdrTexels::Rgba32F(_) => HdrFormat::Rgba32F,
+            HdrTexels::Rgba16F(_) => HdrFormat::Rgba16F,
+        }
+    }
+
+    /// The average of the samples of pixel `(x, y)`.
+    pub fn resolve_pixel(&self, x: usize, y: usize) -> Result<Vec4, BufferError> {
+        if x < self.width && y < self.height {
+            let count = self.samples.count();
+            let i = (y * self.width + x) * count;
+            let sum = (i..i + count).fold(Vec4::ZERO, |sum, i| sum + self.texels.get(i));
+            Ok(sum * (1.0 / count as f32))
+        } else {
+            Err(BufferError::OutOfBounds)
+        }
+    }
+}
+
+impl Buffer for HdrBuffer {
+    fn new(width: usize, height: usize) -> Self {
+        Self::with_format(width, height, HdrFormat::Rgba32F)
+    }
+
+    fn clear(&mut self) {
+        ops::Fill::fill(self, Vec4::ZERO);
+    }
+}
+
+impl ops::Fill<Vec4> for HdrBuffer {
+    fn fill(&mut self, color: Vec4) {
+        match &mut self.texels {
+            HdrTexels::Rgba32F(texels) => texels.fill(color),
+            HdrTexels::Rgba16F(texels) => texels.fill(color::pack_half(color)),
+        }
+    }
+}
+
+impl ops::SetPixel<Vec4> for HdrBuffer {
+    fn set_pixel(&mut self, x: usize, y: usize, color: Vec4) -> Result<(), BufferError> {
+        if x < self.width && y < self.height {
+            let count = self.samples.count();
+            let i = (y * self.width + x) * count;
+            for i in i..i + count {
+                self.texels.set(i, color);
+            }
+            Ok(())
+        } else {
+            Err(BufferError::OutOfBounds)
+        }
+    }
+}
+
+impl ops::BlendPixel<Vec4> for HdrBuffer {
+    fn blend_pixel(
+        &mut self,
+        x: usize,
+        y: usize,
+        color: Vec4,
+        state: &BlendState,
+    ) -> Result<(), BufferError> {
+        if x < self.width && y < self.height {
+            let count = self.samples.count();
+            let i = (y * self.width + x) * count;
+            for i in i..i + count {
+                let blended = state.blend(color, self.texels.get(i));
+                self.texels.set(i, blended);
+            }
+            Ok(())
+        } else {
+            Err(BufferError::OutOfBounds)
+        }
+    }
+}
+
+impl ops::GetPixel<Vec4> for HdrBuffer {
+    fn get_pixel(&self, x: usize, y: usize) -> std::result::Result<Vec4, BufferError> {
+        if x < self.width && y < self.height {
+            Ok(self.texels.get((y * self.width + x) * self.samples.count()))
         } else {
             Err(BufferError::OutOfBounds)
         }
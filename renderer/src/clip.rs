@@ -0,0 +1,174 @@
+use math::Vec4;
+
+use crate::shader::{Varyings, VertexOutput};
+
+// Homogeneous clip planes: a vertex is inside when `dot(plane, position) >= 0`.
+const PLANES: [Vec4; 6] = [
+    Vec4 {
+        x: 1.0,
+        y: 0.0,
+        z: 0.0,
+        w: 1.0,
+    },
+    Vec4 {
+        x: -1.0,
+        y: 0.0,
+        z: 0.0,
+        w: 1.0,
+    },
+    Vec4 {
+        x: 0.0,
+        y: 1.0,
+        z: 0.0,
+        w: 1.0,
+    },
+    Vec4 {
+        x: 0.0,
+        y: -1.0,
+        z: 0.0,
+        w: 1.0,
+    },
+    Vec4 {
+        x: 0.0,
+        y: 0.0,
+        z: 1.0,
+        w: 1.0,
+    },
+    Vec4 {
+        x: 0.0,
+        y: 0.0,
+        z: -1.0,
+        w: 1.0,
+    },
+];
+
+fn lerp<V: Varyings>(a: &VertexOutput<V>, b: &VertexOutput<V>, t: f32) -> VertexOutput<V> {
+    VertexOutput {
+        position: a.position + (b.position - a.position) * t,
+        varyings: a.varyings.lerp(b.varyings, t),
+    }
+}
+
+/// Clips a triangle against the view frustum, writing the resulting convex
+/// polygon to `out`. `out` is left empty when the triangle is fully outside.
+pub(crate) fn clip_triangle<V: Varyings>(
+    triangle: [VertexOutput<V>; 3],
+    out: &mut Vec<VertexOutput<V>>,
+    scratch: &mut Vec<VertexOutput<V>>,
+) {
+    out.clear();
+    out.extend_from_slice(&triangle);
+
+    for plane in PLANES {
+        let distances = out.iter().map(|v| Vec4::dot(plane, v.position));
+        if distances.clone().all(|d| d >= 0.0) {
+            continue;
+        }
+
+        std::mem::swap(out, scratch);
+        out.clear();
+
+        for i in 0..scratch.len() {
+            let a = &scratch[i];
+            let b = &scratch[(i + 1) % scratch.len()];
+            let da = Vec4::dot(plane, a.position);
+            let db = Vec4::dot(plane, b.position);
+
+            if da >= 0.0 {
+                out.push(*a);
+            }
+            if (da >= 0.0) != (db >= 0.0) {
+                out.push(lerp(a, b, da / (da - db)));
+            }
+        }
+
+        if out.len() < 3 {
+            out.clear();
+            return;
+        }
+    }
+}
+
+/// Clips a line segment against the view frustum, returning `None` when it is
+/// fully outside.
+pub(crate) fn clip_line<V: Varyings>(
+    a: VertexOutput<V>,
+    b: VertexOutput<V>,
+) -> Option<(VertexOutput<V>, VertexOutput<V>)> {
+    let mut t0: f32 = 0.0;
+    let mut t1: f32 = 1.0;
+
+    for plane in PLANES {
+        let da = Vec4::dot(plane, a.position);
+        let db = Vec4::dot(plane, b.position);
+
+        if da < 0.0 && db < 0.0 {
+            return None;
+        }
+        if da < 0.0 {
+            t0 = t0.max(da / (da - db));
+        } else if db < 0.0 {
+            t1 = t1.min(da / (da - db));
+        }
+    }
+
+    if t0 > t1 {
+        return None;
+    }
+
+    Some((lerp(&a, &b, t0), lerp(&a, &b, t1)))
+}
+
+/// Returns whether a point lies inside the view frustum.
+pub(crate) fn point_visible(position: Vec4) -> bool {
+    PLANES
+        .iter()
+        .all(|&plane| Vec4::dot(plane, position) >= 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(x: f32, y: f32, z: f32, w: f32) -> VertexOutput<f32> {
+        VertexOutput {
+            position: Vec4::new(x, y, z, w),
+            varyings: x,
+        }
+    }
+
+    #[test]
+    fn triangle_crossing_near_plane_becomes_quad() {
+        let (mut out, mut scratch) = (Vec::new(), Vec::new());
+        let triangle = [
+            vertex(0.0, 0.0, -2.0, 1.0),
+            vertex(0.5, 0.0, 0.0, 1.0),
+            vertex(0.0, 0.5, 0.0, 1.0),
+        ];
+        clip_triangle(triangle, &mut out, &mut scratch);
+
+        assert_eq!(out.len(), 4);
+        assert!(out.iter().all(|v| v.position.z >= -v.position.w - 1e-6));
+        // Varyings are interpolated with the position.
+        assert!(out.iter().all(|v| v.varyings == v.position.x));
+    }
+
+    #[test]
+    fn outside_primitives_are_dropped() {
+        let (mut out, mut scratch) = (Vec::new(), Vec::new());
+        let triangle = [
+            vertex(2.0, 0.0, 0.0, 1.0),
+            vertex(3.0, 0.0, 0.0, 1.0),
+            vertex(2.0, 1.0, 0.0, 1.0),
+        ];
+        clip_triangle(triangle, &mut out, &mut scratch);
+        assert!(out.is_empty());
+
+        assert!(clip_line(triangle[0], triangle[1]).is_none());
+        let (a, b) = clip_line(vertex(-3.0, 0.0, 0.0, 1.0), vertex(3.0, 0.0, 0.0, 1.0)).unwrap();
+        assert_eq!((a.position.x, b.position.x), (-1.0, 1.0));
+
+        assert!(point_visible(Vec4::new(0.5, 0.5, 0.5, 1.0)));
+        assert!(!point_visible(Vec4::new(0.0, 0.0, 0.0, -1.0)));
+    }
+}
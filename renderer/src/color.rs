@@ -0,0 +1,233 @@
+use std::sync::OnceLock;
+
+use math::Vec4;
+
+// Colors in a `FrameBuffer` are packed as 0xAARRGGBB.
+
+fn to_u8(channel: f32) -> u32 {
+    (channel.clamp(0.0, 1.0) * 255.0 + 0.5) as u32
+}
+
+pub fn pack(color: Vec4) -> u32 {
+    (to_u8(color.w) << 24) | (to_u8(color.x) << 16) | (to_u8(color.y) << 8) | to_u8(color.z)
+}
+
+pub fn unpack(color: u32) -> Vec4 {
+    Vec4::new(
+        ((color >> 16) & 0xff) as f32 / 255.0,
+        ((color >> 8) & 0xff) as f32 / 255.0,
+        (color & 0xff) as f32 / 255.0,
+        ((color >> 24) & 0xff) as f32 / 255.0,
+    )
+}
+
+/// Scales the color channels by alpha, for use with premultiplied blending.
+pub fn premultiply(color: Vec4) -> Vec4 {
+    Vec4::new(
+        color.x * color.w,
+        color.y * color.w,
+        color.z * color.w,
+        color.w,
+    )
+}
+
+/// Inverse of [`premultiply`]. Fully transparent colors become zero.
+pub fn unpremultiply(color: Vec4) -> Vec4 {
+    if color.w == 0.0 {
+        return Vec4::ZERO;
+    }
+
+    Vec4::new(
+        color.x / color.w,
+        color.y / color.w,
+        color.z / color.w,
+        color.w,
+    )
+}
+
+/// Decodes an sRGB-encoded channel to linear light.
+pub fn srgb_to_linear(channel: f32) -> f32 {
+    if channel <= 0.04045 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a linear channel with the sRGB transfer function.
+pub fn linear_to_srgb(channel: f32) -> f32 {
+    if channel <= 0.0031308 {
+        channel * 12.92
+    } else {
+        1.055 * channel.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn decode_table() -> &'static [f32; 256] {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| std::array::from_fn(|i| srgb_to_linear(i as f32 / 255.0)))
+}
+
+/// Linear values at which the encoded byte steps up, halfway between the
+/// values neighbouring bytes decode to in sRGB space.
+fn encode_thresholds() -> &'static [f32; 255] {
+    static THRESHOLDS: OnceLock<[f32; 255]> = OnceLock::new();
+    THRESHOLDS.get_or_init(|| std::array::from_fn(|i| srgb_to_linear((i as f32 + 0.5) / 255.0)))
+}
+
+/// Decodes an sRGB byte to linear light through a lookup table.
+pub fn srgb_u8_to_linear(channel: u8) -> f32 {
+    decode_table()[channel as usize]
+}
+
+/// Encodes a linear channel to the nearest sRGB byte without evaluating the
+/// transfer function, by searching the decision thresholds.
+pub fn linear_to_srgb_u8(channel: f32) -> u8 {
+    encode_thresholds().partition_point(|&threshold| threshold <= channel) as u8
+}
+
+/// Like [`pack`], but encodes the color channels with the sRGB transfer
+/// function. Alpha stays linear.
+pub fn pack_srgb(color: Vec4) -> u32 {
+    let channel = |c: f32| linear_to_srgb_u8(c) as u32;
+    (to_u8(color.w) << 24) | (channel(color.x) << 16) | (channel(color.y) << 8) | channel(color.z)
+}
+
+/// Like [`unpack`], but decodes sRGB-encoded color channels to linear light.
+pub fn unpack_srgb(color: u32) -> Vec4 {
+    Vec4::new(
+        srgb_u8_to_linear((color >> 16) as u8),
+        srgb_u8_to_linear((color >> 8) as u8),
+        srgb_u8_to_linear(color as u8),
+        ((color >> 24) & 0xff) as f32 / 255.0,
+    )
+}
+
+/// [`unpack_srgb`] when `srgb` is set, otherwise [`unpack`].
+pub(crate) fn decode(color: u32, srgb: bool) -> Vec4 {
+    if srgb {
+        unpack_srgb(color)
+    } else {
+        unpack(color)
+    }
+}
+
+/// [`pack_srgb`] when `srgb` is set, otherwise [`pack`].
+pub(crate) fn encode(color: Vec4, srgb: bool) -> u32 {
+    if srgb {
+        pack_srgb(color)
+    } else {
+        pack(color)
+    }
+}
+
+/// Converts to the nearest IEEE 754 half-precision value, as stored in
+/// `HdrFormat::Rgba16F` buffers. Values too large for a half become infinite.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    // Rounds `bits` shifted right by `shift` to nearest, ties to even.
+    let round = |bits: u32, shift: u32| {
+        let kept = bits >> shift;
+        let rest = bits & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        kept + (rest > halfway || (rest == halfway && kept & 1 == 1)) as u32
+    };
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        sign | 0x7c00
+    } else if exponent > 0 {
+        // A carry out of the mantissa correctly bumps the exponent, up to
+        // infinity.
+        sign | round(((exponent as u32) << 23) | mantissa, 13) as u16
+    } else if exponent >= -10 {
+        sign | round(mantissa | 0x80_0000, (14 - exponent) as u32) as u16
+    } else {
+        sign
+    }
+}
+
+pub fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+
+    match exponent {
+        0 => {
+            let magnitude = mantissa as f32 / (1 << 24) as f32;
+            if sign != 0 {
+                -magnitude
+            } else {
+                magnitude
+            }
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 112) << 23) | (mantissa << 13)),
+    }
+}
+
+/// Converts each channel to half precision, as stored in
+/// `HdrFormat::Rgba16F` buffers, in RGBA order.
+pub fn pack_half(color: Vec4) -> [u16; 4] {
+    [color.x, color.y, color.z, color.w].map(f32_to_f16)
+}
+
+pub fn unpack_half(color: [u16; 4]) -> Vec4 {
+    let [r, g, b, a] = color.map(f16_to_f32);
+    Vec4::new(r, g, b, a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_tables_match_the_transfer_curve() {
+        for byte in 0..=255 {
+            let linear = srgb_u8_to_linear(byte);
+            assert!((linear - srgb_to_linear(byte as f32 / 255.0)).abs() < 1e-7);
+            assert_eq!(linear_to_srgb_u8(linear), byte);
+        }
+
+        // Away from ties, the search rounds like the exact curve does.
+        for i in 0..=20_000 {
+            let x = i as f32 / 20_000.0;
+            let exact = linear_to_srgb(x) * 255.0;
+            if (exact.fract() - 0.5).abs() > 1e-3 {
+                assert_eq!(linear_to_srgb_u8(x), (exact + 0.5) as u8, "{x}");
+            }
+        }
+
+        assert_eq!(linear_to_srgb_u8(-1.0), 0);
+        assert_eq!(linear_to_srgb_u8(2.0), 255);
+        assert_eq!(linear_to_srgb_u8(f32::NAN), 0);
+        assert_eq!(pack_srgb(Vec4::new(0.5, 0.0, 1.0, 0.5)), 0x80bc00ff);
+        assert_eq!(unpack_srgb(0x80bc00ff).w, unpack(0x80bc00ff).w);
+    }
+
+    #[test]
+    fn halves_round_to_nearest_and_back() {
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16(65520.0), 0x7c00);
+        assert_eq!(f32_to_f16(1e-9), 0);
+        assert_eq!(f32_to_f16(5.960_464_5e-8), 1);
+        assert_eq!(f16_to_f32(0x0001), 5.960_464_5e-8);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+
+        for half in 0..0x7c00 {
+            assert_eq!(f32_to_f16(f16_to_f32(half)), half);
+            assert_eq!(f32_to_f16(-f16_to_f32(half)), half | 0x8000);
+        }
+    }
+}
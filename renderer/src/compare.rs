@@ -0,0 +1,270 @@
+use math::Vec4;
+
+use crate::{
+    buffer::{
+        ops::{ToArray, ToArrayMut},
+        BufferError, FrameBuffer,
+    },
+    color,
+};
+
+// Metrics compare the red, green and blue channels as stored, scaled to
+// `[0, 1]`, so sRGB buffers are compared as they'd be displayed. Alpha is
+// ignored, and multisampled buffers are compared by their first samples.
+
+/// The first sample of every pixel of two images of the same size, paired
+/// up.
+fn pixels(reference: &FrameBuffer, actual: &FrameBuffer) -> Result<Vec<(Vec4, Vec4)>, BufferError> {
+    if reference.width != actual.width || reference.height != actual.height {
+        return Err(BufferError::SizeMismatch);
+    }
+
+    let first = |image: &FrameBuffer| -> Result<Vec<Vec4>, BufferError> {
+        Ok(image
+            .to_array()?
+            .chunks_exact(image.samples.count())
+            .map(|samples| color::unpack(samples[0]))
+            .collect())
+    };
+
+    Ok(first(reference)?.into_iter().zip(first(actual)?).collect())
+}
+
+fn channel_errors(a: Vec4, b: Vec4) -> [f32; 3] {
+    [(a.x - b.x).abs(), (a.y - b.y).abs(), (a.z - b.z).abs()]
+}
+
+/// Whether any color channel differs by more than `threshold`. Stored
+/// channels only differ by whole 8-bit steps, so both are counted in those,
+/// keeping float rounding from deciding a difference of exactly the
+/// threshold.
+fn exceeds(a: Vec4, b: Vec4, threshold: f32) -> bool {
+    let steps = (threshold * 255.0 + 1e-3).floor();
+    channel_errors(a, b)
+        .iter()
+        .any(|&e| (e * 255.0).round() > steps)
+}
+
+fn luma(color: Vec4) -> f32 {
+    0.299 * color.x + 0.587 * color.y + 0.114 * color.z
+}
+
+/// Mean squared error over all color channels. Zero for empty images.
+pub fn mse(reference: &FrameBuffer, actual: &FrameBuffer) -> Result<f32, BufferError> {
+    let pixels = pixels(reference, actual)?;
+    let sum: f32 = pixels
+        .iter()
+        .flat_map(|&(a, b)| channel_errors(a, b))
+        .map(|e| e * e)
+        .sum();
+
+    Ok(sum / (pixels.len() * 3).max(1) as f32)
+}
+
+/// Peak signal-to-noise ratio in decibels, infinite for identical images.
+/// Around 40 dB differences are hard to see; 8-bit rounding alone gives
+/// about 59 dB.
+pub fn psnr(reference: &FrameBuffer, actual: &FrameBuffer) -> Result<f32, BufferError> {
+    Ok(-10.0 * mse(reference, actual)?.log10())
+}
+
+/// Largest difference in any color channel of any pixel.
+pub fn max_error(reference: &FrameBuffer, actual: &FrameBuffer) -> Result<f32, BufferError> {
+    Ok(pixels(reference, actual)?
+        .iter()
+        .flat_map(|&(a, b)| channel_errors(a, b))
+        .fold(0.0, f32::max))
+}
+
+/// Number of pixels with a color channel differing by more than
+/// `threshold`, which allows differences of whole 8-bit steps up to and
+/// including it.
+pub fn differing_pixels(
+    reference: &FrameBuffer,
+    actual: &FrameBuffer,
+    threshold: f32,
+) -> Result<usize, BufferError> {
+    Ok(pixels(reference, actual)?
+        .iter()
+        .filter(|&&(a, b)| exceeds(a, b, threshold))
+        .count())
+}
+
+/// Blurs row-major `values` with the 11-tap Gaussian SSIM is defined with,
+/// clamping at the edges.
+fn gaussian_blur(values: &[f32], width: usize, height: usize) -> Vec<f32> {
+    const RADIUS: isize = 5;
+    const SIGMA: f32 = 1.5;
+
+    let weights: Vec<f32> = (-RADIUS..=RADIUS)
+        .map(|i| (-((i * i) as f32) / (2.0 * SIGMA * SIGMA)).exp())
+        .collect();
+    let total: f32 = weights.iter().sum();
+
+    let pass = |values: &[f32], index: &dyn Fn(usize, isize) -> usize| -> Vec<f32> {
+        (0..values.len())
+            .map(|i| {
+                weights
+                    .iter()
+                    .zip(-RADIUS..=RADIUS)
+                    .map(|(weight, offset)| weight * values[index(i, offset)])
+                    .sum::<f32>()
+                    / total
+            })
+            .collect()
+    };
+    let clamp = |v: isize, size: usize| v.clamp(0, size as isize - 1) as usize;
+
+    let rows = pass(values, &|i, offset| {
+        let (x, y) = (i % width, i / width);
+        y * width + clamp(x as isize + offset, width)
+    });
+    pass(&rows, &|i, offset| {
+        let (x, y) = (i % width, i / width);
+        clamp(y as isize + offset, height) * width + x
+    })
+}
+
+/// Mean structural similarity of the images' luma, from 1 for identical
+/// images down towards 0 and below as structure differs. Unlike `mse`, it
+/// weighs changes to edges and texture over uniform shifts in brightness.
+pub fn ssim(reference: &FrameBuffer, actual: &FrameBuffer) -> Result<f32, BufferError> {
+    const C1: f32 = 0.01 * 0.01;
+    const C2: f32 = 0.03 * 0.03;
+
+    let pixels = pixels(reference, actual)?;
+    if pixels.is_empty() {
+        return Ok(1.0);
+    }
+
+    let (width, height) = (reference.width, reference.height);
+    let blur = |f: &dyn Fn(f32, f32) -> f32| {
+        let values: Vec<f32> = pixels.iter().map(|&(a, b)| f(luma(a), luma(b))).collect();
+        gaussian_blur(&values, width, height)
+    };
+
+    let mean_a = blur(&|a, _| a);
+    let mean_b = blur(&|_, b| b);
+    let square_a = blur(&|a, _| a * a);
+    let square_b = blur(&|_, b| b * b);
+    let product = blur(&|a, b| a * b);
+
+    let sum: f32 = (0..pixels.len())
+        .map(|i| {
+            let (ma, mb) = (mean_a[i], mean_b[i]);
+            let variance_a = square_a[i] - ma * ma;
+            let variance_b = square_b[i] - mb * mb;
+            let covariance = product[i] - ma * mb;
+
+            (2.0 * ma * mb + C1) * (2.0 * covariance + C2)
+                / ((ma * ma + mb * mb + C1) * (variance_a + variance_b + C2))
+        })
+        .sum();
+
+    Ok(sum / pixels.len() as f32)
+}
+
+/// Draws `reference` dimmed to gray into every sample of `target`, with
+/// pixels differing by more than `threshold` in red, brighter the larger
+/// the difference. The threshold is applied as by [`differing_pixels`].
+/// All three must be the same size.
+pub fn diff_image(
+    reference: &FrameBuffer,
+    actual: &FrameBuffer,
+    threshold: f32,
+    target: &mut FrameBuffer,
+) -> Result<(), BufferError> {
+    if target.width != reference.width || target.height != reference.height {
+        return Err(BufferError::SizeMismatch);
+    }
+
+    let pixels = pixels(reference, actual)?;
+    let samples = target.samples.count();
+
+    for (pixel, (a, b)) in target.to_array_mut()?.chunks_exact_mut(samples).zip(pixels) {
+        let error = channel_errors(a, b).into_iter().fold(0.0, f32::max);
+        pixel.fill(if exceeds(a, b, threshold) {
+            color::pack(Vec4::new(0.5 + 0.5 * error, 0.0, 0.0, 1.0))
+        } else {
+            let gray = 0.25 * luma(a);
+            color::pack(Vec4::new(gray, gray, gray, 1.0))
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::Buffer;
+
+    #[test]
+    fn metrics_grow_with_the_difference() {
+        let mut reference = FrameBuffer::new(64, 64);
+        for (i, pixel) in reference.to_array_mut().unwrap().iter_mut().enumerate() {
+            *pixel = 0xff000000 | ((i as u32 * 0x030507) & 0x7f7f7f);
+        }
+        assert_eq!(mse(&reference, &reference), Ok(0.0));
+        assert_eq!(psnr(&reference, &reference), Ok(f32::INFINITY));
+        assert_eq!(ssim(&reference, &reference), Ok(1.0));
+        assert_eq!(max_error(&reference, &reference), Ok(0.0));
+
+        // One 8-bit step in every channel.
+        let mut shifted = reference.clone();
+        for pixel in shifted.to_array_mut().unwrap() {
+            *pixel += 0x010101;
+        }
+        let step = 1.0 / 255.0;
+        assert!((mse(&reference, &shifted).unwrap() - step * step).abs() < 1e-9);
+        assert!((psnr(&reference, &shifted).unwrap() - 48.13).abs() < 0.01);
+        assert!((max_error(&reference, &shifted).unwrap() - step).abs() < 1e-6);
+        assert_eq!(
+            differing_pixels(&reference, &shifted, step / 2.0),
+            Ok(64 * 64)
+        );
+        assert_eq!(differing_pixels(&reference, &shifted, step * 1.5), Ok(0));
+
+        // Noise hurts structural similarity far more than the same change
+        // spread evenly.
+        let mut noisy = reference.clone();
+        for (i, pixel) in noisy.to_array_mut().unwrap().iter_mut().enumerate() {
+            if i % 2 == 0 {
+                *pixel += 0x101010;
+            }
+        }
+        let mut brighter = reference.clone();
+        for pixel in brighter.to_array_mut().unwrap() {
+            *pixel += 0x080808;
+        }
+        assert!(ssim(&reference, &noisy).unwrap() < ssim(&reference, &brighter).unwrap());
+
+        assert!(mse(&reference, &FrameBuffer::new(64, 1)).is_err());
+    }
+
+    #[test]
+    fn thresholds_count_whole_steps() {
+        // Every byte value against the same two steps up.
+        let mut reference = FrameBuffer::new(254, 1);
+        for (i, pixel) in reference.to_array_mut().unwrap().iter_mut().enumerate() {
+            *pixel = 0xff000000 | (i as u32 * 0x010101);
+        }
+        let mut shifted = reference.clone();
+        for pixel in shifted.to_array_mut().unwrap() {
+            *pixel += 0x020202;
+        }
+
+        let two = 2.0 / 255.0;
+        assert_eq!(differing_pixels(&reference, &shifted, two), Ok(0));
+        assert_eq!(differing_pixels(&reference, &shifted, 1.0 / 255.0), Ok(254));
+
+        let mut diff = FrameBuffer::new(254, 1);
+        diff_image(&reference, &shifted, two, &mut diff).unwrap();
+        // Gray everywhere, with nothing marked red.
+        assert!(diff
+            .to_array()
+            .unwrap()
+            .iter()
+            .all(|&p| (p >> 16) & 0xff == p & 0xff));
+    }
+}
@@ -0,0 +1,485 @@
+use math::{Mat4, Vec2, Vec3, Vec4};
+
+use crate::{
+    buffer::{
+        ops::{ToArray, ToArrayMut},
+        Buffer, BufferError, FrameBuffer,
+    },
+    color,
+    mesh::{Mesh, Topology},
+    msaa::SampleCount,
+    pipeline::{CompareFunction, DrawError, Pipeline, PipelineState, RasterState, RenderTarget},
+    resample::Kernel,
+    shader::{FragmentInput, FragmentOutput, FragmentShader, VertexOutput, VertexShader},
+    texture::{FilterMode, Sampler, Texture2D},
+};
+
+/// Faces of a cube map, in storage order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl CubeFace {
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX,
+        CubeFace::NegativeX,
+        CubeFace::PositiveY,
+        CubeFace::NegativeY,
+        CubeFace::PositiveZ,
+        CubeFace::NegativeZ,
+    ];
+
+    /// The face `direction` points through, and the position on it with
+    /// both coordinates in `[-1, 1]`.
+    fn project(direction: Vec3) -> (CubeFace, Vec2) {
+        let Vec3 { x, y, z } = direction;
+        let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+
+        let (face, s, t, major) = if ax >= ay && ax >= az {
+            if x > 0.0 {
+                (CubeFace::PositiveX, -z, -y, ax)
+            } else {
+                (CubeFace::NegativeX, z, -y, ax)
+            }
+        } else if ay >= az {
+            if y > 0.0 {
+                (CubeFace::PositiveY, x, z, ay)
+            } else {
+                (CubeFace::NegativeY, x, -z, ay)
+            }
+        } else if z > 0.0 {
+            (CubeFace::PositiveZ, x, -y, az)
+        } else {
+            (CubeFace::NegativeZ, -x, -y, az)
+        };
+
+        (face, Vec2::new(s / major, t / major))
+    }
+
+    /// Inverse of `project`. Positions outside `[-1, 1]` give directions
+    /// through the neighbouring faces.
+    fn direction(self, st: Vec2) -> Vec3 {
+        let Vec2 { x: s, y: t } = st;
+
+        match self {
+            CubeFace::PositiveX => Vec3::new(1.0, -t, -s),
+            CubeFace::NegativeX => Vec3::new(-1.0, -t, s),
+            CubeFace::PositiveY => Vec3::new(s, 1.0, t),
+            CubeFace::NegativeY => Vec3::new(s, -1.0, -t),
+            CubeFace::PositiveZ => Vec3::new(s, -t, 1.0),
+            CubeFace::NegativeZ => Vec3::new(-s, -t, -1.0),
+        }
+    }
+}
+
+/// Six square images covering every direction, sampled by direction vector.
+/// Face images are laid out as seen from inside the cube, following the
+/// usual cube map conventions, and decoded by their own `srgb` flags.
+#[derive(Debug)]
+pub struct TextureCube {
+    /// `levels[level][face]`, halving in size down to 1×1.
+    levels: Vec<[FrameBuffer; 6]>,
+}
+
+impl TextureCube {
+    /// Builds a cube map from its faces in `CubeFace::ALL` order. Faces must
+    /// be square, single-sampled and all the same size.
+    pub fn new(faces: [FrameBuffer; 6]) -> Result<Self, BufferError> {
+        let size = faces[0].width;
+
+        if faces.iter().any(|face| face.samples != SampleCount::X1) {
+            return Err(BufferError::SampleCountMismatch);
+        }
+        if size == 0
+            || faces
+                .iter()
+                .any(|face| face.width != size || face.height != size)
+        {
+            return Err(BufferError::SizeMismatch);
+        }
+
+        Ok(Self {
+            levels: vec![faces],
+        })
+    }
+
+    /// Resamples an equirectangular panorama into faces of `size` texels.
+    /// The panorama's top row looks along +Y and its centre column along -Z.
+    /// Faces are stored in the same color space as the panorama.
+    pub fn from_equirectangular(panorama: &Texture2D, sampler: &Sampler, size: usize) -> Self {
+        let srgb = panorama.image().srgb;
+        let faces = CubeFace::ALL.map(|face| {
+            let mut image = FrameBuffer::new(size, size);
+            image.srgb = srgb;
+            let pixels = image.to_array_mut().unwrap();
+
+            for y in 0..size {
+                for x in 0..size {
+                    let st = Vec2::new(
+                        (x as f32 + 0.5) / size as f32 * 2.0 - 1.0,
+                        (y as f32 + 0.5) / size as f32 * 2.0 - 1.0,
+                    );
+                    let d = face.direction(st).normalise();
+                    let uv = Vec2::new(
+                        0.5 + d.x.atan2(-d.z) / std::f32::consts::TAU,
+                        d.y.clamp(-1.0, 1.0).acos() / std::f32::consts::PI,
+                    );
+
+                    pixels[y * size + x] = color::encode(panorama.sample(sampler, uv), srgb);
+                }
+            }
+
+            image
+        });
+
+        Self {
+            levels: vec![faces],
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.levels[0][0].width
+    }
+
+    pub fn face(&self, face: CubeFace) -> &FrameBuffer {
+        &self.levels[0][face as usize]
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Rebuilds the mip chain of every face. Faces are filtered on their
+    /// own, so blurry levels may show faint seams.
+    pub fn generate_mipmaps(&mut self, kernel: Kernel) {
+        self.levels.truncate(1);
+
+        let faces = CubeFace::ALL.map(|face| {
+            let mut texture = Texture2D::new(self.face(face).clone()).unwrap();
+            texture.generate_mipmaps(kernel);
+            texture
+        });
+
+        for level in 1..faces[0].level_count() {
+            self.levels.push(
+                faces
+                    .each_ref()
+                    .map(|face| face.level(level).unwrap().clone()),
+            );
+        }
+    }
+
+    /// Fetches texel `(x, y)` of `face`, following coordinates past the edge
+    /// onto the neighbouring face so filtering is seamless.
+    fn texel(&self, level: usize, face: CubeFace, x: i64, y: i64) -> Vec4 {
+        let size = self.levels[level][0].width;
+        let inside = |v: i64| (0..size as i64).contains(&v);
+
+        let (face, x, y) = if inside(x) && inside(y) {
+            (face, x as usize, y as usize)
+        } else {
+            let st = Vec2::new(
+                (x as f32 + 0.5) / size as f32 * 2.0 - 1.0,
+                (y as f32 + 0.5) / size as f32 * 2.0 - 1.0,
+            );
+            let (face, st) = CubeFace::project(face.direction(st));
+            let texel = |v: f32| (((v + 1.0) * 0.5 * size as f32) as usize).min(size - 1);
+
+            (face, texel(st.x), texel(st.y))
+        };
+
+        let image = &self.levels[level][face as usize];
+        image.decode(image.to_array().unwrap()[y * size + x])
+    }
+
+    fn filter(&self, level: usize, filter: FilterMode, direction: Vec3) -> Vec4 {
+        let (face, st) = CubeFace::project(direction);
+        let size = self.levels[level][0].width as f32;
+        let x = (st.x + 1.0) * 0.5 * size;
+        let y = (st.y + 1.0) * 0.5 * size;
+
+        match filter {
+            FilterMode::Nearest => {
+                let texel = |v: f32| (v.floor() as i64).min(size as i64 - 1);
+                self.texel(level, face, texel(x), texel(y))
+            }
+            FilterMode::Linear => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let texel = |x, y| self.texel(level, face, x, y);
+                let top = texel(x0, y0) * (1.0 - tx) + texel(x0 + 1, y0) * tx;
+                let bottom = texel(x0, y0 + 1) * (1.0 - tx) + texel(x0 + 1, y0 + 1) * tx;
+
+                top * (1.0 - ty) + bottom * ty
+            }
+        }
+    }
+
+    /// Samples the base level in `direction`, which need not be normalised.
+    /// Addressing modes don't apply to cube maps.
+    pub fn sample(&self, sampler: &Sampler, direction: Vec3) -> Vec4 {
+        self.filter(0, sampler.mag_filter, direction)
+    }
+
+    /// Samples at an explicit level of detail; see
+    /// [`Texture2D::sample_level`].
+    pub fn sample_level(&self, sampler: &Sampler, direction: Vec3, lod: f32) -> Vec4 {
+        let lod = lod.max(sampler.min_lod).min(sampler.max_lod);
+        if lod.is_nan() || lod <= 0.0 {
+            return self.filter(0, sampler.mag_filter, direction);
+        }
+
+        let lod = lod.min((self.levels.len() - 1) as f32);
+
+        match sampler.mipmap_filter {
+            FilterMode::Nearest => self.filter(lod.round() as usize, sampler.min_filter, direction),
+            FilterMode::Linear => {
+                let level = lod.floor() as usize;
+                let t = lod - level as f32;
+                let near = self.filter(level, sampler.min_filter, direction);
+
+                if t == 0.0 {
+                    return near;
+                }
+
+                let far = self.filter(level + 1, sampler.min_filter, direction);
+                near * (1.0 - t) + far * t
+            }
+        }
+    }
+}
+
+/// Reflects the incident direction `incident` about `normal`, for looking up
+/// environment reflections.
+pub fn reflect(incident: Vec3, normal: Vec3) -> Vec3 {
+    incident - normal * (2.0 * Vec3::dot(normal, incident))
+}
+
+struct SkyboxVertexShader;
+
+impl VertexShader for SkyboxVertexShader {
+    type Vertex = Vec2;
+    type Uniforms = ();
+    type Varyings = Vec2;
+
+    fn shade(&self, vertex: &Vec2, _: &()) -> VertexOutput<Vec2> {
+        // On the far plane, so the sky only fills pixels nothing else covers.
+        VertexOutput {
+            position: Vec4::new(vertex.x, vertex.y, 1.0, 1.0),
+            varyings: *vertex,
+        }
+    }
+}
+
+struct SkyboxFragmentShader<'a> {
+    cube:                    &'a TextureCube,
+    sampler:                 Sampler,
+    inverse_view_projection: Mat4,
+}
+
+impl FragmentShader for SkyboxFragmentShader<'_> {
+    type Uniforms = ();
+    type Varyings = Vec2;
+
+    const WRITES_DEPTH: bool = false;
+
+    fn shade(&self, input: &FragmentInput<Vec2>, _: &()) -> Option<FragmentOutput> {
+        let unproject = |z: f32| {
+            let p = Vec4::new(input.varyings.x, input.varyings.y, z, 1.0)
+                * self.inverse_view_projection;
+            Vec3::new(p.x / p.w, p.y / p.w, p.z / p.w)
+        };
+        let direction = unproject(1.0) - unproject(-1.0);
+
+        Some(FragmentOutput::new(
+            self.cube.sample(&self.sampler, direction),
+        ))
+    }
+}
+
+/// Fills every pixel of `target` still at the far plane with `cube`, as seen
+/// through the camera whose inverse view-projection matrix is given. Draw it
+/// after opaque geometry so covered pixels are skipped by the depth test.
+///
+/// The viewport, scissor, stencil, blending and debug mode come from
+/// `state`; its depth and raster settings are replaced by the skybox's own.
+pub fn draw_skybox(
+    cube: &TextureCube,
+    sampler: &Sampler,
+    inverse_view_projection: Mat4,
+    state: &PipelineState,
+    target: &mut RenderTarget,
+) -> Result<(), DrawError> {
+    let mut pipeline = Pipeline::new(
+        SkyboxVertexShader,
+        SkyboxFragmentShader {
+            cube,
+            sampler: *sampler,
+            inverse_view_projection,
+        },
+    );
+    pipeline.state = *state;
+    pipeline.state.depth.compare = CompareFunction::LessEqual;
+    pipeline.state.depth.write = false;
+    pipeline.state.raster = RasterState::default();
+
+    // A single triangle covering the whole viewport.
+    let triangle = Mesh::new(
+        vec![
+            Vec2::new(-1.0, -1.0),
+            Vec2::new(3.0, -1.0),
+            Vec2::new(-1.0, 3.0),
+        ],
+        Topology::TriangleList,
+    );
+
+    pipeline.draw(&triangle, &(), target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffer::{
+            ops::{Fill, GetPixel, SetPixel},
+            DepthBuffer, Rect,
+        },
+        pipeline::{DepthState, PolygonMode},
+        testing::identity,
+        texture::AddressMode,
+    };
+
+    /// A cube whose faces are each a flat color, in `CubeFace::ALL` order.
+    fn colored_cube() -> (TextureCube, [Vec4; 6]) {
+        let colors = [
+            Vec4::new(1.0, 0.0, 0.0, 1.0),
+            Vec4::new(0.0, 1.0, 0.0, 1.0),
+            Vec4::new(0.0, 0.0, 1.0, 1.0),
+            Vec4::new(1.0, 1.0, 0.0, 1.0),
+            Vec4::new(0.0, 1.0, 1.0, 1.0),
+            Vec4::new(1.0, 0.0, 1.0, 1.0),
+        ];
+        let faces = colors.map(|c| {
+            let mut face = FrameBuffer::new(4, 4);
+            face.fill(color::pack(c));
+            face
+        });
+        (TextureCube::new(faces).unwrap(), colors)
+    }
+
+    #[test]
+    fn directions_select_faces_and_blend_across_edges() {
+        let (mut cube, colors) = colored_cube();
+        let sampler = Sampler::new(FilterMode::Linear, AddressMode::ClampToEdge);
+
+        assert_eq!(cube.sample(&sampler, Vec3::new(1.0, 0.1, 0.2)), colors[0]);
+        assert_eq!(cube.sample(&sampler, Vec3::new(0.0, -2.0, 0.5)), colors[3]);
+        assert_eq!(cube.sample(&sampler, Vec3::new(0.0, 0.0, -1.0)), colors[5]);
+
+        // Next to the edge between +X and +Z, both faces contribute.
+        let edge = cube.sample(&sampler, Vec3::new(1.0, 0.0, 0.999));
+        assert!(edge.x > 0.3 && edge.y > 0.3, "{edge:?}");
+
+        cube.generate_mipmaps(Kernel::Box);
+        assert_eq!(cube.level_count(), 3);
+        assert_eq!(
+            cube.sample_level(&sampler, Vec3::new(1.0, 0.0, 0.0), 2.0),
+            colors[0]
+        );
+        let inverted = Sampler {
+            min_lod: 2.0,
+            max_lod: 1.0,
+            ..sampler
+        };
+        assert_eq!(
+            cube.sample_level(&inverted, Vec3::new(1.0, 0.0, 0.0), 0.0),
+            colors[0]
+        );
+    }
+
+    #[test]
+    fn panoramas_wrap_around_the_cube() {
+        let mut panorama = FrameBuffer::new(16, 8);
+        for y in 0..8 {
+            for x in 0..16 {
+                let color = Vec4::new(x as f32 / 15.0, y as f32 / 7.0, 0.0, 1.0);
+                panorama.set_pixel(x, y, color::pack(color)).unwrap();
+            }
+        }
+        let panorama = Texture2D::new(panorama).unwrap();
+        let sampler = Sampler::new(FilterMode::Linear, AddressMode::ClampToEdge);
+        let cube = TextureCube::from_equirectangular(&panorama, &sampler, 8);
+
+        let centre = |face| color::unpack(cube.face(face).get_pixel(4, 4).unwrap());
+        // The top row of the panorama is straight up, its middle straight ahead.
+        assert!(centre(CubeFace::PositiveY).y < 0.1);
+        let ahead = centre(CubeFace::NegativeZ);
+        assert!((ahead.x - 0.5).abs() < 0.1 && (ahead.y - 0.5).abs() < 0.1);
+    }
+
+    #[test]
+    fn reflections_mirror_about_the_normal() {
+        let reflected = reflect(Vec3::new(1.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(reflected, Vec3::new(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn skybox_fills_only_uncovered_pixels() {
+        let (cube, colors) = colored_cube();
+        let sampler = Sampler::new(FilterMode::Linear, AddressMode::ClampToEdge);
+        let draw = |state: &PipelineState| {
+            let mut color = FrameBuffer::new(8, 8);
+            let mut depth = DepthBuffer::new(8, 8);
+            depth.set_pixel(0, 0, 0.3).unwrap();
+            draw_skybox(
+                &cube,
+                &sampler,
+                identity(),
+                state,
+                &mut RenderTarget::new(&mut color, &mut depth),
+            )
+            .unwrap();
+            color
+        };
+        let drawn = |color: &FrameBuffer| {
+            color
+                .to_array()
+                .unwrap()
+                .iter()
+                .filter(|&&p| p != 0)
+                .count()
+        };
+
+        // Without a transform, the camera looks down +Z.
+        let color = draw(&PipelineState::default());
+        assert_eq!(color.get_pixel(0, 0).unwrap(), 0);
+        assert_eq!(color::unpack(color.get_pixel(4, 4).unwrap()), colors[4]);
+        assert_eq!(drawn(&color), 63);
+
+        // The caller's scissor applies, and its depth and raster state don't.
+        let state = PipelineState {
+            scissor: Some(Rect::new(0, 0, 4, 8)),
+            depth: DepthState {
+                compare: CompareFunction::Never,
+                ..Default::default()
+            },
+            raster: RasterState {
+                polygon_mode: PolygonMode::Point,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let color = draw(&state);
+        assert_eq!(drawn(&color), 31);
+        assert_eq!(color.get_pixel(5, 4).unwrap(), 0);
+    }
+}
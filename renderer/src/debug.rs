@@ -0,0 +1,417 @@
+use math::{Vec2, Vec3, Vec4};
+
+use crate::{
+    buffer::{
+        ops::{GetPixel, ToArrayMut},
+        BufferError, FrameBuffer, HdrBuffer,
+    },
+    color,
+    depth::Colormap,
+    shader::{FragmentInput, Varyings},
+};
+
+/// Replaces what the fragment shader would draw with information about the
+/// rendering itself. Set through `PipelineState::debug`; the fragment shader
+/// isn't run while a mode is active.
+///
+/// Modes showing normals or texture coordinates find them through the
+/// pipeline's [`DebugFields`], so varyings need no changes to be inspected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugMode {
+    /// Counts the fragments rasterized into each pixel, whether or not they
+    /// pass the depth and stencil tests. Each one adds 1 to every channel of
+    /// the first color attachment, which [`overdraw_heatmap`] turns into a
+    /// picture. Counts saturate at 255 in a `FrameBuffer`; count into an
+    /// `HdrBuffer` to go higher. Depth and stencil are left untouched.
+    Overdraw,
+    /// Gives each primitive of a draw its own color.
+    PrimitiveId,
+    /// Shows the normal mapped from `[-1, 1]` to `[0, 1]`.
+    Normals,
+    /// A checkerboard of `squares` squares per unit of texture coordinates,
+    /// tinted red along u and green along v so flipped or rotated
+    /// coordinates stand out.
+    UvChecker { squares: f32 },
+    /// The mip level a `width`×`height` texture would be sampled from, going
+    /// red, orange, yellow, green, cyan, blue, purple and white from the
+    /// full size image down.
+    MipLevel { width: usize, height: usize },
+    /// How many lanes of each 2×2 quad the primitive covers: red for one,
+    /// orange for two, yellow for three and green for all four. Uncovered
+    /// lanes are shaded only for derivatives. Lines and points always show
+    /// one.
+    QuadOccupancy,
+}
+
+/// Shown where a mode needs varyings that don't provide what it shows.
+const MISSING: Vec4 = Vec4 {
+    x: 1.0,
+    y: 0.0,
+    z: 1.0,
+    w: 1.0,
+};
+
+const LEVEL_COLORS: [Vec3; 8] = [
+    Vec3 {
+        x: 1.0,
+        y: 0.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 1.0,
+        y: 0.5,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 1.0,
+        y: 1.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: 1.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: 1.0,
+        z: 1.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: 0.0,
+        z: 1.0,
+    },
+    Vec3 {
+        x: 0.5,
+        y: 0.0,
+        z: 1.0,
+    },
+    Vec3 {
+        x: 1.0,
+        y: 1.0,
+        z: 1.0,
+    },
+];
+
+fn opaque(color: Vec3) -> Vec4 {
+    Vec4::new(color.x, color.y, color.z, 1.0)
+}
+
+/// Where debug modes find the normal and texture coordinates among a draw's
+/// varyings, shown in magenta when missing. Defaults to
+/// [`Varyings::debug_normal`] and [`Varyings::debug_uv`]; set the fields to
+/// pick them out of varyings that don't implement those, such as tuples.
+#[derive(Debug, Clone, Copy)]
+pub struct DebugFields<V> {
+    pub normal: fn(&V) -> Option<Vec3>,
+    pub uv:     fn(&V) -> Option<Vec2>,
+}
+
+impl<V: Varyings> DebugFields<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<V: Varyings> Default for DebugFields<V> {
+    fn default() -> Self {
+        Self {
+            normal: V::debug_normal,
+            uv:     V::debug_uv,
+        }
+    }
+}
+
+impl DebugMode {
+    /// The color of a fragment of primitive `primitive`, whose quad has
+    /// `lanes` lanes covered.
+    pub(crate) fn shade<V: Varyings>(
+        self,
+        input: &FragmentInput<V>,
+        fields: &DebugFields<V>,
+        primitive: u32,
+        lanes: u32,
+    ) -> Vec4 {
+        match self {
+            // Counted by the pipeline instead.
+            DebugMode::Overdraw => MISSING,
+            DebugMode::PrimitiveId => {
+                // A hash spreads neighbouring indices over distant colors;
+                // the floor keeps every primitive visible against black.
+                let mut h = primitive.wrapping_mul(0x9e37_79b9);
+                h ^= h >> 16;
+                h = h.wrapping_mul(0x85eb_ca6b);
+                h ^= h >> 13;
+                let channel = |shift: u32| 0.2 + 0.8 * ((h >> shift) & 0xff) as f32 / 255.0;
+
+                Vec4::new(channel(0), channel(8), channel(16), 1.0)
+            }
+            DebugMode::Normals => match (fields.normal)(&input.varyings) {
+                Some(normal) => opaque(normal.normalise() * 0.5 + 0.5),
+                None => MISSING,
+            },
+            DebugMode::UvChecker { squares } => match (fields.uv)(&input.varyings) {
+                Some(Vec2 { x: u, y: v }) => {
+                    let (u, v) = (u * squares, v * squares);
+                    let shade = if (u.floor() + v.floor()).rem_euclid(2.0) < 1.0 {
+                        0.9
+                    } else {
+                        0.3
+                    };
+                    let tint = |t: f32| 0.4 + 0.6 * (t / squares).rem_euclid(1.0);
+
+                    Vec4::new(shade * tint(u), shade * tint(v), shade * 0.4, 1.0)
+                }
+                None => MISSING,
+            },
+            DebugMode::MipLevel { width, height } => {
+                let (Some(ddx), Some(ddy)) = ((fields.uv)(&input.ddx), (fields.uv)(&input.ddy))
+                else {
+                    return MISSING;
+                };
+
+                let texels =
+                    |d: Vec2| Vec2::new(d.x * width as f32, d.y * height as f32).magnitude();
+                let lod = texels(ddx)
+                    .max(texels(ddy))
+                    .log2()
+                    .clamp(0.0, (LEVEL_COLORS.len() - 1) as f32);
+                let level = (lod.floor() as usize).min(LEVEL_COLORS.len() - 2);
+                let t = lod - level as f32;
+
+                opaque(LEVEL_COLORS[level] * (1.0 - t) + LEVEL_COLORS[level + 1] * t)
+            }
+            DebugMode::QuadOccupancy => opaque(LEVEL_COLORS[lanes.clamp(1, 4) as usize - 1]),
+        }
+    }
+}
+
+/// A buffer [`DebugMode::Overdraw`] counts fragments into.
+pub trait OverdrawCounts {
+    fn size(&self) -> (usize, usize);
+
+    /// The count of pixel `(x, y)`, read from its first sample.
+    fn count(&self, x: usize, y: usize) -> Result<u32, BufferError>;
+}
+
+impl OverdrawCounts for FrameBuffer {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn count(&self, x: usize, y: usize) -> Result<u32, BufferError> {
+        Ok((self.get_pixel(x, y)? >> 16) & 0xff)
+    }
+}
+
+impl OverdrawCounts for HdrBuffer {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn count(&self, x: usize, y: usize) -> Result<u32, BufferError> {
+        Ok(self.get_pixel(x, y)?.x as u32)
+    }
+}
+
+/// Colors the fragment counts left in `counts` by [`DebugMode::Overdraw`]
+/// into `target`, which must be the same size. Pixels drawn once are dark
+/// blue, going through the Turbo colormap up to red at `max` and above.
+/// Untouched pixels are black.
+pub fn overdraw_heatmap(
+    counts: &impl OverdrawCounts,
+    max: u32,
+    target: &mut FrameBuffer,
+) -> Result<(), BufferError> {
+    let (width, height) = counts.size();
+    if width != target.width || height != target.height {
+        return Err(BufferError::SizeMismatch);
+    }
+
+    let samples = target.samples.count();
+    for (i, pixel) in target.to_array_mut()?.chunks_exact_mut(samples).enumerate() {
+        let count = counts.count(i % width, i / width)?;
+        pixel.fill(if count == 0 {
+            0xff000000
+        } else {
+            let t = (count - 1) as f32 / max.saturating_sub(1).max(1) as f32;
+            let c = Colormap::Turbo.map(t);
+            color::pack(Vec4::new(c.x, c.y, c.z, 1.0))
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffer::{ops::ToArray, Buffer, DepthBuffer},
+        mesh::{Mesh, Topology},
+        pipeline::{CompareFunction, Pipeline, RenderTarget},
+        shader::{FragmentOutput, FragmentShader},
+        testing::{fullscreen, Positions},
+        tile::Tiling,
+    };
+
+    struct Black;
+
+    impl FragmentShader for Black {
+        type Uniforms = ();
+        type Varyings = Vec4;
+
+        const WRITES_DEPTH: bool = false;
+
+        fn shade(&self, _: &FragmentInput<Vec4>, _: &()) -> Option<FragmentOutput> {
+            Some(FragmentOutput::new(Vec4::new(0.0, 0.0, 0.0, 1.0)))
+        }
+    }
+
+    /// Two triangles over a 32×32 target: `(4, 9)` is covered by the first
+    /// only, `(26, 5)` by the second only and `(16, 28)` by both.
+    fn triangles() -> Mesh<Vec4> {
+        Mesh::new(
+            vec![
+                Vec4::new(-1.0, -1.0, 0.0, 1.0),
+                Vec4::new(1.0, -1.0, 0.0, 1.0),
+                Vec4::new(-1.0, 1.0, 0.0, 1.0),
+                Vec4::new(-1.0, -1.0, 0.5, 1.0),
+                Vec4::new(1.0, -1.0, 0.5, 1.0),
+                Vec4::new(1.0, 1.0, 0.5, 1.0),
+            ],
+            Topology::TriangleList,
+        )
+    }
+
+    fn render(mode: DebugMode, fields: Option<DebugFields<Vec4>>) -> FrameBuffer {
+        let mut outputs = Vec::new();
+        for tiling in [
+            None,
+            Some(Tiling {
+                tile_size: 8,
+                threads:   3,
+            }),
+        ] {
+            let mut color = FrameBuffer::new(32, 32);
+            let mut depth = DepthBuffer::new(32, 32);
+            let mut pipeline = Pipeline::new(Positions, Black);
+            pipeline.state.debug = Some(mode);
+            pipeline.set_tiling(tiling);
+            if let Some(fields) = fields {
+                pipeline.debug_fields = fields;
+            }
+
+            pipeline
+                .draw(
+                    &triangles(),
+                    &(),
+                    &mut RenderTarget::new(&mut color, &mut depth),
+                )
+                .unwrap();
+            outputs.push(color);
+        }
+
+        assert_eq!(outputs[0].to_array(), outputs[1].to_array(), "{mode:?}");
+        outputs.remove(0)
+    }
+
+    #[test]
+    fn modes_show_what_they_inspect() {
+        // A normal facing the camera, and texture coordinates spanning the
+        // target.
+        let fields = DebugFields {
+            normal: |_: &Vec4| Some(Vec3::new(0.0, 0.0, 1.0)),
+            uv:     |p: &Vec4| Some(Vec2::new(p.x * 0.5 + 0.5, p.y * 0.5 + 0.5)),
+        };
+        // Derivatives go through the fields too, so they must be linear.
+        let scales = DebugFields {
+            uv: |p: &Vec4| Some(Vec2::new(p.x * 0.5, p.y * 0.5)),
+            ..fields
+        };
+        let pixel = |mode, fields| {
+            let color = render(mode, fields);
+            move |x, y| color.get_pixel(x, y).unwrap()
+        };
+
+        let overdraw = pixel(DebugMode::Overdraw, None);
+        assert_eq!(overdraw(4, 9), 0xff010101);
+        assert_eq!(overdraw(16, 28), 0xff020202);
+
+        let ids = pixel(DebugMode::PrimitiveId, None);
+        assert_ne!(ids(4, 9), ids(26, 5));
+        assert_ne!(ids(4, 9) & 0xffffff, 0);
+
+        assert_eq!(pixel(DebugMode::Normals, Some(fields))(4, 9), 0xff8080ff);
+
+        let checker = pixel(DebugMode::UvChecker { squares: 4.0 }, Some(fields));
+        assert_ne!(checker(4, 28), checker(12, 28));
+        // Squares two apart match but for the red tint growing along u.
+        assert_eq!(checker(4, 28) & 0xffff, checker(20, 28) & 0xffff);
+        assert!(checker(20, 28) & 0xff0000 > checker(4, 28) & 0xff0000);
+
+        // Eight texels per pixel reads level 3.
+        let mip = DebugMode::MipLevel {
+            width:  256,
+            height: 256,
+        };
+        assert_eq!(pixel(mip, Some(scales))(4, 9), 0xff00ff00);
+
+        let occupancy = pixel(DebugMode::QuadOccupancy, None);
+        assert_eq!(occupancy(4, 9), 0xff00ff00);
+    }
+
+    #[test]
+    fn varyings_without_fields_show_as_missing() {
+        for mode in [
+            DebugMode::Normals,
+            DebugMode::UvChecker { squares: 4.0 },
+            DebugMode::MipLevel {
+                width:  16,
+                height: 16,
+            },
+        ] {
+            assert_eq!(render(mode, None).get_pixel(4, 9).unwrap(), 0xffff00ff);
+        }
+    }
+
+    #[test]
+    fn heatmaps_read_counts_past_eight_bits_from_hdr_buffers() {
+        let mesh = fullscreen();
+        let mut pipeline = Pipeline::new(Positions, Black);
+        pipeline.state.debug = Some(DebugMode::Overdraw);
+        pipeline.state.depth.compare = CompareFunction::Always;
+
+        let mut counts = FrameBuffer::new(4, 4);
+        let mut hdr_counts = HdrBuffer::new(4, 4);
+        let mut depth = DepthBuffer::new(4, 4);
+        for _ in 0..300 {
+            pipeline
+                .draw(&mesh, &(), &mut RenderTarget::new(&mut counts, &mut depth))
+                .unwrap();
+            pipeline
+                .draw(
+                    &mesh,
+                    &(),
+                    &mut RenderTarget::new(&mut hdr_counts, &mut depth),
+                )
+                .unwrap();
+        }
+        assert_eq!(counts.count(1, 1), Ok(255));
+        assert_eq!(hdr_counts.count(1, 1), Ok(300));
+
+        let mut heat = FrameBuffer::new(4, 4);
+        overdraw_heatmap(&hdr_counts, 300, &mut heat).unwrap();
+        let hottest = Colormap::Turbo.map(1.0);
+        assert_eq!(heat.get_pixel(1, 1).unwrap(), color::pack(opaque(hottest)));
+
+        overdraw_heatmap(&counts, 300, &mut heat).unwrap();
+        assert_ne!(heat.get_pixel(1, 1).unwrap(), color::pack(opaque(hottest)));
+
+        assert_eq!(
+            overdraw_heatmap(&hdr_counts, 300, &mut FrameBuffer::new(2, 2)),
+            Err(BufferError::SizeMismatch)
+        );
+    }
+}
@@ -0,0 +1,408 @@
+use std::sync::Mutex;
+
+use math::{Mat4, Vec3, Vec4};
+
+use crate::{
+    buffer::{ops::ToArray, Buffer, BufferError, DepthBuffer, FrameBuffer, Rect},
+    color,
+    pipeline::{RenderTarget, Viewport},
+    tile::Tiling,
+    view::ViewMut,
+    workers,
+};
+
+/// Surface attributes rendered by a geometry pass, for lighting afterwards
+/// in screen space. A fragment shader writes the albedo as its color and the
+/// rest with [`FragmentOutput::with_attachments`](crate::shader::FragmentOutput::with_attachments),
+/// all in linear space.
+#[derive(Debug, Clone)]
+pub struct GBuffer {
+    /// Base color in RGB, stored sRGB encoded for precision in the darks.
+    pub albedo:   FrameBuffer,
+    /// World-space normal, stored with [`encode_normal`].
+    pub normal:   FrameBuffer,
+    /// Specular intensity in red and glossiness in green, both `[0, 1]`.
+    pub material: FrameBuffer,
+    /// Light given off by the surface itself, added after lighting.
+    pub emissive: FrameBuffer,
+    pub depth:    DepthBuffer,
+}
+
+impl GBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut albedo = FrameBuffer::new(width, height);
+        albedo.srgb = true;
+
+        Self {
+            albedo,
+            normal: FrameBuffer::new(width, height),
+            material: FrameBuffer::new(width, height),
+            emissive: FrameBuffer::new(width, height),
+            depth: DepthBuffer::new(width, height),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.depth.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.depth.height
+    }
+
+    pub fn clear(&mut self) {
+        self.albedo.clear();
+        self.normal.clear();
+        self.material.clear();
+        self.emissive.clear();
+        self.depth.clear();
+    }
+
+    /// A render target with the attachments in albedo, normal, material,
+    /// emissive order.
+    pub fn target(&mut self) -> RenderTarget<'_> {
+        RenderTarget::with_attachments(
+            vec![
+                (&mut self.albedo).into(),
+                (&mut self.normal).into(),
+                (&mut self.material).into(),
+                (&mut self.emissive).into(),
+            ],
+            &mut self.depth,
+        )
+    }
+}
+
+/// Maps a unit normal into `[0, 1]` so it fits a color attachment.
+pub fn encode_normal(normal: Vec3) -> Vec4 {
+    Vec4::new(
+        normal.x * 0.5 + 0.5,
+        normal.y * 0.5 + 0.5,
+        normal.z * 0.5 + 0.5,
+        1.0,
+    )
+}
+
+pub fn decode_normal(color: Vec4) -> Vec3 {
+    Vec3::new(
+        color.x * 2.0 - 1.0,
+        color.y * 2.0 - 1.0,
+        color.z * 2.0 - 1.0,
+    )
+    .normalise()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Light {
+    /// Light travelling along `direction` from infinitely far away.
+    Directional { direction: Vec3, color: Vec3 },
+    /// Light spreading out from `position`, fading to nothing at `range`.
+    Point {
+        position: Vec3,
+        color:    Vec3,
+        range:    f32,
+    },
+}
+
+impl Light {
+    /// Direction towards the light from `position`, and the light arriving
+    /// there, or `None` when it's out of range.
+    fn incident(&self, position: Vec3) -> Option<(Vec3, Vec3)> {
+        match *self {
+            Light::Directional { direction, color } => Some((-direction.normalise(), color)),
+            Light::Point {
+                position: light,
+                color,
+                range,
+            } => {
+                let offset = light - position;
+                let distance = offset.magnitude();
+                if distance >= range || distance == 0.0 {
+                    return None;
+                }
+
+                // Inverse square falloff, windowed to reach zero at `range`.
+                let window = (1.0 - (distance / range).powi(4)).powi(2);
+                let falloff = window / (distance * distance + 1.0);
+
+                Some((offset / distance, color * falloff))
+            }
+        }
+    }
+}
+
+/// Lights a [`GBuffer`] with Blinn-Phong shading, as seen from the camera it
+/// was rendered with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightingPass {
+    /// Inverse of the view-projection matrix used for the geometry pass.
+    pub inverse_view_projection: Mat4,
+    /// The geometry pass's viewport, covering the whole target when unset.
+    /// Only pixels inside it with a depth in its range are lit.
+    pub viewport:                Option<Viewport>,
+    pub camera_position:         Vec3,
+    /// Light reaching every surface from all directions.
+    pub ambient:                 Vec3,
+    /// Lights tiles in parallel when set, otherwise on the calling thread.
+    pub tiling:                  Option<Tiling>,
+}
+
+impl LightingPass {
+    pub fn new(inverse_view_projection: Mat4, camera_position: Vec3) -> Self {
+        Self {
+            inverse_view_projection,
+            viewport: None,
+            camera_position,
+            ambient: Vec3::ZERO,
+            tiling: None,
+        }
+    }
+
+    /// Writes the lit color of every pixel of `gbuffer` into `target`, which
+    /// must be the same size. Pixels the geometry pass didn't cover are left
+    /// untouched, so a background drawn beforehand shows through.
+    pub fn apply(
+        &self,
+        gbuffer: &GBuffer,
+        lights: &[Light],
+        target: &mut FrameBuffer,
+    ) -> Result<(), BufferError> {
+        let (width, height) = (gbuffer.width(), gbuffer.height());
+        if target.width != width || target.height != height {
+            return Err(BufferError::SizeMismatch);
+        }
+
+        let (samples, srgb, dither) = (target.samples.count(), target.srgb, target.dither);
+        let encode = |color, x, y| dither.encode(color, srgb, x, y);
+        let viewport =
+            self.viewport
+                .unwrap_or(Viewport::new(0.0, 0.0, width as f32, height as f32));
+        let mut whole = target.view_mut(Rect::new(0, 0, width, height))?;
+
+        match &self.tiling {
+            Some(tiling) => {
+                let queue = Mutex::new(
+                    whole
+                        .into_tiles(tiling.tile_size, tiling.tile_size)
+                        .into_iter(),
+                );
+
+                workers::run(tiling.threads, &|| loop {
+                    let Some(mut tile) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+                    self.light_region(gbuffer, lights, &viewport, &mut tile, samples, &encode);
+                });
+            }
+            None => self.light_region(gbuffer, lights, &viewport, &mut whole, samples, &encode),
+        }
+
+        Ok(())
+    }
+
+    /// Lights the pixels of `region`, a view of a target with `samples`
+    /// samples per pixel, packing each pixel's color with `encode`. The
+    /// G-buffer is read from its first samples.
+    fn light_region(
+        &self,
+        gbuffer: &GBuffer,
+        lights: &[Light],
+        viewport: &Viewport,
+        region: &mut ViewMut<u32>,
+        samples: usize,
+        encode: &impl Fn(Vec4, usize, usize) -> u32,
+    ) {
+        let width = gbuffer.width();
+        let Rect {
+            x: left, y: top, ..
+        } = region.rect();
+        let gbuffer_samples = gbuffer.depth.samples.count();
+        let depth_range = viewport.max_depth - viewport.min_depth;
+        let covers = |x: f32, y: f32, z: f32| {
+            (viewport.x..viewport.x + viewport.width).contains(&x)
+                && (viewport.y..viewport.y + viewport.height).contains(&y)
+                && (viewport.min_depth.min(viewport.max_depth)
+                    ..=viewport.min_depth.max(viewport.max_depth))
+                    .contains(&z)
+        };
+
+        let depth = gbuffer.depth.to_array().unwrap();
+        let albedo = gbuffer.albedo.to_array().unwrap();
+        let normal = gbuffer.normal.to_array().unwrap();
+        let material = gbuffer.material.to_array().unwrap();
+        let emissive = gbuffer.emissive.to_array().unwrap();
+
+        for (y, row) in (top..).zip(region.rows_mut()) {
+            for (x, pixel) in (left..).zip(row.chunks_exact_mut(samples)) {
+                let i = y * width + x;
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let z = depth[i * gbuffer_samples];
+                // Anything else is background, or was drawn by another pass.
+                if !covers(px, py, z) {
+                    continue;
+                }
+
+                // Undoes the viewport transform. A flat depth range keeps no
+                // depth to undo, so surfaces are put midway into the frustum.
+                let ndc_z = if depth_range == 0.0 {
+                    0.0
+                } else {
+                    (z - viewport.min_depth) / depth_range * 2.0 - 1.0
+                };
+                let ndc = Vec4::new(
+                    (px - viewport.x) / viewport.width * 2.0 - 1.0,
+                    1.0 - (py - viewport.y) / viewport.height * 2.0,
+                    ndc_z,
+                    1.0,
+                );
+                let p = ndc * self.inverse_view_projection;
+                let position = Vec3::new(p.x / p.w, p.y / p.w, p.z / p.w);
+
+                let rgb = |c: Vec4| Vec3::new(c.x, c.y, c.z);
+                let albedo = rgb(gbuffer.albedo.decode(albedo[i * gbuffer_samples]));
+                let normal = decode_normal(color::unpack(normal[i * gbuffer_samples]));
+                let material = color::unpack(material[i * gbuffer_samples]);
+                let (specular, shininess) = (material.x, 2.0 + material.y * 254.0);
+                let view = (self.camera_position - position).normalise();
+
+                let lit = lights.iter().fold(
+                    rgb(gbuffer.emissive.decode(emissive[i * gbuffer_samples]))
+                        + self.ambient * albedo,
+                    |sum, light| {
+                        let Some((direction, radiance)) = light.incident(position) else {
+                            return sum;
+                        };
+                        let n_dot_l = Vec3::dot(normal, direction);
+                        if n_dot_l <= 0.0 {
+                            return sum;
+                        }
+
+                        let half = (direction + view).normalise();
+                        let n_dot_h = Vec3::dot(normal, half).max(0.0);
+
+                        sum + radiance * (albedo * n_dot_l + specular * n_dot_h.powf(shininess))
+                    },
+                );
+
+                pixel.fill(encode(Vec4::new(lit.x, lit.y, lit.z, 1.0), x, y));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffer::ops::{Fill, GetPixel},
+        mesh::{Mesh, Topology},
+        pipeline::Pipeline,
+        shader::{FragmentInput, FragmentOutput, FragmentShader},
+        testing::{identity, Positions},
+    };
+
+    /// A white, slightly shiny surface facing the camera.
+    struct Surface;
+
+    impl FragmentShader for Surface {
+        type Uniforms = ();
+        type Varyings = Vec4;
+
+        const WRITES_DEPTH: bool = false;
+
+        fn shade(&self, _: &FragmentInput<Vec4>, _: &()) -> Option<FragmentOutput> {
+            Some(
+                FragmentOutput::new(Vec4::new(1.0, 1.0, 1.0, 1.0)).with_attachments(&[
+                    encode_normal(Vec3::new(0.0, 0.0, 1.0)),
+                    Vec4::new(0.5, 0.5, 0.0, 1.0),
+                    Vec4::new(0.0, 0.0, 0.0, 1.0),
+                ]),
+            )
+        }
+    }
+
+    const BACKGROUND: u32 = 0xff0000ff;
+
+    /// Renders a sloped surface over `viewport` of a `width`×8 G-buffer and
+    /// lights it, with positions in world space equal to NDC.
+    fn render(width: usize, viewport: Option<Viewport>, tiling: Option<Tiling>) -> FrameBuffer {
+        let mesh = Mesh::new(
+            vec![
+                Vec4::new(-1.0, -1.0, -0.5, 1.0),
+                Vec4::new(3.0, -1.0, 0.5, 1.0),
+                Vec4::new(-1.0, 3.0, 0.0, 1.0),
+            ],
+            Topology::TriangleList,
+        );
+        let mut gbuffer = GBuffer::new(width, 8);
+        let mut geometry = Pipeline::new(Positions, Surface);
+        geometry.state.viewport = viewport;
+        geometry.draw(&mesh, &(), &mut gbuffer.target()).unwrap();
+
+        let mut pass = LightingPass::new(identity(), Vec3::new(0.0, 0.0, 5.0));
+        pass.viewport = viewport;
+        pass.tiling = tiling;
+        let lights = [Light::Point {
+            position: Vec3::new(0.25, -0.5, 0.5),
+            color:    Vec3::new(1.0, 1.0, 1.0),
+            range:    2.0,
+        }];
+
+        let mut lit = FrameBuffer::new(width, 8);
+        lit.fill(BACKGROUND);
+        pass.apply(&gbuffer, &lights, &mut lit).unwrap();
+        lit
+    }
+
+    #[test]
+    fn lighting_undoes_the_geometry_viewport() {
+        let full = render(8, None, None);
+        let offset = render(
+            16,
+            Some(Viewport {
+                min_depth: 0.5,
+                max_depth: 1.0,
+                ..Viewport::new(8.0, 0.0, 8.0, 8.0)
+            }),
+            None,
+        );
+
+        for y in 0..8 {
+            for x in 0..8 {
+                assert_eq!(offset.get_pixel(x, y).unwrap(), BACKGROUND);
+
+                let expected = full.get_pixel(x, y).unwrap();
+                let actual = offset.get_pixel(x + 8, y).unwrap();
+                assert_ne!(expected, BACKGROUND);
+                for shift in [0, 8, 16] {
+                    let channel = |p: u32| ((p >> shift) & 0xff) as i32;
+                    assert!(
+                        (channel(expected) - channel(actual)).abs() <= 1,
+                        "{x} {y}: {expected:x} {actual:x}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tiled_lighting_matches_serial() {
+        let serial = render(16, None, None);
+        let tiled = render(
+            16,
+            None,
+            Some(Tiling {
+                tile_size: 3,
+                threads:   3,
+            }),
+        );
+        assert_eq!(serial.to_array().unwrap(), tiled.to_array().unwrap());
+    }
+
+    #[test]
+    fn normals_round_trip_through_colors() {
+        let normal = Vec3::new(0.6, -0.8, 0.0);
+        let decoded = decode_normal(color::unpack(color::pack(encode_normal(normal))));
+        assert!((decoded - normal).magnitude() < 0.01, "{decoded:?}");
+    }
+}
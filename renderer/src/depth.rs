@@ -0,0 +1,413 @@
+use std::io::{self, Write};
+
+use math::{Vec3, Vec4};
+
+use crate::{
+    buffer::{
+        ops::{ToArray, ToArrayMut},
+        BufferError, DepthBuffer, FrameBuffer,
+    },
+    color,
+};
+
+/// Gradient mapping `[0, 1]` to display colors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Colormap {
+    /// Black to white.
+    #[default]
+    Grayscale,
+    /// Google's Turbo, a rainbow with even perceived steps, through its
+    /// polynomial approximation.
+    Turbo,
+    /// Matplotlib's viridis, dark blue to yellow, through a polynomial fit.
+    Viridis,
+}
+
+impl Colormap {
+    /// The sRGB-encoded color at `t`, which is clamped to `[0, 1]`.
+    pub fn map(self, t: f32) -> Vec3 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Colormap::Grayscale => Vec3::new(t, t, t),
+            Colormap::Turbo => {
+                let powers = [1.0, t, t * t, t * t * t, t.powi(4), t.powi(5)];
+                let channel = |c: [f32; 6]| {
+                    c.iter()
+                        .zip(powers)
+                        .map(|(c, p)| c * p)
+                        .sum::<f32>()
+                        .clamp(0.0, 1.0)
+                };
+
+                Vec3::new(
+                    channel([
+                        0.135_721_4,
+                        4.615_392_6,
+                        -42.660_324,
+                        132.131_08,
+                        -152.942_4,
+                        59.286_38,
+                    ]),
+                    channel([
+                        0.091_402_61,
+                        2.194_188_4,
+                        4.842_966_6,
+                        -14.185_033,
+                        4.277_299,
+                        2.829_566,
+                    ]),
+                    channel([
+                        0.106_673_3,
+                        12.641_946,
+                        -60.582_05,
+                        110.362_77,
+                        -89.903_11,
+                        27.348_25,
+                    ]),
+                )
+            }
+            Colormap::Viridis => {
+                const C: [[f32; 3]; 7] = [
+                    [0.277_727_33, 0.005_407_344_5, 0.334_099_8],
+                    [0.105_093_04, 1.404_613_5, 1.384_590_1],
+                    [-0.330_861_83, 0.214_847_56, 0.095_095_16],
+                    [-4.634_230_6, -5.799_101, -19.332_441],
+                    [6.228_27, 14.179_933, 56.690_55],
+                    [4.776_385, -13.745_145, -65.353_03],
+                    [-5.435_456, 4.645_852_6, 26.312_435],
+                ];
+                let channel = |i: usize| {
+                    C.iter()
+                        .rev()
+                        .fold(0.0, |sum, c| sum * t + c[i])
+                        .clamp(0.0, 1.0)
+                };
+
+                Vec3::new(channel(0), channel(1), channel(2))
+            }
+        }
+    }
+}
+
+/// How depths stored in a [`DepthBuffer`] relate to distance from the
+/// camera.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DepthProjection {
+    /// Shows stored depth as is, which is already linear for orthographic
+    /// projections.
+    #[default]
+    Raw,
+    /// Depth from a perspective projection with these clip plane
+    /// distances, shown as linear distance with `near` at 0 and `far` at 1.
+    Perspective { near: f32, far: f32 },
+}
+
+/// Turns a [`DepthBuffer`] into an image for inspection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthVisualization {
+    pub projection: DepthProjection,
+    pub colormap:   Colormap,
+    /// Stretches the range of depths present in the buffer over the whole
+    /// colormap, to make small differences visible.
+    pub normalize:  bool,
+    /// Shown where nothing was drawn.
+    pub background: u32,
+}
+
+impl DepthVisualization {
+    pub fn new(projection: DepthProjection, colormap: Colormap) -> Self {
+        Self {
+            projection,
+            colormap,
+            normalize: false,
+            background: 0xff000000,
+        }
+    }
+
+    /// Maps a stored depth to the `[0, 1]` the colormap is indexed with,
+    /// before normalization.
+    pub fn linearize(&self, depth: f32) -> f32 {
+        match self.projection {
+            DepthProjection::Raw => depth,
+            DepthProjection::Perspective { near, far } => {
+                // Undoes the viewport's mapping to `[0, 1]`, then the
+                // projection's to NDC.
+                let ndc = depth * 2.0 - 1.0;
+                let distance = 2.0 * near * far / (far + near - ndc * (far - near));
+
+                (distance - near) / (far - near)
+            }
+        }
+    }
+
+    /// Draws `depth` into every sample of `target`, which must be the same
+    /// size. Colors are written display-ready, whatever the target's `srgb`
+    /// flag. Multisampled depth is read from its first samples.
+    pub fn present(
+        &self,
+        depth: &DepthBuffer,
+        target: &mut FrameBuffer,
+    ) -> Result<(), BufferError> {
+        if depth.width != target.width || depth.height != target.height {
+            return Err(BufferError::SizeMismatch);
+        }
+
+        let values: Vec<Option<f32>> = depth
+            .to_array()?
+            .chunks_exact(depth.samples.count())
+            .map(|samples| samples[0].is_finite().then(|| self.linearize(samples[0])))
+            .collect();
+
+        let (offset, scale) = if self.normalize {
+            let (min, max) = values
+                .iter()
+                .flatten()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| {
+                    (min.min(v), max.max(v))
+                });
+            if max > min {
+                (min, 1.0 / (max - min))
+            } else {
+                (min, 0.0)
+            }
+        } else {
+            (0.0, 1.0)
+        };
+
+        let samples = target.samples.count();
+        for (pixel, value) in target.to_array_mut()?.chunks_exact_mut(samples).zip(values) {
+            pixel.fill(match value {
+                Some(value) => {
+                    let c = self.colormap.map((value - offset) * scale);
+                    color::pack(Vec4::new(c.x, c.y, c.z, 1.0))
+                }
+                None => self.background,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl DepthBuffer {
+    /// Stored depths of the first sample of every pixel, in row-major order.
+    fn first_samples(&self) -> impl Iterator<Item = f32> + '_ {
+        self.pixels()
+            .chunks_exact(self.samples.count())
+            .map(|samples| samples[0])
+    }
+
+    /// Writes the raw depths as a grayscale Portable Float Map. Cleared
+    /// pixels keep their infinite depth. Multisampled buffers are written
+    /// from their first samples.
+    pub fn write_pfm(&self, mut writer: impl Write) -> io::Result<()> {
+        // A negative scale marks the data as little-endian.
+        write!(writer, "Pf\n{} {}\n-1.0\n", self.width, self.height)?;
+
+        // Rows run bottom to top.
+        let depths: Vec<f32> = self.first_samples().collect();
+        let bytes: Vec<u8> = depths
+            .chunks_exact(self.width.max(1))
+            .rev()
+            .flatten()
+            .flat_map(|depth| depth.to_le_bytes())
+            .collect();
+
+        writer.write_all(&bytes)
+    }
+
+    /// Writes the raw depths as an uncompressed single-channel 32-bit float
+    /// TIFF, under the same rules as `write_pfm`.
+    pub fn write_tiff(&self, mut writer: impl Write) -> io::Result<()> {
+        const SHORT: u16 = 3;
+        const LONG: u16 = 4;
+
+        let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "image too large for TIFF");
+        let width = u32::try_from(self.width).map_err(|_| too_large())?;
+        let height = u32::try_from(self.height).map_err(|_| too_large())?;
+        let bytes = width
+            .checked_mul(height)
+            .and_then(|n| n.checked_mul(4))
+            .ok_or_else(too_large)?;
+
+        // Tag, type and value, in ascending tag order as TIFF requires.
+        let mut entries: [(u16, u16, u32); 10] = [
+            (256, LONG, width),  // ImageWidth
+            (257, LONG, height), // ImageLength
+            (258, SHORT, 32),    // BitsPerSample
+            (259, SHORT, 1),     // Compression: none
+            (262, SHORT, 1),     // PhotometricInterpretation: black is zero
+            (273, LONG, 0),      // StripOffsets, filled in below
+            (277, SHORT, 1),     // SamplesPerPixel
+            (278, LONG, height), // RowsPerStrip
+            (279, LONG, bytes),  // StripByteCounts
+            (339, SHORT, 3),     // SampleFormat: IEEE float
+        ];
+
+        // Header, then the directory, then the pixels on a 4-byte boundary.
+        let directory = 2 + entries.len() * 12 + 4;
+        let data = (8 + directory as u32).next_multiple_of(4);
+        entries[5].2 = data;
+        data.checked_add(bytes).ok_or_else(too_large)?;
+
+        writer.write_all(b"II*\0")?;
+        writer.write_all(&8u32.to_le_bytes())?;
+        writer.write_all(&(entries.len() as u16).to_le_bytes())?;
+        for (tag, kind, value) in entries {
+            writer.write_all(&tag.to_le_bytes())?;
+            writer.write_all(&kind.to_le_bytes())?;
+            writer.write_all(&1u32.to_le_bytes())?;
+            // Values shorter than the field are left-justified.
+            match kind {
+                SHORT => writer.write_all(&[(value as u16).to_le_bytes(), [0; 2]].concat())?,
+                _ => writer.write_all(&value.to_le_bytes())?,
+            }
+        }
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(&vec![0; data as usize - 8 - directory])?;
+
+        let bytes: Vec<u8> = self
+            .first_samples()
+            .flat_map(|depth| depth.to_le_bytes())
+            .collect();
+
+        writer.write_all(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::{
+        ops::{GetPixel, SetPixel},
+        Buffer,
+    };
+
+    /// Depths 0.25, 0.5 and 0.75 in three pixels of a 3×2 buffer, the rest
+    /// cleared.
+    fn depths() -> DepthBuffer {
+        let mut depth = DepthBuffer::new(3, 2);
+        depth.set_pixel(0, 0, 0.25).unwrap();
+        depth.set_pixel(1, 0, 0.5).unwrap();
+        depth.set_pixel(2, 1, 0.75).unwrap();
+        depth
+    }
+
+    #[test]
+    fn perspective_depth_linearizes_to_distance() {
+        let visualization = DepthVisualization::new(
+            DepthProjection::Perspective {
+                near: 1.0,
+                far:  10.0,
+            },
+            Colormap::Turbo,
+        );
+
+        // A point 5 units away, projected to NDC and then to `[0, 1]`.
+        let ndc = (11.0 - 20.0 / 5.0) / 9.0;
+        let stored = (ndc + 1.0) / 2.0;
+        assert!((visualization.linearize(stored) - 4.0 / 9.0).abs() < 1e-5);
+        assert!(visualization.linearize(0.0).abs() < 1e-5);
+        assert!((visualization.linearize(1.0) - 1.0).abs() < 1e-5);
+
+        let raw = DepthVisualization::new(DepthProjection::Raw, Colormap::Grayscale);
+        assert_eq!(raw.linearize(stored), stored);
+    }
+
+    #[test]
+    fn colormaps_clamp_and_run_between_their_end_colors() {
+        for colormap in [Colormap::Grayscale, Colormap::Turbo, Colormap::Viridis] {
+            assert_eq!(colormap.map(-1.0), colormap.map(0.0), "{colormap:?}");
+            assert_eq!(colormap.map(2.0), colormap.map(1.0), "{colormap:?}");
+        }
+
+        assert_eq!(Colormap::Grayscale.map(0.25), Vec3::new(0.25, 0.25, 0.25));
+
+        // Viridis runs dark blue to yellow, its green rising throughout.
+        let greens: Vec<f32> = (0..=10)
+            .map(|i| Colormap::Viridis.map(i as f32 / 10.0).y)
+            .collect();
+        assert!(greens.windows(2).all(|w| w[1] > w[0]), "{greens:?}");
+        let (start, end) = (Colormap::Viridis.map(0.0), Colormap::Viridis.map(1.0));
+        assert!(start.z > start.x && end.x > end.z);
+
+        // Turbo runs blue through green to red.
+        let middle = Colormap::Turbo.map(0.5);
+        assert!(middle.y > middle.x && middle.y > middle.z);
+        let end = Colormap::Turbo.map(1.0);
+        assert!(end.x > end.y && end.x > end.z);
+    }
+
+    #[test]
+    fn normalizing_stretches_the_depths_present() {
+        let depth = depths();
+        let mut visualization = DepthVisualization::new(DepthProjection::Raw, Colormap::Grayscale);
+        visualization.background = 0xff0000ff;
+        let mut target = FrameBuffer::new(3, 2);
+
+        visualization.present(&depth, &mut target).unwrap();
+        assert_eq!(target.get_pixel(0, 0).unwrap(), 0xff404040);
+        assert_eq!(target.get_pixel(0, 1).unwrap(), 0xff0000ff);
+
+        visualization.normalize = true;
+        visualization.present(&depth, &mut target).unwrap();
+        assert_eq!(target.get_pixel(0, 0).unwrap(), 0xff000000);
+        assert_eq!(target.get_pixel(1, 0).unwrap(), 0xff808080);
+        assert_eq!(target.get_pixel(2, 1).unwrap(), 0xffffffff);
+        assert_eq!(target.get_pixel(0, 1).unwrap(), 0xff0000ff);
+
+        assert_eq!(
+            visualization.present(&depth, &mut FrameBuffer::new(2, 2)),
+            Err(BufferError::SizeMismatch)
+        );
+    }
+
+    #[test]
+    fn exports_write_raw_depths_after_their_headers() {
+        let depth = depths();
+        let floats = |bytes: &[u8]| -> Vec<f32> {
+            bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect()
+        };
+
+        let mut pfm = Vec::new();
+        depth.write_pfm(&mut pfm).unwrap();
+        let header = b"Pf\n3 2\n-1.0\n";
+        assert!(pfm.starts_with(header));
+        // Bottom row first.
+        assert_eq!(
+            floats(&pfm[header.len()..]),
+            [f32::INFINITY, f32::INFINITY, 0.75, 0.25, 0.5, f32::INFINITY]
+        );
+
+        let mut tiff = Vec::new();
+        depth.write_tiff(&mut tiff).unwrap();
+        let u16_at = |at: usize| u16::from_le_bytes([tiff[at], tiff[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes(tiff[at..at + 4].try_into().unwrap());
+        assert_eq!(&tiff[..4], b"II*\0");
+        assert_eq!(u32_at(4), 8);
+
+        let entries = u16_at(8) as usize;
+        let entry = |tag: u16| {
+            (0..entries)
+                .map(|i| 10 + i * 12)
+                .find(|&at| u16_at(at) == tag)
+                .map(|at| u32_at(at + 8) & if u16_at(at + 2) == 3 { 0xffff } else { !0 })
+        };
+        assert_eq!(entry(256), Some(3));
+        assert_eq!(entry(257), Some(2));
+        assert_eq!(entry(339), Some(3));
+        assert_eq!(entry(279), Some(24));
+
+        let data = entry(273).unwrap() as usize;
+        assert_eq!(data % 4, 0);
+        assert_eq!(tiff.len(), data + 24);
+        assert_eq!(
+            floats(&tiff[data..]),
+            [0.25, 0.5, f32::INFINITY, f32::INFINITY, f32::INFINITY, 0.75]
+        );
+    }
+}
@@ -0,0 +1,607 @@
+use std::sync::OnceLock;
+
+use math::Vec4;
+
+use crate::{
+    buffer::{ops::ToArrayMut, BufferError, FrameBuffer},
+    color,
+};
+
+/// How colors are rounded to the palette a [`FrameBuffer`] stores, trading
+/// banding in smooth gradients for fine noise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Dither {
+    /// Rounds every pixel to the nearest color.
+    #[default]
+    None,
+    /// Offsets pixels by an 8×8 Bayer matrix, giving a regular cross-hatch.
+    Bayer,
+    /// Offsets pixels by a tiling blue noise mask, giving noise without
+    /// visible structure or clumps.
+    BlueNoise,
+    /// Spreads each pixel's rounding error over its unvisited neighbours,
+    /// scanning rows in alternating directions.
+    FloydSteinberg,
+    /// Like `FloydSteinberg`, but spreads only three quarters of the error
+    /// over a wider neighbourhood, keeping more contrast.
+    Atkinson,
+}
+
+/// Colors pixels may be rounded to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Palette {
+    /// This many evenly spaced levels per color channel, from 2 up to the
+    /// 256 a `FrameBuffer` holds.
+    Levels(u32),
+    /// Packed colors as the target stores them. Pixels take the color with
+    /// the nearest RGB, alpha included.
+    Colors(Vec<u32>),
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::Levels(256)
+    }
+}
+
+impl Palette {
+    /// The palette color nearest to `color`, packed and unpacked.
+    fn nearest(&self, color: Vec4) -> (u32, Vec4) {
+        match self {
+            Palette::Levels(levels) => {
+                let steps = steps(*levels);
+                let level = |c: f32| (c.clamp(0.0, 1.0) * steps).round() / steps;
+                let color = Vec4::new(level(color.x), level(color.y), level(color.z), color.w);
+                let packed = color::pack(color);
+
+                (packed, color::unpack(packed))
+            }
+            Palette::Colors(colors) => {
+                let distance = |packed: u32| {
+                    let d = color::unpack(packed) - color;
+                    d.x * d.x + d.y * d.y + d.z * d.z
+                };
+                let packed = colors
+                    .iter()
+                    .copied()
+                    .min_by(|&a, &b| distance(a).total_cmp(&distance(b)))
+                    .unwrap_or(0);
+
+                (packed, color::unpack(packed))
+            }
+        }
+    }
+
+    /// Roughly the distance between neighbouring colors, which ordered
+    /// dithering spreads its offsets over.
+    fn spacing(&self) -> f32 {
+        match self {
+            Palette::Levels(levels) => 1.0 / steps(*levels),
+            Palette::Colors(colors) => 1.0 / ((colors.len() as f32).cbrt() - 1.0).max(1.0),
+        }
+    }
+}
+
+/// Steps between `levels` evenly spaced levels, once clamped to what a
+/// `FrameBuffer` can hold.
+fn steps(levels: u32) -> f32 {
+    (levels.clamp(2, 256) - 1) as f32
+}
+
+const BAYER_SIZE: usize = 8;
+
+/// Threshold of Bayer matrix cell `(x, y)`, in `[-0.5, 0.5)`.
+fn bayer(x: usize, y: usize) -> f32 {
+    // Interleaves the bits of `x ^ y` and `y`, most significant last.
+    let (x, y) = (x % BAYER_SIZE, y % BAYER_SIZE);
+    let (a, b) = (x ^ y, y);
+    let rank = ((a & 1) << 5)
+        | ((b & 1) << 4)
+        | ((a & 2) << 2)
+        | ((b & 2) << 1)
+        | ((a & 4) >> 1)
+        | ((b & 4) >> 2);
+
+    (rank as f32 + 0.5) / (BAYER_SIZE * BAYER_SIZE) as f32 - 0.5
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// Ranks of a blue noise mask made with Ulichney's void-and-cluster method,
+/// in row-major order.
+fn blue_noise() -> &'static [u16] {
+    static MASK: OnceLock<Vec<u16>> = OnceLock::new();
+    MASK.get_or_init(|| {
+        const N: usize = BLUE_NOISE_SIZE;
+        const RADIUS: isize = 6;
+        const SIGMA: f32 = 1.5;
+
+        // Energy of a pixel is the Gaussian-weighted count of set pixels
+        // around it, wrapping at the edges so the mask tiles.
+        let weights: Vec<f32> = (-RADIUS..=RADIUS)
+            .flat_map(|dy| (-RADIUS..=RADIUS).map(move |dx| (dx, dy)))
+            .map(|(dx, dy)| (-((dx * dx + dy * dy) as f32) / (2.0 * SIGMA * SIGMA)).exp())
+            .collect();
+        let splat = |energy: &mut [f32], i: usize, sign: f32| {
+            let (x, y) = ((i % N) as isize, (i / N) as isize);
+            for (j, weight) in weights.iter().enumerate() {
+                let dx = (j as isize % (2 * RADIUS + 1)) - RADIUS;
+                let dy = (j as isize / (2 * RADIUS + 1)) - RADIUS;
+                let (px, py) = (
+                    (x + dx).rem_euclid(N as isize),
+                    (y + dy).rem_euclid(N as isize),
+                );
+                energy[py as usize * N + px as usize] += sign * weight;
+            }
+        };
+        // The tightest cluster among set pixels, or the largest void among
+        // unset ones.
+        let extreme = |set: &[bool], energy: &[f32], cluster: bool| {
+            (0..N * N)
+                .filter(|&i| set[i] == cluster)
+                .reduce(|a, b| {
+                    let better = if cluster {
+                        energy[b] > energy[a]
+                    } else {
+                        energy[b] < energy[a]
+                    };
+                    if better {
+                        b
+                    } else {
+                        a
+                    }
+                })
+                .unwrap()
+        };
+
+        // A fixed pseudo-random starting pattern, a tenth of the pixels set.
+        let mut set = vec![false; N * N];
+        let mut energy = vec![0.0; N * N];
+        let mut state = 0x2545_f491_u32;
+        let mut count = 0;
+        while count < N * N / 10 {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let i = state as usize % (N * N);
+            if !set[i] {
+                set[i] = true;
+                splat(&mut energy, i, 1.0);
+                count += 1;
+            }
+        }
+
+        // Move set pixels from clusters into voids until they're even.
+        loop {
+            let cluster = extreme(&set, &energy, true);
+            set[cluster] = false;
+            splat(&mut energy, cluster, -1.0);
+
+            let void = extreme(&set, &energy, false);
+            set[void] = true;
+            splat(&mut energy, void, 1.0);
+
+            if void == cluster {
+                break;
+            }
+        }
+
+        // Rank the starting pixels by removing the tightest clusters, then
+        // the rest by filling the largest voids.
+        let mut ranks = vec![0; N * N];
+        let (mut removing, mut removing_energy) = (set.clone(), energy.clone());
+        for rank in (0..count).rev() {
+            let cluster = extreme(&removing, &removing_energy, true);
+            removing[cluster] = false;
+            splat(&mut removing_energy, cluster, -1.0);
+            ranks[cluster] = rank as u16;
+        }
+        for rank in count..N * N {
+            let void = extreme(&set, &energy, false);
+            set[void] = true;
+            splat(&mut energy, void, 1.0);
+            ranks[void] = rank as u16;
+        }
+
+        ranks
+    })
+}
+
+/// Threshold of blue noise mask texel `(x, y)`, in `[-0.5, 0.5)`.
+fn blue_noise_threshold(x: usize, y: usize) -> f32 {
+    let rank = blue_noise()[(y % BLUE_NOISE_SIZE) * BLUE_NOISE_SIZE + x % BLUE_NOISE_SIZE];
+    (rank as f32 + 0.5) / (BLUE_NOISE_SIZE * BLUE_NOISE_SIZE) as f32 - 0.5
+}
+
+impl Dither {
+    /// Packs linear `color` for pixel `(x, y)` of a [`FrameBuffer`], sRGB
+    /// encoded when `srgb` is set. Converting one pixel at a time leaves no
+    /// neighbours to spread error over, so error diffusion uses blue noise
+    /// instead.
+    pub(crate) fn encode(self, color: Vec4, srgb: bool, x: usize, y: usize) -> u32 {
+        let threshold = match self {
+            Dither::None => return color::encode(color, srgb),
+            Dither::Bayer => bayer(x, y),
+            Dither::BlueNoise | Dither::FloydSteinberg | Dither::Atkinson => {
+                blue_noise_threshold(x, y)
+            }
+        };
+
+        let channel = |c: f32| {
+            let c = c.clamp(0.0, 1.0);
+            let encoded = if srgb { color::linear_to_srgb(c) } else { c };
+            encoded + threshold / 255.0
+        };
+        color::pack(Vec4::new(
+            channel(color.x),
+            channel(color.y),
+            channel(color.z),
+            color.w,
+        ))
+    }
+}
+
+// Error diffusion weights as `(dx, dy, weight)`, for scanning left to right.
+const FLOYD_STEINBERG: &[(isize, usize, f32)] = &[
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0),
+    (0, 1, 5.0 / 16.0),
+    (1, 1, 1.0 / 16.0),
+];
+const ATKINSON: &[(isize, usize, f32)] = &[
+    (1, 0, 1.0 / 8.0),
+    (2, 0, 1.0 / 8.0),
+    (-1, 1, 1.0 / 8.0),
+    (0, 1, 1.0 / 8.0),
+    (1, 1, 1.0 / 8.0),
+    (0, 2, 1.0 / 8.0),
+];
+
+/// Converts float colors to the packed pixels of a [`FrameBuffer`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Quantizer {
+    pub dither:  Dither,
+    pub palette: Palette,
+}
+
+impl Quantizer {
+    pub fn new(dither: Dither) -> Self {
+        Self {
+            dither,
+            palette: Palette::default(),
+        }
+    }
+
+    /// Writes linear `colors`, one per pixel in row-major order, into every
+    /// sample of `target`, encoded as its `srgb` flag says.
+    pub fn write(&self, colors: &[Vec4], target: &mut FrameBuffer) -> Result<(), BufferError> {
+        let encoded: Vec<Vec4> = if target.srgb {
+            colors
+                .iter()
+                .map(|c| {
+                    let encode = |c: f32| color::linear_to_srgb(c.clamp(0.0, 1.0));
+                    Vec4::new(encode(c.x), encode(c.y), encode(c.z), c.w)
+                })
+                .collect()
+        } else {
+            colors.to_vec()
+        };
+
+        self.write_encoded(&encoded, target)
+    }
+
+    /// Like [`write`](Self::write), but with colors already in the target's
+    /// encoding.
+    fn write_encoded(&self, colors: &[Vec4], target: &mut FrameBuffer) -> Result<(), BufferError> {
+        if colors.len() != target.width * target.height {
+            return Err(BufferError::SizeMismatch);
+        }
+
+        let packed = self.quantize(colors, target.width);
+        let samples = target.samples.count();
+        for (pixel, color) in target.to_array_mut()?.chunks_exact_mut(samples).zip(packed) {
+            pixel.fill(color);
+        }
+
+        Ok(())
+    }
+
+    /// Rounds each of `colors`, rows of `width` pixels, to the palette.
+    fn quantize(&self, colors: &[Vec4], width: usize) -> Vec<u32> {
+        let ordered = |threshold: fn(usize, usize) -> f32| {
+            let spacing = self.palette.spacing();
+            colors
+                .iter()
+                .enumerate()
+                .map(|(i, &color)| {
+                    let offset = threshold(i % width, i / width) * spacing;
+                    let offset = Vec4::new(offset, offset, offset, 0.0);
+                    self.palette.nearest(color + offset).0
+                })
+                .collect()
+        };
+
+        match self.dither {
+            Dither::None => colors.iter().map(|&c| self.palette.nearest(c).0).collect(),
+            Dither::Bayer => ordered(bayer),
+            Dither::BlueNoise => ordered(blue_noise_threshold),
+            Dither::FloydSteinberg => self.diffuse(colors, width, FLOYD_STEINBERG),
+            Dither::Atkinson => self.diffuse(colors, width, ATKINSON),
+        }
+    }
+
+    /// Error diffusion over serpentine rows, mirroring `kernel` on rows
+    /// scanned right to left.
+    fn diffuse(&self, colors: &[Vec4], width: usize, kernel: &[(isize, usize, f32)]) -> Vec<u32> {
+        let mut packed = vec![0; colors.len()];
+        if width == 0 {
+            return packed;
+        }
+
+        let height = colors.len() / width;
+        let mut working = colors.to_vec();
+
+        for y in 0..height {
+            let reverse = y % 2 == 1;
+            for step in 0..width {
+                let x = if reverse { width - 1 - step } else { step };
+                let i = y * width + x;
+
+                let wanted = working[i];
+                let wanted = Vec4::new(
+                    wanted.x.clamp(0.0, 1.0),
+                    wanted.y.clamp(0.0, 1.0),
+                    wanted.z.clamp(0.0, 1.0),
+                    wanted.w,
+                );
+                let (color, got) = self.palette.nearest(wanted);
+                packed[i] = color;
+
+                let error = wanted - got;
+                let error = Vec4::new(error.x, error.y, error.z, 0.0);
+                for &(dx, dy, weight) in kernel {
+                    let nx = x as isize + if reverse { -dx } else { dx };
+                    let ny = y + dy;
+                    if nx >= 0 && (nx as usize) < width && ny < height {
+                        let j = ny * width + nx as usize;
+                        working[j] = working[j] + error * weight;
+                    }
+                }
+            }
+        }
+
+        packed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use math::Vec3;
+
+    use super::*;
+    use crate::{
+        blend::BlendState,
+        buffer::{
+            ops::{BlendPixel, Fill, ToArray},
+            Buffer, DepthBuffer, HdrBuffer,
+        },
+        deferred::{GBuffer, LightingPass},
+        msaa::SampleCount,
+        pipeline::{Pipeline, RenderTarget},
+        resample::Kernel,
+        shader::{FragmentInput, FragmentOutput, FragmentShader},
+        testing::{fullscreen, identity, Positions},
+        tile::Tiling,
+        tonemap::{ToneMapping, Tonemapper},
+    };
+
+    const DITHERS: [Dither; 4] = [
+        Dither::Bayer,
+        Dither::BlueNoise,
+        Dither::FloydSteinberg,
+        Dither::Atkinson,
+    ];
+
+    fn grey(value: f32) -> Vec4 {
+        Vec4::new(value, value, value, 1.0)
+    }
+
+    /// The average blue channel of `buffer`, in 8-bit steps.
+    fn mean(buffer: &FrameBuffer) -> f32 {
+        let pixels = buffer.to_array().unwrap();
+        pixels.iter().map(|&p| (p & 0xff) as f32).sum::<f32>() / pixels.len() as f32
+    }
+
+    #[test]
+    fn dithering_keeps_the_average_of_flat_colors() {
+        for dither in DITHERS {
+            for value in [0.25, 0.5, 0.8] {
+                let mut quantizer = Quantizer::new(dither);
+                quantizer.palette = Palette::Levels(2);
+                let mut target = FrameBuffer::new(64, 64);
+                quantizer
+                    .write(&[grey(value); 64 * 64], &mut target)
+                    .unwrap();
+
+                let tolerance = if dither == Dither::Atkinson {
+                    0.1
+                } else {
+                    0.02
+                };
+                let on = mean(&target) / 255.0;
+                assert!((on - value).abs() < tolerance, "{dither:?} {value} {on}");
+            }
+        }
+
+        // Without dithering every pixel rounds the same way.
+        let mut quantizer = Quantizer::new(Dither::None);
+        quantizer.palette = Palette::Levels(2);
+        let mut target = FrameBuffer::new(8, 8);
+        quantizer.write(&[grey(0.4); 64], &mut target).unwrap();
+        assert!(target.to_array().unwrap().iter().all(|&p| p == 0xff000000));
+    }
+
+    #[test]
+    fn palettes_restrict_the_colors_written() {
+        let colors = vec![0xff000000, 0xffff0000, 0xff0000ff];
+        let mut quantizer = Quantizer::new(Dither::FloydSteinberg);
+        quantizer.palette = Palette::Colors(colors.clone());
+        let mut target = FrameBuffer::new(8, 8);
+        target.srgb = true;
+
+        quantizer
+            .write(&[Vec4::new(0.2, 0.0, 0.2, 1.0); 64], &mut target)
+            .unwrap();
+        assert!(target
+            .to_array()
+            .unwrap()
+            .iter()
+            .all(|p| colors.contains(p)));
+        assert!(target.to_array().unwrap().contains(&0xffff0000));
+
+        assert_eq!(
+            quantizer.write(&[Vec4::ZERO; 3], &mut target),
+            Err(BufferError::SizeMismatch)
+        );
+    }
+
+    #[test]
+    fn level_counts_clamp_to_what_a_buffer_holds() {
+        let gradient: Vec<Vec4> = (0..32 * 8).map(|i| grey((i % 32) as f32 / 31.0)).collect();
+        let write = |levels: u32| {
+            let mut quantizer = Quantizer::new(Dither::Bayer);
+            quantizer.palette = Palette::Levels(levels);
+            let mut target = FrameBuffer::new(32, 8);
+            quantizer.write(&gradient, &mut target).unwrap();
+            target.to_array().unwrap().to_vec()
+        };
+
+        assert_eq!(write(0), write(2));
+        assert_eq!(write(1), write(2));
+        assert_eq!(write(1000), write(256));
+    }
+
+    struct Flat(Vec4);
+
+    impl FragmentShader for Flat {
+        type Uniforms = ();
+        type Varyings = Vec4;
+
+        const WRITES_DEPTH: bool = false;
+
+        fn shade(&self, _: &FragmentInput<Vec4>, _: &()) -> Option<FragmentOutput> {
+            Some(FragmentOutput::new(self.0))
+        }
+    }
+
+    #[test]
+    fn targets_dither_every_conversion_into_them() {
+        // Halfway between two 8-bit steps, so dithering splits the pixels
+        // between them and rounding alone picks the upper one.
+        let halfway = 100.5 / 255.0;
+        let (width, height) = (32, 32);
+        let mesh = fullscreen();
+
+        for dither in [
+            Dither::None,
+            Dither::Bayer,
+            Dither::BlueNoise,
+            Dither::Atkinson,
+        ] {
+            let target = || {
+                let mut target = FrameBuffer::new(width, height);
+                target.dither = dither;
+                target
+            };
+            let mut converted = Vec::new();
+
+            for tiling in [
+                None,
+                Some(Tiling {
+                    tile_size: 8,
+                    threads:   3,
+                }),
+            ] {
+                let mut color = target();
+                let mut depth = DepthBuffer::new(width, height);
+                let mut pipeline = Pipeline::new(Positions, Flat(grey(halfway)));
+                pipeline.set_tiling(tiling);
+                pipeline
+                    .draw(&mesh, &(), &mut RenderTarget::new(&mut color, &mut depth))
+                    .unwrap();
+                converted.push(color);
+            }
+            assert_eq!(
+                converted[0].to_array(),
+                converted[1].to_array(),
+                "{dither:?}"
+            );
+
+            // A white surface lit only by ambient light.
+            let mut gbuffer = GBuffer::new(width, height);
+            Pipeline::new(Positions, Flat(grey(1.0)))
+                .draw(&mesh, &(), &mut gbuffer.target())
+                .unwrap();
+            let mut pass = LightingPass::new(identity(), Vec3::ZERO);
+            pass.ambient = Vec3::new(halfway, halfway, halfway);
+            for tiling in [
+                None,
+                Some(Tiling {
+                    tile_size: 8,
+                    threads:   3,
+                }),
+            ] {
+                pass.tiling = tiling;
+                let mut lit = target();
+                pass.apply(&gbuffer, &[], &mut lit).unwrap();
+                converted.push(lit);
+            }
+
+            let mut blended = target();
+            for (x, y) in (0..height).flat_map(|y| (0..width).map(move |x| (x, y))) {
+                blended
+                    .blend_pixel(x, y, grey(halfway), &BlendState::REPLACE)
+                    .unwrap();
+            }
+            converted.push(blended);
+
+            // Samples, and below pixels, alternating between the two steps
+            // average to halfway.
+            let alternating = |buffer: &mut FrameBuffer| {
+                let pixels = buffer.to_array_mut().unwrap();
+                for (i, pixel) in pixels.iter_mut().enumerate() {
+                    *pixel = 0xff646464 + (i as u32 + i as u32 / 32 % 2) % 2 * 0x010101;
+                }
+            };
+            let mut multisampled = FrameBuffer::multisampled(width, height, SampleCount::X4);
+            alternating(&mut multisampled);
+            let mut resolved = target();
+            multisampled.resolve(&mut resolved).unwrap();
+            converted.push(resolved);
+
+            let mut checkered = target();
+            alternating(&mut checkered);
+            converted.push(checkered.resize(width / 2, height / 2, Kernel::Box));
+
+            // Undo the tone curve.
+            let mut hdr = HdrBuffer::new(width, height);
+            hdr.fill(grey(halfway / (1.0 - halfway)));
+            let mut presented = target();
+            ToneMapping::new(Tonemapper::Reinhard)
+                .present(&hdr, &mut presented)
+                .unwrap();
+            converted.push(presented);
+
+            for (i, buffer) in converted.iter().enumerate() {
+                let pixels = buffer.to_array().unwrap();
+                let upper = pixels.iter().filter(|&&p| p & 0xff == 101).count();
+                if dither == Dither::None {
+                    assert_eq!(upper, pixels.len(), "{dither:?} {i}");
+                } else {
+                    assert!((mean(buffer) - 100.5).abs() < 0.1, "{dither:?} {i}");
+                    assert!(pixels.iter().all(|&p| [100, 101].contains(&(p & 0xff))));
+                }
+            }
+        }
+    }
+}
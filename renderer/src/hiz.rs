@@ -0,0 +1,212 @@
+use crate::{msaa::SampleCount, pipeline::CompareFunction, raster::Bounds};
+
+/// Side length in pixels of a block in the finest level of the hierarchy.
+pub const BLOCK_SIZE: usize = 8;
+
+#[derive(Debug, Clone)]
+struct Level {
+    width:  usize,
+    height: usize,
+    min:    Vec<f32>,
+    max:    Vec<f32>,
+}
+
+/// A min/max depth pyramid over a `DepthBuffer`. Level 0 holds one entry per
+/// `BLOCK_SIZE` square block and every following level halves the resolution
+/// until a single block covers the buffer.
+#[derive(Debug, Clone)]
+pub struct DepthHierarchy {
+    levels: Vec<Level>,
+    /// Set when the depth buffer was mutated behind the hierarchy's back.
+    stale:  bool,
+}
+
+impl DepthHierarchy {
+    pub(crate) fn new(width: usize, height: usize, depth: f32) -> Self {
+        let mut levels = Vec::new();
+        let mut w = width.div_ceil(BLOCK_SIZE).max(1);
+        let mut h = height.div_ceil(BLOCK_SIZE).max(1);
+
+        loop {
+            levels.push(Level {
+                width:  w,
+                height: h,
+                min:    vec![depth; w * h],
+                max:    vec![depth; w * h],
+            });
+
+            if w == 1 && h == 1 {
+                break;
+            }
+            w = w.div_ceil(2);
+            h = h.div_ceil(2);
+        }
+
+        Self {
+            levels,
+            stale: false,
+        }
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Side length in pixels of a block at `level`.
+    pub fn block_size(level: usize) -> usize {
+        BLOCK_SIZE << level
+    }
+
+    /// The `(min, max)` depth of block `(x, y)` at `level`.
+    pub fn range(&self, level: usize, x: usize, y: usize) -> Option<(f32, f32)> {
+        let level = self.levels.get(level)?;
+        if x >= level.width || y >= level.height {
+            return None;
+        }
+
+        let i = y * level.width + x;
+        Some((level.min[i], level.max[i]))
+    }
+
+    pub(crate) fn fill(&mut self, depth: f32) {
+        for level in &mut self.levels {
+            level.min.fill(depth);
+            level.max.fill(depth);
+        }
+        self.stale = false;
+    }
+
+    pub(crate) fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    /// Rebuilds everything if the buffer was mutated externally.
+    pub(crate) fn sync(
+        &mut self,
+        depth: &[f32],
+        width: usize,
+        height: usize,
+        samples: SampleCount,
+    ) {
+        if self.stale {
+            self.update(depth, width, samples, Bounds::new(width, height));
+            self.stale = false;
+        }
+    }
+
+    /// Recomputes the blocks overlapping `bounds` from `depth`, a row-major
+    /// buffer `width` pixels wide, and propagates them up the pyramid. Blocks
+    /// span every sample of their pixels.
+    pub(crate) fn update(
+        &mut self,
+        depth: &[f32],
+        width: usize,
+        samples: SampleCount,
+        bounds: Bounds,
+    ) {
+        if bounds.is_empty() {
+            return;
+        }
+
+        let count = samples.count();
+        let stride = width * count;
+        let height = depth.len() / stride.max(1);
+        let (bx0, by0) = (bounds.min_x / BLOCK_SIZE, bounds.min_y / BLOCK_SIZE);
+        let (bx1, by1) = (
+            (bounds.max_x - 1) / BLOCK_SIZE,
+            (bounds.max_y - 1) / BLOCK_SIZE,
+        );
+
+        let base = &mut self.levels[0];
+        for by in by0..=by1.min(base.height - 1) {
+            for bx in bx0..=bx1.min(base.width - 1) {
+                let (mut min, mut max) = (f32::INFINITY, f32::NEG_INFINITY);
+
+                for y in by * BLOCK_SIZE..((by + 1) * BLOCK_SIZE).min(height) {
+                    let row = &depth[y * stride..(y + 1) * stride];
+                    let (x0, x1) = (bx * BLOCK_SIZE, ((bx + 1) * BLOCK_SIZE).min(width));
+                    for &d in &row[x0 * count..x1 * count] {
+                        min = min.min(d);
+                        max = max.max(d);
+                    }
+                }
+
+                let i = by * base.width + bx;
+                base.min[i] = min;
+                base.max[i] = max;
+            }
+        }
+
+        let (mut x0, mut y0, mut x1, mut y1) = (bx0, by0, bx1, by1);
+        for l in 1..self.levels.len() {
+            (x0, y0, x1, y1) = (x0 / 2, y0 / 2, x1 / 2, y1 / 2);
+            let (lower, upper) = self.levels.split_at_mut(l);
+            let (child, parent) = (&lower[l - 1], &mut upper[0]);
+
+            for y in y0..=y1.min(parent.height - 1) {
+                for x in x0..=x1.min(parent.width - 1) {
+                    let (mut min, mut max) = (f32::INFINITY, f32::NEG_INFINITY);
+
+                    for cy in y * 2..(y * 2 + 2).min(child.height) {
+                        for cx in x * 2..(x * 2 + 2).min(child.width) {
+                            min = min.min(child.min[cy * child.width + cx]);
+                            max = max.max(child.max[cy * child.width + cx]);
+                        }
+                    }
+
+                    parent.min[y * parent.width + x] = min;
+                    parent.max[y * parent.width + x] = max;
+                }
+            }
+        }
+    }
+
+    /// Conservative `(min, max)` depth over `bounds`, read from the coarsest
+    /// level at which the region spans at most 2×2 blocks.
+    pub(crate) fn region_range(&self, bounds: Bounds) -> (f32, f32) {
+        let mut level = 0;
+        while level + 1 < self.levels.len() {
+            let size = Self::block_size(level);
+            let span_x = (bounds.max_x - 1) / size - bounds.min_x / size;
+            let span_y = (bounds.max_y - 1) / size - bounds.min_y / size;
+            if span_x <= 1 && span_y <= 1 {
+                break;
+            }
+            level += 1;
+        }
+
+        let size = Self::block_size(level);
+        let l = &self.levels[level];
+        let (mut min, mut max) = (f32::INFINITY, f32::NEG_INFINITY);
+
+        for y in bounds.min_y / size..=((bounds.max_y - 1) / size).min(l.height - 1) {
+            for x in bounds.min_x / size..=((bounds.max_x - 1) / size).min(l.width - 1) {
+                min = min.min(l.min[y * l.width + x]);
+                max = max.max(l.max[y * l.width + x]);
+            }
+        }
+
+        (min, max)
+    }
+
+    /// Returns whether every fragment with depth in `[z_min, z_max]` inside
+    /// `bounds` is guaranteed to fail `compare`.
+    pub(crate) fn occludes(
+        &self,
+        compare: CompareFunction,
+        bounds: Bounds,
+        z_min: f32,
+        z_max: f32,
+    ) -> bool {
+        let (min, max) = self.region_range(bounds);
+
+        // Interpolated depth can land a few ulps outside the vertex range.
+        let slack = |z: f32| z.abs() * f32::EPSILON * 4.0;
+
+        match compare {
+            CompareFunction::Less | CompareFunction::LessEqual => z_min - slack(z_min) > max,
+            CompareFunction::Greater | CompareFunction::GreaterEqual => z_max + slack(z_max) < min,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::io::{self, BufRead, Read, Write};
+
+use crate::buffer::{
+    ops::{ToArray, ToArrayMut},
+    Buffer, FrameBuffer,
+};
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the next whitespace-separated header field, skipping `#` comments.
+fn header_field(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut field = String::new();
+    let mut byte = [0];
+
+    loop {
+        reader.read_exact(&mut byte)?;
+        match byte[0] {
+            b'#' if field.is_empty() => {
+                let mut comment = Vec::new();
+                reader.read_until(b'\n', &mut comment)?;
+            }
+            b' ' | b'\t' | b'\r' | b'\n' if field.is_empty() => {}
+            b' ' | b'\t' | b'\r' | b'\n' => return Ok(field),
+            c => field.push(c as char),
+        }
+    }
+}
+
+impl FrameBuffer {
+    /// Writes the color channels as a binary PPM, bytes as stored, so sRGB
+    /// buffers come out ready to view. Alpha is dropped. Multisampled
+    /// buffers are written from their first samples.
+    pub fn write_ppm(&self, mut writer: impl Write) -> io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+
+        let bytes: Vec<u8> = self
+            .to_array()
+            .unwrap()
+            .chunks_exact(self.samples.count())
+            .flat_map(|pixel| {
+                let [_, r, g, b] = pixel[0].to_be_bytes();
+                [r, g, b]
+            })
+            .collect();
+
+        writer.write_all(&bytes)
+    }
+
+    /// Reads a binary PPM with 8-bit channels into an opaque, single-sampled
+    /// buffer.
+    pub fn read_ppm(mut reader: impl BufRead) -> io::Result<FrameBuffer> {
+        if header_field(&mut reader)? != "P6" {
+            return Err(invalid("not a binary PPM"));
+        }
+
+        let mut number = || -> io::Result<usize> {
+            header_field(&mut reader)?
+                .parse()
+                .map_err(|_| invalid("malformed PPM header"))
+        };
+        let (width, height, max) = (number()?, number()?, number()?);
+        if max != 255 {
+            return Err(invalid("only 8-bit PPMs are supported"));
+        }
+
+        let len = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .ok_or_else(|| invalid("PPM dimensions too large"))?;
+
+        // Reading rather than allocating up front keeps a header claiming a
+        // huge image from allocating more than the data that follows.
+        let mut bytes = Vec::new();
+        reader.take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() < len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "PPM pixel data is truncated",
+            ));
+        }
+
+        let mut image = FrameBuffer::new(width, height);
+        for (pixel, rgb) in image
+            .to_array_mut()
+            .unwrap()
+            .iter_mut()
+            .zip(bytes.chunks_exact(3))
+        {
+            *pixel = u32::from_be_bytes([0xff, rgb[0], rgb[1], rgb[2]]);
+        }
+
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ppms_round_trip() {
+        let mut image = FrameBuffer::new(5, 3);
+        for (i, pixel) in image.to_array_mut().unwrap().iter_mut().enumerate() {
+            *pixel = 0xff000000 | (i as u32 * 0x0a1b2c);
+        }
+
+        let mut bytes = Vec::new();
+        image.write_ppm(&mut bytes).unwrap();
+        let read = FrameBuffer::read_ppm(bytes.as_slice()).unwrap();
+
+        assert_eq!((read.width, read.height), (5, 3));
+        assert_eq!(read.to_array().unwrap(), image.to_array().unwrap());
+
+        let commented = b"P6\n# made by hand\n1 1\n255\n\x01\x02\x03";
+        let read = FrameBuffer::read_ppm(&commented[..]).unwrap();
+        assert_eq!(read.to_array().unwrap(), &[0xff010203]);
+    }
+
+    #[test]
+    fn malformed_ppms_are_rejected() {
+        let error = |bytes: &[u8]| FrameBuffer::read_ppm(bytes).unwrap_err().kind();
+
+        assert_eq!(error(b"P3\n1 1\n255\n1 2 3"), io::ErrorKind::InvalidData);
+        assert_eq!(error(b"P6\n1 x\n255\n"), io::ErrorKind::InvalidData);
+        assert_eq!(error(b"P6\n1 1\n65535\n"), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error(b"P6\n2 1\n255\n\x01\x02\x03"),
+            io::ErrorKind::UnexpectedEof
+        );
+
+        let huge = format!("P6\n{} {}\n255\n", usize::MAX / 2, 3);
+        assert_eq!(error(huge.as_bytes()), io::ErrorKind::InvalidData);
+
+        // A header claiming a huge image fails on the missing data rather
+        // than allocating it.
+        let large = format!("P6\n{} {}\n255\n", 1 << 20, 1 << 20);
+        assert_eq!(error(large.as_bytes()), io::ErrorKind::UnexpectedEof);
+    }
+}
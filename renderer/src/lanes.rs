@@ -0,0 +1,73 @@
+// Values for the four lanes of a 2×2 quad, with element-wise arithmetic.
+// They are plain arrays; whether the operations get vectorized is up to the
+// compiler.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct F32Lanes(pub [f32; 4]);
+
+impl F32Lanes {
+    #[inline]
+    pub fn splat(v: f32) -> Self {
+        Self([v; 4])
+    }
+}
+
+impl std::ops::Add<F32Lanes> for F32Lanes {
+    type Output = F32Lanes;
+
+    #[inline]
+    fn add(self, b: F32Lanes) -> F32Lanes {
+        F32Lanes(std::array::from_fn(|i| self.0[i] + b.0[i]))
+    }
+}
+
+impl std::ops::Mul<F32Lanes> for F32Lanes {
+    type Output = F32Lanes;
+
+    #[inline]
+    fn mul(self, b: F32Lanes) -> F32Lanes {
+        F32Lanes(std::array::from_fn(|i| self.0[i] * b.0[i]))
+    }
+}
+
+impl std::ops::Div<F32Lanes> for F32Lanes {
+    type Output = F32Lanes;
+
+    #[inline]
+    fn div(self, b: F32Lanes) -> F32Lanes {
+        F32Lanes(std::array::from_fn(|i| self.0[i] / b.0[i]))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct I64Lanes(pub [i64; 4]);
+
+impl I64Lanes {
+    #[inline]
+    pub fn splat(v: i64) -> Self {
+        Self([v; 4])
+    }
+
+    /// Bit `i` of the result is set when lane `i` is `>= 0`.
+    #[inline]
+    pub fn non_negative_mask(self) -> u8 {
+        self.0
+            .iter()
+            .enumerate()
+            .fold(0, |mask, (i, &v)| mask | (((v >= 0) as u8) << i))
+    }
+
+    #[inline]
+    pub fn to_f32(self) -> F32Lanes {
+        F32Lanes(self.0.map(|v| v as f32))
+    }
+}
+
+impl std::ops::Add<I64Lanes> for I64Lanes {
+    type Output = I64Lanes;
+
+    #[inline]
+    fn add(self, b: I64Lanes) -> I64Lanes {
+        I64Lanes(std::array::from_fn(|i| self.0[i] + b.0[i]))
+    }
+}
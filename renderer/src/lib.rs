@@ -0,0 +1,28 @@
+pub mod blend;
+pub mod blit;
+pub mod buffer;
+pub mod color;
+pub mod compare;
+pub mod cubemap;
+pub mod debug;
+pub mod deferred;
+pub mod depth;
+pub mod dither;
+pub mod hiz;
+pub mod image;
+pub mod mesh;
+pub mod msaa;
+pub mod pipeline;
+pub mod resample;
+pub mod shader;
+pub mod texture;
+pub mod tile;
+pub mod tonemap;
+pub mod view;
+
+mod clip;
+mod lanes;
+mod raster;
+#[cfg(test)]
+mod testing;
+mod workers;
@@ -1,6 +1,5 @@
 use window::Window;
 
-mod buffer;
 mod window;
 
 fn main() {
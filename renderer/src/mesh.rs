@@ -0,0 +1,169 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    PointList,
+    LineList,
+    LineStrip,
+    TriangleList,
+    TriangleStrip,
+    TriangleFan,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Indices {
+    pub fn len(&self) -> usize {
+        match self {
+            Indices::U16(indices) => indices.len(),
+            Indices::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, i: usize) -> Option<u32> {
+        match self {
+            Indices::U16(indices) => indices.get(i).map(|&index| index as u32),
+            Indices::U32(indices) => indices.get(i).copied(),
+        }
+    }
+
+    pub fn max(&self) -> Option<u32> {
+        match self {
+            Indices::U16(indices) => indices.iter().max().map(|&index| index as u32),
+            Indices::U32(indices) => indices.iter().max().copied(),
+        }
+    }
+}
+
+impl From<Vec<u16>> for Indices {
+    fn from(indices: Vec<u16>) -> Self {
+        Indices::U16(indices)
+    }
+}
+
+impl From<Vec<u32>> for Indices {
+    fn from(indices: Vec<u32>) -> Self {
+        Indices::U32(indices)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mesh<V> {
+    pub vertices: Vec<V>,
+    pub indices:  Option<Indices>,
+    pub topology: Topology,
+}
+
+impl<V> Mesh<V> {
+    pub fn new(vertices: Vec<V>, topology: Topology) -> Self {
+        Self {
+            vertices,
+            indices: None,
+            topology,
+        }
+    }
+
+    pub fn indexed(vertices: Vec<V>, indices: impl Into<Indices>, topology: Topology) -> Self {
+        Self {
+            vertices,
+            indices: Some(indices.into()),
+            topology,
+        }
+    }
+}
+
+/// A primitive assembled from positions in the vertex (or index) stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Primitive {
+    Point(usize),
+    Line(usize, usize),
+    Triangle(usize, usize, usize),
+}
+
+impl Topology {
+    /// Whether every element of the stream belongs to exactly one primitive.
+    pub(crate) fn is_list(self) -> bool {
+        matches!(
+            self,
+            Topology::PointList | Topology::LineList | Topology::TriangleList
+        )
+    }
+
+    /// Assembles `count` stream elements into primitives. Incomplete trailing
+    /// primitives are dropped.
+    pub(crate) fn primitives(self, count: usize) -> Box<dyn Iterator<Item = Primitive>> {
+        match self {
+            Topology::PointList => Box::new((0..count).map(Primitive::Point)),
+            Topology::LineList => {
+                Box::new((0..count / 2).map(|i| Primitive::Line(i * 2, i * 2 + 1)))
+            }
+            Topology::LineStrip => Box::new((1..count.max(1)).map(|i| Primitive::Line(i - 1, i))),
+            Topology::TriangleList => {
+                Box::new((0..count / 3).map(|i| Primitive::Triangle(i * 3, i * 3 + 1, i * 3 + 2)))
+            }
+            // Every other strip triangle is flipped so the whole strip keeps
+            // the winding of its first triangle.
+            Topology::TriangleStrip => Box::new((2..count.max(2)).map(|i| {
+                if i % 2 == 0 {
+                    Primitive::Triangle(i - 2, i - 1, i)
+                } else {
+                    Primitive::Triangle(i - 1, i - 2, i)
+                }
+            })),
+            Topology::TriangleFan => {
+                Box::new((2..count.max(2)).map(|i| Primitive::Triangle(0, i - 1, i)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topologies_assemble_primitives() {
+        let triangles =
+            |topology: Topology, count| -> Vec<Primitive> { topology.primitives(count).collect() };
+
+        assert_eq!(
+            triangles(Topology::TriangleList, 7),
+            [Primitive::Triangle(0, 1, 2), Primitive::Triangle(3, 4, 5)]
+        );
+        assert_eq!(
+            triangles(Topology::TriangleStrip, 5),
+            [
+                Primitive::Triangle(0, 1, 2),
+                Primitive::Triangle(2, 1, 3),
+                Primitive::Triangle(2, 3, 4),
+            ]
+        );
+        assert_eq!(
+            triangles(Topology::TriangleFan, 4),
+            [Primitive::Triangle(0, 1, 2), Primitive::Triangle(0, 2, 3)]
+        );
+        assert_eq!(
+            triangles(Topology::LineStrip, 3),
+            [Primitive::Line(0, 1), Primitive::Line(1, 2)]
+        );
+        assert_eq!(triangles(Topology::LineList, 3), [Primitive::Line(0, 1)]);
+        assert_eq!(triangles(Topology::TriangleStrip, 1), []);
+        assert_eq!(triangles(Topology::LineStrip, 0), []);
+    }
+
+    #[test]
+    fn indices_widen_to_u32() {
+        let indices = Indices::from(vec![3u16, 9, 1]);
+        assert_eq!(indices.len(), 3);
+        assert_eq!(indices.get(1), Some(9));
+        assert_eq!(indices.get(3), None);
+        assert_eq!(indices.max(), Some(9));
+        assert_eq!(Indices::from(Vec::<u32>::new()).max(), None);
+    }
+}
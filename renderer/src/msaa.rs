@@ -0,0 +1,74 @@
+/// Number of samples stored per pixel of a render target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SampleCount {
+    #[default]
+    X1,
+    X2,
+    X4,
+    X8,
+}
+
+// Standard sample positions, in sixteenths of a pixel from the pixel centre.
+const POSITIONS_1: [(i8, i8); 1] = [(0, 0)];
+const POSITIONS_2: [(i8, i8); 2] = [(4, 4), (-4, -4)];
+const POSITIONS_4: [(i8, i8); 4] = [(-2, -6), (6, -2), (-6, 2), (2, 6)];
+const POSITIONS_8: [(i8, i8); 8] = [
+    (1, -3),
+    (-1, 3),
+    (5, 1),
+    (-3, -5),
+    (-5, 5),
+    (-7, -1),
+    (3, 7),
+    (7, -7),
+];
+
+impl SampleCount {
+    pub fn count(self) -> usize {
+        self.positions().len()
+    }
+
+    /// Sample offsets from the pixel centre, in sixteenths of a pixel.
+    pub fn positions(self) -> &'static [(i8, i8)] {
+        match self {
+            SampleCount::X1 => &POSITIONS_1,
+            SampleCount::X2 => &POSITIONS_2,
+            SampleCount::X4 => &POSITIONS_4,
+            SampleCount::X8 => &POSITIONS_8,
+        }
+    }
+
+    /// Offset of sample `i` from the pixel centre, in pixels.
+    pub fn offset(self, i: usize) -> (f32, f32) {
+        let (x, y) = self.positions()[i];
+        (x as f32 / 16.0, y as f32 / 16.0)
+    }
+
+    /// Bit mask with one bit set per sample.
+    pub fn full_mask(self) -> u8 {
+        (((1u16) << self.count()) - 1) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_are_distinct_and_inside_the_pixel() {
+        for samples in [
+            SampleCount::X1,
+            SampleCount::X2,
+            SampleCount::X4,
+            SampleCount::X8,
+        ] {
+            let offsets: Vec<_> = (0..samples.count()).map(|i| samples.offset(i)).collect();
+
+            assert!(offsets.iter().all(|&(x, y)| x.abs() < 0.5 && y.abs() < 0.5));
+            for (i, a) in offsets.iter().enumerate() {
+                assert!(!offsets[i + 1..].contains(a), "{samples:?}");
+            }
+            assert_eq!(samples.full_mask().count_ones() as usize, samples.count());
+        }
+    }
+}
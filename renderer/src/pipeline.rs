@@ -0,0 +1,2402 @@
+use std::collections::HashSet;
+
+use math::{Vec2, Vec4};
+
+use crate::{
+    blend::BlendState,
+    buffer::{BufferError, DepthBuffer, FrameBuffer, HdrBuffer, Rect, StencilBuffer},
+    clip,
+    debug::{DebugFields, DebugMode},
+    hiz::{DepthHierarchy, BLOCK_SIZE},
+    mesh::{Indices, Mesh, Primitive},
+    msaa::SampleCount,
+    raster::{self, Bounds, Fragment, Quad, ScreenVertex, Triangle, LANE_X, LANE_Y},
+    shader::{
+        FragmentInput, FragmentOutput, FragmentShader, Varyings, VertexOutput, VertexShader,
+        MAX_COLOR_ATTACHMENTS,
+    },
+    tile::{self, Tile, Tiling},
+};
+
+/// Why a draw call was rejected. Nothing is rendered when it fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawError {
+    /// The render target's buffers don't fit together.
+    Target(BufferError),
+    /// `draw_indexed` was called on a mesh without an index buffer.
+    MissingIndices,
+    /// An index refers past the end of the mesh's vertices.
+    IndexOutOfBounds,
+}
+
+impl std::fmt::Display for DrawError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DrawError::Target(error) => write!(f, "invalid render target: {error}"),
+            DrawError::MissingIndices => write!(f, "mesh has no index buffer"),
+            DrawError::IndexOutOfBounds => write!(f, "index refers past the last vertex"),
+        }
+    }
+}
+
+impl std::error::Error for DrawError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DrawError::Target(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<BufferError> for DrawError {
+    fn from(error: BufferError) -> Self {
+        DrawError::Target(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareFunction {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl CompareFunction {
+    /// Returns whether `value` passes the comparison against `reference`.
+    pub fn test<T: PartialOrd>(self, value: T, reference: T) -> bool {
+        match self {
+            CompareFunction::Never => false,
+            CompareFunction::Less => value < reference,
+            CompareFunction::Equal => value == reference,
+            CompareFunction::LessEqual => value <= reference,
+            CompareFunction::Greater => value > reference,
+            CompareFunction::NotEqual => value != reference,
+            CompareFunction::GreaterEqual => value >= reference,
+            CompareFunction::Always => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthState {
+    pub compare: CompareFunction,
+    pub write:   bool,
+}
+
+impl Default for DepthState {
+    fn default() -> Self {
+        Self {
+            compare: CompareFunction::Less,
+            write:   true,
+        }
+    }
+}
+
+/// What happens to a stencil value after a stencil or depth test.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StencilOp {
+    #[default]
+    Keep,
+    Zero,
+    /// Writes the reference value.
+    Replace,
+    /// Increments, saturating at 255.
+    IncrementClamp,
+    /// Decrements, saturating at 0.
+    DecrementClamp,
+    Invert,
+    IncrementWrap,
+    DecrementWrap,
+}
+
+impl StencilOp {
+    fn apply(self, value: u8, reference: u8) -> u8 {
+        match self {
+            StencilOp::Keep => value,
+            StencilOp::Zero => 0,
+            StencilOp::Replace => reference,
+            StencilOp::IncrementClamp => value.saturating_add(1),
+            StencilOp::DecrementClamp => value.saturating_sub(1),
+            StencilOp::Invert => !value,
+            StencilOp::IncrementWrap => value.wrapping_add(1),
+            StencilOp::DecrementWrap => value.wrapping_sub(1),
+        }
+    }
+}
+
+/// Stencil test and operations for one triangle facing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StencilFaceState {
+    /// Compares the masked reference against the masked stored value.
+    pub compare:    CompareFunction,
+    /// Applied when the stencil test fails.
+    pub fail:       StencilOp,
+    /// Applied when the stencil test passes and the depth test fails.
+    pub depth_fail: StencilOp,
+    /// Applied when both tests pass.
+    pub pass:       StencilOp,
+}
+
+impl Default for StencilFaceState {
+    fn default() -> Self {
+        Self {
+            compare:    CompareFunction::Always,
+            fail:       StencilOp::Keep,
+            depth_fail: StencilOp::Keep,
+            pass:       StencilOp::Keep,
+        }
+    }
+}
+
+/// Only takes effect when the render target has a stencil buffer. Lines and
+/// points use the front face state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StencilState {
+    pub front:      StencilFaceState,
+    pub back:       StencilFaceState,
+    pub reference:  u8,
+    pub read_mask:  u8,
+    pub write_mask: u8,
+}
+
+impl Default for StencilState {
+    fn default() -> Self {
+        Self {
+            front:      StencilFaceState::default(),
+            back:       StencilFaceState::default(),
+            reference:  0,
+            read_mask:  0xff,
+            write_mask: 0xff,
+        }
+    }
+}
+
+impl StencilState {
+    fn face(&self, front_facing: bool) -> &StencilFaceState {
+        if front_facing {
+            &self.front
+        } else {
+            &self.back
+        }
+    }
+
+    fn test(&self, front_facing: bool, value: u8) -> bool {
+        self.face(front_facing)
+            .compare
+            .test(self.reference & self.read_mask, value & self.read_mask)
+    }
+
+    fn update(&self, op: StencilOp, value: u8) -> u8 {
+        let updated = op.apply(value, self.reference);
+        (value & !self.write_mask) | (updated & self.write_mask)
+    }
+
+    /// Whether fragments that fail either test leave the stencil untouched,
+    /// so skipping them early is unobservable.
+    fn keeps_failures(&self) -> bool {
+        [self.front, self.back]
+            .iter()
+            .all(|face| face.fail == StencilOp::Keep && face.depth_fail == StencilOp::Keep)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CullMode {
+    #[default]
+    None,
+    Front,
+    Back,
+}
+
+/// Winding order, as seen on screen, of triangles that face the viewer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrontFace {
+    #[default]
+    Ccw,
+    Cw,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PolygonMode {
+    #[default]
+    Fill,
+    /// Triangle edges are drawn with the line rasterizer.
+    Line,
+    /// Triangle vertices are drawn as points.
+    Point,
+    /// Triangles are filled and their edges drawn over them in
+    /// `overlay_color`, bypassing the fragment shader.
+    Overlay,
+}
+
+/// Units of point sizes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PointSizing {
+    /// Sizes are in pixels.
+    #[default]
+    Pixels,
+    /// Sizes are in world units, so points shrink with distance like the
+    /// rest of the scene. `scale` is the projection's vertical scale,
+    /// `1 / tan(fov_y / 2)` for a perspective projection.
+    World { scale: f32 },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PointShape {
+    /// Covers every pixel whose centre falls in the screen-aligned square.
+    #[default]
+    Square,
+    /// Covers the samples inside the disc inscribed in the square, so edges
+    /// are antialiased when multisampling.
+    Round,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RasterState {
+    pub cull_mode:         CullMode,
+    pub front_face:        FrontFace,
+    pub polygon_mode:      PolygonMode,
+    /// Pulled off the depth of edges drawn by `PolygonMode::Line` and
+    /// `PolygonMode::Overlay` so they win the depth test against the surface.
+    pub line_depth_offset: f32,
+    /// Also pulled off edge depths, times the steepest change in the
+    /// triangle's depth per pixel, since edge pixels can sit up to a pixel
+    /// away from the surface point they're tested against.
+    pub line_depth_slope:  f32,
+    /// Side length of rasterized points in `point_sizing` units, unless the
+    /// vertex shader sets one through [`Varyings::point_size`]. Points are
+    /// at least a pixel across.
+    pub point_size:        f32,
+    pub point_sizing:      PointSizing,
+    pub point_shape:       PointShape,
+    pub overlay_color:     Vec4,
+}
+
+impl Default for RasterState {
+    fn default() -> Self {
+        Self {
+            cull_mode:         CullMode::None,
+            front_face:        FrontFace::Ccw,
+            polygon_mode:      PolygonMode::Fill,
+            line_depth_offset: 1e-5,
+            line_depth_slope:  1.0,
+            point_size:        1.0,
+            point_sizing:      PointSizing::Pixels,
+            point_shape:       PointShape::Square,
+            overlay_color:     Vec4::new(1.0, 1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl RasterState {
+    fn is_front_facing(&self, ccw: bool) -> bool {
+        ccw == (self.front_face == FrontFace::Ccw)
+    }
+
+    fn culls(&self, front_facing: bool) -> bool {
+        match self.cull_mode {
+            CullMode::None => false,
+            CullMode::Front => front_facing,
+            CullMode::Back => !front_facing,
+        }
+    }
+}
+
+/// Maps normalized device coordinates onto a rectangle of the render target
+/// and a range of depth values. May extend past the target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x:         f32,
+    pub y:         f32,
+    pub width:     f32,
+    pub height:    f32,
+    pub min_depth: f32,
+    pub max_depth: f32,
+}
+
+impl Viewport {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PipelineState {
+    pub depth:    DepthState,
+    pub stencil:  StencilState,
+    pub raster:   RasterState,
+    /// Blending into each color attachment; fragments overwrite attachments
+    /// whose entry is unset.
+    pub blend:    [Option<BlendState>; MAX_COLOR_ATTACHMENTS],
+    /// Covers the whole render target when unset.
+    pub viewport: Option<Viewport>,
+    /// Pixels outside the rectangle are never written.
+    pub scissor:  Option<Rect>,
+    /// Draws debugging information in place of the fragment shader's output
+    /// when set.
+    pub debug:    Option<DebugMode>,
+}
+
+/// A buffer fragment colors are written to.
+pub enum ColorAttachment<'a> {
+    /// 8 bits per channel; colors are clamped to `[0, 1]`, and sRGB encoded
+    /// when the buffer's `srgb` flag is set.
+    Rgba8(&'a mut FrameBuffer),
+    /// Floating point, kept unclamped.
+    Hdr(&'a mut HdrBuffer),
+}
+
+impl ColorAttachment<'_> {
+    pub fn width(&self) -> usize {
+        match self {
+            ColorAttachment::Rgba8(buffer) => buffer.width,
+            ColorAttachment::Hdr(buffer) => buffer.width,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        match self {
+            ColorAttachment::Rgba8(buffer) => buffer.height,
+            ColorAttachment::Hdr(buffer) => buffer.height,
+        }
+    }
+
+    pub fn samples(&self) -> SampleCount {
+        match self {
+            ColorAttachment::Rgba8(buffer) => buffer.samples,
+            ColorAttachment::Hdr(buffer) => buffer.samples,
+        }
+    }
+}
+
+impl<'a> From<&'a mut FrameBuffer> for ColorAttachment<'a> {
+    fn from(buffer: &'a mut FrameBuffer) -> Self {
+        ColorAttachment::Rgba8(buffer)
+    }
+}
+
+impl<'a> From<&'a mut HdrBuffer> for ColorAttachment<'a> {
+    fn from(buffer: &'a mut HdrBuffer) -> Self {
+        ColorAttachment::Hdr(buffer)
+    }
+}
+
+/// The buffers a draw call renders into. All must have the same dimensions
+/// and sample count.
+pub struct RenderTarget<'a> {
+    /// Color attachments, written from `FragmentOutput::colors` in order.
+    /// May be empty to only render depth and stencil.
+    pub colors:  Vec<ColorAttachment<'a>>,
+    pub depth:   &'a mut DepthBuffer,
+    pub stencil: Option<&'a mut StencilBuffer>,
+}
+
+impl<'a> RenderTarget<'a> {
+    pub fn new(color: impl Into<ColorAttachment<'a>>, depth: &'a mut DepthBuffer) -> Self {
+        Self::with_attachments(vec![color.into()], depth)
+    }
+
+    pub fn with_stencil(
+        color: impl Into<ColorAttachment<'a>>,
+        depth: &'a mut DepthBuffer,
+        stencil: &'a mut StencilBuffer,
+    ) -> Self {
+        Self {
+            colors: vec![color.into()],
+            depth,
+            stencil: Some(stencil),
+        }
+    }
+
+    /// A target with several color attachments, such as a G-buffer.
+    pub fn with_attachments(colors: Vec<ColorAttachment<'a>>, depth: &'a mut DepthBuffer) -> Self {
+        Self {
+            colors,
+            depth,
+            stencil: None,
+        }
+    }
+
+    fn validate(&self) -> Result<(), BufferError> {
+        if self.colors.len() > MAX_COLOR_ATTACHMENTS {
+            return Err(BufferError::TooManyAttachments);
+        }
+
+        let (width, height) = (self.depth.width, self.depth.height);
+
+        let colors_match = self
+            .colors
+            .iter()
+            .all(|color| color.width() == width && color.height() == height);
+        let stencil_matches = self
+            .stencil
+            .as_ref()
+            .is_none_or(|stencil| stencil.width == width && stencil.height == height);
+
+        if !(colors_match && stencil_matches) {
+            return Err(BufferError::SizeMismatch);
+        }
+
+        let samples = self.depth.samples;
+        if self.colors.iter().any(|color| color.samples() != samples)
+            || self
+                .stencil
+                .as_ref()
+                .is_some_and(|stencil| stencil.samples != samples)
+        {
+            return Err(BufferError::SampleCountMismatch);
+        }
+
+        Ok(())
+    }
+}
+
+const VERTEX_CACHE_SIZE: usize = 32;
+
+/// FIFO post-transform cache so vertices shared between neighbouring
+/// primitives are only shaded once.
+struct VertexCache<V> {
+    entries: Vec<(u32, VertexOutput<V>)>,
+    next:    usize,
+}
+
+impl<V: Copy> VertexCache<V> {
+    fn new() -> Self {
+        Self {
+            entries: Vec::with_capacity(VERTEX_CACHE_SIZE),
+            next:    0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.next = 0;
+    }
+
+    fn get_or_shade<F>(&mut self, index: u32, shade: F) -> VertexOutput<V>
+    where
+        F: FnOnce() -> VertexOutput<V>,
+    {
+        if let Some((_, output)) = self.entries.iter().find(|(i, _)| *i == index) {
+            return *output;
+        }
+
+        let output = shade();
+        if self.entries.len() < VERTEX_CACHE_SIZE {
+            self.entries.push((index, output));
+        } else {
+            self.entries[self.next] = (index, output);
+            self.next = (self.next + 1) % VERTEX_CACHE_SIZE;
+        }
+
+        output
+    }
+}
+
+/// Geometry-stage state for one draw: clipping storage and the primitives
+/// recorded so far.
+struct Assembler<'a, V> {
+    viewport:   Viewport,
+    /// The part of the render target inside the scissor rectangle.
+    clip:       Bounds,
+    samples:    SampleCount,
+    polygon:    Vec<VertexOutput<V>>,
+    scratch:    Vec<VertexOutput<V>>,
+    /// Depth pyramid used to drop triangles that are hidden entirely.
+    occlusion:  Option<&'a DepthHierarchy>,
+    /// Index within the draw of the primitive being assembled.
+    primitive:  u32,
+    primitives: Vec<RasterPrimitive<V>>,
+    /// Clip-space endpoints of the triangle edges drawn for the current
+    /// instance, so edges shared by two triangles are only drawn once.
+    edges:      HashSet<[[u32; 4]; 2]>,
+}
+
+impl<'a, V> Assembler<'a, V> {
+    fn new(
+        viewport: Viewport,
+        clip: Bounds,
+        samples: SampleCount,
+        occlusion: Option<&'a DepthHierarchy>,
+    ) -> Self {
+        Self {
+            viewport,
+            clip,
+            samples,
+            polygon: Vec::new(),
+            scratch: Vec::new(),
+            occlusion,
+            primitive: 0,
+            primitives: Vec::new(),
+            edges: HashSet::new(),
+        }
+    }
+}
+
+/// A post-clip primitive in window coordinates, recorded by the geometry
+/// stage and replayed by the rasterizer. `id` is the index within the draw of
+/// the primitive it came from.
+enum RasterPrimitive<V> {
+    Triangle {
+        triangle:     Triangle<V>,
+        front_facing: bool,
+        id:           u32,
+    },
+    Line {
+        a:            ScreenVertex<V>,
+        b:            ScreenVertex<V>,
+        front_facing: bool,
+        depth_offset: f32,
+        /// Written as-is instead of running the fragment shader.
+        overlay:      Option<Vec4>,
+        id:           u32,
+    },
+    Point {
+        vertex:       ScreenVertex<V>,
+        /// In pixels.
+        size:         f32,
+        shape:        PointShape,
+        front_facing: bool,
+        id:           u32,
+    },
+}
+
+impl<V: Varyings> RasterPrimitive<V> {
+    fn bounds(&self) -> Bounds {
+        match self {
+            RasterPrimitive::Triangle { triangle, .. } => triangle.bounds(),
+            RasterPrimitive::Line { a, b, .. } => raster::line_bounds(a, b),
+            RasterPrimitive::Point { vertex, size, .. } => raster::point_bounds(vertex, *size),
+        }
+    }
+}
+
+/// Rasterizes a draw's primitives in parallel tiles. Only instantiated for
+/// shaders that can be shared between threads.
+type RenderTiled<F> = fn(
+    &Tiling,
+    &[RasterPrimitive<<F as FragmentShader>::Varyings>],
+    &mut RenderTarget,
+    &Backend<F>,
+);
+
+pub struct Pipeline<V, F: FragmentShader> {
+    pub vertex_shader:   V,
+    pub fragment_shader: F,
+    pub state:           PipelineState,
+    /// Where `state.debug` modes find normals and texture coordinates.
+    pub debug_fields:    DebugFields<F::Varyings>,
+    tiling:              Option<(Tiling, RenderTiled<F>)>,
+}
+
+impl<V, F> Pipeline<V, F>
+where
+    V: VertexShader,
+    V::Uniforms: Sync,
+    V::Varyings: Sync,
+    F: FragmentShader<Uniforms = V::Uniforms, Varyings = V::Varyings> + Sync,
+{
+    /// Rasterizes in parallel tiles when set, otherwise on the calling thread.
+    pub fn set_tiling(&mut self, tiling: Option<Tiling>) {
+        self.tiling = tiling.map(|tiling| (tiling, render_tiled::<F> as RenderTiled<F>));
+    }
+}
+
+impl<V, F> Pipeline<V, F>
+where
+    V: VertexShader,
+    F: FragmentShader<Uniforms = V::Uniforms, Varyings = V::Varyings>,
+{
+    pub fn new(vertex_shader: V, fragment_shader: F) -> Self {
+        Self {
+            vertex_shader,
+            fragment_shader,
+            state: PipelineState::default(),
+            debug_fields: DebugFields::new(),
+            tiling: None,
+        }
+    }
+
+    pub fn tiling(&self) -> Option<Tiling> {
+        self.tiling.map(|(tiling, _)| tiling)
+    }
+
+    /// Draws the vertices of `mesh` in order, ignoring its index buffer.
+    pub fn draw(
+        &self,
+        mesh: &Mesh<V::Vertex>,
+        uniforms: &V::Uniforms,
+        target: &mut RenderTarget,
+    ) -> Result<(), DrawError> {
+        self.submit(mesh, None, 1, uniforms, target)
+    }
+
+    /// Draws `mesh` through its index buffer.
+    pub fn draw_indexed(
+        &self,
+        mesh: &Mesh<V::Vertex>,
+        uniforms: &V::Uniforms,
+        target: &mut RenderTarget,
+    ) -> Result<(), DrawError> {
+        let indices = mesh.indices.as_ref().ok_or(DrawError::MissingIndices)?;
+        self.submit(mesh, Some(indices), 1, uniforms, target)
+    }
+
+    /// Draws `instances` copies of `mesh`, through its index buffer when it
+    /// has one. The vertex shader receives the instance index through
+    /// [`VertexShader::shade_instance`].
+    pub fn draw_instanced(
+        &self,
+        mesh: &Mesh<V::Vertex>,
+        instances: u32,
+        uniforms: &V::Uniforms,
+        target: &mut RenderTarget,
+    ) -> Result<(), DrawError> {
+        self.submit(mesh, mesh.indices.as_ref(), instances, uniforms, target)
+    }
+
+    fn submit(
+        &self,
+        mesh: &Mesh<V::Vertex>,
+        indices: Option<&Indices>,
+        instances: u32,
+        uniforms: &V::Uniforms,
+        target: &mut RenderTarget,
+    ) -> Result<(), DrawError> {
+        target.validate()?;
+
+        let count = match indices {
+            Some(indices) => {
+                if indices
+                    .max()
+                    .is_some_and(|max| max as usize >= mesh.vertices.len())
+                {
+                    return Err(DrawError::IndexOutOfBounds);
+                }
+                indices.len()
+            }
+            None => mesh.vertices.len(),
+        };
+
+        // Without indices, list vertices are never shared between primitives,
+        // so the cache could only miss.
+        let cached = indices.is_some() || !mesh.topology.is_list();
+        let (width, height) = (target.depth.width, target.depth.height);
+        let samples = target.depth.samples;
+        let viewport =
+            self.state
+                .viewport
+                .unwrap_or(Viewport::new(0.0, 0.0, width as f32, height as f32));
+
+        let frame = Bounds::new(width, height);
+        let clip = match self.state.scissor {
+            Some(scissor) => Bounds::from(scissor).intersect(&frame),
+            None => Some(frame),
+        };
+        let Some(clip) = clip else {
+            return Ok(());
+        };
+
+        // Fragments can be tested before shading when the shader can't move
+        // them and skipping a failing one leaves the stencil unchanged.
+        // Counting overdraw needs every fragment, hidden or not.
+        let early_tests = !F::WRITES_DEPTH
+            && (target.stencil.is_none() || self.state.stencil.keeps_failures())
+            && self.state.debug != Some(DebugMode::Overdraw);
+
+        // The hierarchy only works for ordering comparisons.
+        target.depth.sync_hierarchy();
+        let hierarchy = target.depth.hierarchy.take();
+        let occlusion = hierarchy.as_ref().filter(|_| {
+            early_tests
+                && matches!(
+                    self.state.depth.compare,
+                    CompareFunction::Less
+                        | CompareFunction::LessEqual
+                        | CompareFunction::Greater
+                        | CompareFunction::GreaterEqual
+                )
+        });
+
+        let mut cache = VertexCache::new();
+        let mut assembler = Assembler::new(viewport, clip, samples, occlusion);
+
+        for instance in 0..instances {
+            cache.clear();
+            assembler.edges.clear();
+
+            for primitive in mesh.topology.primitives(count) {
+                let mut fetch = |i: usize| {
+                    let index = indices.map_or(i as u32, |indices| indices.get(i).unwrap());
+                    let shade = || {
+                        self.vertex_shader.shade_instance(
+                            &mesh.vertices[index as usize],
+                            instance,
+                            uniforms,
+                        )
+                    };
+
+                    if cached {
+                        cache.get_or_shade(index, shade)
+                    } else {
+                        shade()
+                    }
+                };
+
+                match primitive {
+                    Primitive::Triangle(a, b, c) => {
+                        let triangle = [fetch(a), fetch(b), fetch(c)];
+                        self.assemble_triangle(triangle, &mut assembler);
+                    }
+                    Primitive::Line(a, b) => {
+                        let (a, b) = (fetch(a), fetch(b));
+                        self.assemble_line(a, b, true, 0.0, None, &mut assembler);
+                    }
+                    Primitive::Point(a) => {
+                        let a = fetch(a);
+                        self.assemble_point(a, true, &mut assembler);
+                    }
+                }
+
+                // Counted across instances, so each instance's primitives
+                // get their own ids.
+                assembler.primitive = assembler.primitive.wrapping_add(1);
+            }
+        }
+
+        let primitives = assembler.primitives;
+        let backend = Backend {
+            fragment_shader: &self.fragment_shader,
+            state: &self.state,
+            debug_fields: &self.debug_fields,
+            uniforms,
+            clip,
+            early_tests,
+            occlusion,
+        };
+
+        match &self.tiling {
+            Some((tiling, render_tiled)) => render_tiled(tiling, &primitives, target, &backend),
+            None => tile::render_serial(&primitives, target, |primitive, tile| {
+                backend.rasterize(primitive, tile)
+            }),
+        }
+
+        if let Some(mut hierarchy) = hierarchy {
+            let dirty = primitives
+                .iter()
+                .filter_map(|primitive| primitive.bounds().intersect(&clip))
+                .reduce(|a, b| a.union(&b));
+
+            if let Some(dirty) = dirty {
+                hierarchy.update(target.depth.pixels(), width, samples, dirty);
+            }
+            target.depth.hierarchy = Some(hierarchy);
+        }
+
+        Ok(())
+    }
+
+    fn assemble_triangle(
+        &self,
+        triangle: [VertexOutput<V::Varyings>; 3],
+        assembler: &mut Assembler<V::Varyings>,
+    ) {
+        let raster = &self.state.raster;
+        let viewport = assembler.viewport;
+
+        clip::clip_triangle(triangle, &mut assembler.polygon, &mut assembler.scratch);
+
+        let mut facing = None;
+        let mut slope = 0.0;
+
+        for i in 1..assembler.polygon.len().saturating_sub(1) {
+            let polygon = &assembler.polygon;
+            let screen = [&polygon[0], &polygon[i], &polygon[i + 1]]
+                .map(|v| ScreenVertex::from_clip(v, &viewport));
+
+            let Some(triangle) = Triangle::setup(screen, assembler.samples) else {
+                continue;
+            };
+
+            let front_facing = raster.is_front_facing(triangle.ccw);
+            facing = Some(front_facing);
+
+            let (dz_dx, dz_dy) = triangle.depth_slope();
+            slope = dz_dx.abs().max(dz_dy.abs());
+
+            if raster.culls(front_facing) {
+                return;
+            }
+
+            if !matches!(
+                raster.polygon_mode,
+                PolygonMode::Fill | PolygonMode::Overlay
+            ) {
+                continue;
+            }
+
+            let Some(bounds) = triangle.bounds().intersect(&assembler.clip) else {
+                continue;
+            };
+
+            if let Some(hierarchy) = assembler.occlusion {
+                let (z_min, z_max) = triangle.depth_range();
+                if hierarchy.occludes(self.state.depth.compare, bounds, z_min, z_max) {
+                    continue;
+                }
+            }
+
+            assembler.primitives.push(RasterPrimitive::Triangle {
+                triangle,
+                front_facing,
+                id: assembler.primitive,
+            });
+        }
+
+        // Culling and facing come from the filled triangle, so edges and
+        // vertices are only drawn for triangles that would have been filled.
+        let Some(front_facing) = facing else {
+            return;
+        };
+        let offset = raster.line_depth_offset + raster.line_depth_slope * slope;
+
+        match raster.polygon_mode {
+            PolygonMode::Fill => {}
+            PolygonMode::Line | PolygonMode::Overlay => {
+                let overlay =
+                    (raster.polygon_mode == PolygonMode::Overlay).then_some(raster.overlay_color);
+
+                for i in 0..3 {
+                    let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+
+                    let key = |v: &VertexOutput<V::Varyings>| {
+                        [v.position.x, v.position.y, v.position.z, v.position.w].map(f32::to_bits)
+                    };
+                    let (ka, kb) = (key(&a), key(&b));
+                    if !assembler.edges.insert([ka.min(kb), ka.max(kb)]) {
+                        continue;
+                    }
+
+                    self.assemble_line(a, b, front_facing, offset, overlay, assembler);
+                }
+            }
+            PolygonMode::Point => {
+                for vertex in triangle {
+                    self.assemble_point(vertex, front_facing, assembler);
+                }
+            }
+        }
+    }
+
+    fn assemble_line(
+        &self,
+        a: VertexOutput<V::Varyings>,
+        b: VertexOutput<V::Varyings>,
+        front_facing: bool,
+        depth_offset: f32,
+        overlay: Option<Vec4>,
+        assembler: &mut Assembler<V::Varyings>,
+    ) {
+        let viewport = assembler.viewport;
+
+        if let Some((a, b)) = clip::clip_line(a, b) {
+            assembler.primitives.push(RasterPrimitive::Line {
+                a: ScreenVertex::from_clip(&a, &viewport),
+                b: ScreenVertex::from_clip(&b, &viewport),
+                front_facing,
+                depth_offset,
+                overlay,
+                id: assembler.primitive,
+            });
+        }
+    }
+
+    fn assemble_point(
+        &self,
+        a: VertexOutput<V::Varyings>,
+        front_facing: bool,
+        assembler: &mut Assembler<V::Varyings>,
+    ) {
+        if clip::point_visible(a.position) {
+            let raster = &self.state.raster;
+            let size = a.varyings.point_size().unwrap_or(raster.point_size);
+            let size = match raster.point_sizing {
+                PointSizing::Pixels => size,
+                // Projected to NDC, then to pixels; visible points have a
+                // positive `w`.
+                PointSizing::World { scale } => {
+                    size * scale / a.position.w * assembler.viewport.height * 0.5
+                }
+            };
+
+            assembler.primitives.push(RasterPrimitive::Point {
+                vertex: ScreenVertex::from_clip(&a, &assembler.viewport),
+                size,
+                shape: raster.point_shape,
+                front_facing,
+                id: assembler.primitive,
+            });
+        }
+    }
+}
+
+fn render_tiled<F>(
+    tiling: &Tiling,
+    primitives: &[RasterPrimitive<F::Varyings>],
+    target: &mut RenderTarget,
+    backend: &Backend<F>,
+) where
+    F: FragmentShader + Sync,
+    F::Uniforms: Sync,
+    F::Varyings: Sync,
+{
+    tile::render_tiled(
+        tiling,
+        primitives,
+        target,
+        RasterPrimitive::bounds,
+        |primitive, tile| backend.rasterize(primitive, tile),
+    );
+}
+
+/// Per-draw state shared by every rasterizer thread.
+struct Backend<'a, F: FragmentShader> {
+    fragment_shader: &'a F,
+    state:           &'a PipelineState,
+    debug_fields:    &'a DebugFields<F::Varyings>,
+    uniforms:        &'a F::Uniforms,
+    /// Scissor rectangle clipped to the render target.
+    clip:            Bounds,
+    /// Depth and stencil test fragments before they are shaded.
+    early_tests:     bool,
+    occlusion:       Option<&'a DepthHierarchy>,
+}
+
+impl<F: FragmentShader> Backend<'_, F> {
+    fn rasterize(&self, primitive: &RasterPrimitive<F::Varyings>, tile: &mut Tile) {
+        let Some(bounds) = tile.bounds.intersect(&self.clip) else {
+            return;
+        };
+
+        match primitive {
+            RasterPrimitive::Triangle {
+                triangle,
+                front_facing,
+                id,
+            } => self.rasterize_triangle(triangle, *front_facing, *id, bounds, tile),
+            RasterPrimitive::Line {
+                a,
+                b,
+                front_facing,
+                depth_offset,
+                overlay,
+                id,
+            } => raster::rasterize_line(a, b, bounds, |fragment| {
+                let fragment = Fragment {
+                    z: fragment.z - depth_offset,
+                    ..fragment
+                };
+
+                let coverage = tile.samples.full_mask();
+
+                match overlay {
+                    Some(color) if self.state.debug.is_none() => self.write_fragment(
+                        tile.index(fragment.x, fragment.y),
+                        coverage,
+                        fragment.z,
+                        &FragmentOutput::new(*color),
+                        *front_facing,
+                        tile,
+                    ),
+                    _ => self.shade_fragment(
+                        fragment,
+                        coverage,
+                        Vec2::new(0.0, 0.0),
+                        *front_facing,
+                        *id,
+                        tile,
+                    ),
+                }
+            }),
+            RasterPrimitive::Point {
+                vertex,
+                size,
+                shape,
+                front_facing,
+                id,
+            } => {
+                let round = *shape == PointShape::Round;
+                raster::rasterize_point(
+                    vertex,
+                    *size,
+                    round.then_some(tile.samples),
+                    bounds,
+                    |fragment, coverage, point_coord| {
+                        self.shade_fragment(
+                            fragment,
+                            coverage,
+                            point_coord,
+                            *front_facing,
+                            *id,
+                            tile,
+                        )
+                    },
+                )
+            }
+        }
+    }
+
+    fn rasterize_triangle(
+        &self,
+        triangle: &Triangle<F::Varyings>,
+        front_facing: bool,
+        id: u32,
+        bounds: Bounds,
+        tile: &mut Tile,
+    ) {
+        let Some(hierarchy) = self.occlusion else {
+            triangle.rasterize(bounds, |quad| self.shade_quad(quad, front_facing, id, tile));
+            return;
+        };
+
+        let Some(covered) = triangle.bounds().intersect(&bounds) else {
+            return;
+        };
+
+        // Walk the covered hierarchy blocks and skip the ones the triangle is
+        // hidden behind. Blocks are even-aligned so no quad is split.
+        let (z_min, z_max) = triangle.depth_range();
+        let compare = self.state.depth.compare;
+
+        for by in covered.min_y / BLOCK_SIZE..=(covered.max_y - 1) / BLOCK_SIZE {
+            for bx in covered.min_x / BLOCK_SIZE..=(covered.max_x - 1) / BLOCK_SIZE {
+                let block = Bounds {
+                    min_x: bx * BLOCK_SIZE,
+                    min_y: by * BLOCK_SIZE,
+                    max_x: (bx + 1) * BLOCK_SIZE,
+                    max_y: (by + 1) * BLOCK_SIZE,
+                };
+
+                let Some(block) = block.intersect(&covered) else {
+                    continue;
+                };
+                if hierarchy.occludes(compare, block, z_min, z_max) {
+                    continue;
+                }
+
+                triangle.rasterize(block, |quad| self.shade_quad(quad, front_facing, id, tile));
+            }
+        }
+    }
+
+    fn shade_quad(&self, quad: Quad<F::Varyings>, front_facing: bool, id: u32, tile: &mut Tile) {
+        let samples = tile.samples;
+
+        if self.state.debug == Some(DebugMode::Overdraw) {
+            for lane in 0..4 {
+                let index = tile.index(quad.x + LANE_X[lane], quad.y + LANE_Y[lane]);
+                for sample in 0..samples.count() {
+                    if quad.coverage[lane] & (1 << sample) != 0 {
+                        count_fragment(index + sample, tile);
+                    }
+                }
+            }
+            return;
+        }
+
+        let sample_z = |lane: usize, sample: usize| {
+            let (dx, dy) = samples.offset(sample);
+            quad.z[lane] + quad.dz_dx * dx + quad.dz_dy * dy
+        };
+
+        let mut coverage = quad.coverage;
+        let mut mask = quad.mask;
+
+        // Early tests: samples that would fail are never written, and lanes
+        // without any remaining samples are never shaded.
+        if self.early_tests {
+            for lane in 0..4 {
+                let index = tile.index(quad.x + LANE_X[lane], quad.y + LANE_Y[lane]);
+                for sample in 0..samples.count() {
+                    if coverage[lane] & (1 << sample) != 0
+                        && !self.passes(index + sample, sample_z(lane, sample), front_facing, tile)
+                    {
+                        coverage[lane] &= !(1 << sample);
+                    }
+                }
+                if coverage[lane] == 0 {
+                    mask &= !(1 << lane);
+                }
+            }
+
+            if mask == 0 {
+                return;
+            }
+        }
+
+        // Coarse derivatives: one pair per quad, shared by all four lanes.
+        let ddx = quad.varyings[1].sub(quad.varyings[0]);
+        let ddy = quad.varyings[2].sub(quad.varyings[0]);
+
+        let inputs = std::array::from_fn(|lane| FragmentInput {
+            position: Vec4::new(
+                (quad.x + LANE_X[lane]) as f32 + 0.5,
+                (quad.y + LANE_Y[lane]) as f32 + 0.5,
+                quad.z[lane],
+                quad.inv_w[lane],
+            ),
+            front_facing,
+            varyings: quad.varyings[lane],
+            ddx,
+            ddy,
+            point_coord: Vec2::new(0.0, 0.0),
+        });
+
+        let outputs = match self.state.debug {
+            Some(mode) => std::array::from_fn(|lane| {
+                let color =
+                    mode.shade(&inputs[lane], self.debug_fields, id, quad.mask.count_ones());
+                (mask & (1 << lane) != 0).then(|| FragmentOutput::new(color))
+            }),
+            None => self
+                .fragment_shader
+                .shade_quad(&inputs, mask, self.uniforms),
+        };
+
+        for (lane, output) in outputs.into_iter().enumerate() {
+            if mask & (1 << lane) == 0 {
+                continue;
+            }
+            let Some(output) = output else {
+                continue;
+            };
+            debug_assert!(
+                F::WRITES_DEPTH || output.depth.is_none(),
+                "shader returned a depth with `WRITES_DEPTH` unset"
+            );
+
+            let index = tile.index(quad.x + LANE_X[lane], quad.y + LANE_Y[lane]);
+            for sample in 0..samples.count() {
+                if coverage[lane] & (1 << sample) != 0 {
+                    let depth = output.depth.unwrap_or_else(|| sample_z(lane, sample));
+                    self.write_sample(index + sample, depth, &output, front_facing, tile);
+                }
+            }
+        }
+    }
+
+    /// Shades a line or point fragment, which covers the samples set in
+    /// `coverage` at the same depth.
+    fn shade_fragment(
+        &self,
+        fragment: Fragment<F::Varyings>,
+        coverage: u8,
+        point_coord: Vec2,
+        front_facing: bool,
+        id: u32,
+        tile: &mut Tile,
+    ) {
+        let index = tile.index(fragment.x, fragment.y);
+        let covered = |sample: &usize| coverage & (1 << sample) != 0;
+        let samples = 0..tile.samples.count();
+
+        if self.state.debug == Some(DebugMode::Overdraw) {
+            for sample in samples.filter(covered) {
+                count_fragment(index + sample, tile);
+            }
+            return;
+        }
+
+        if self.early_tests
+            && !samples
+                .filter(covered)
+                .any(|sample| self.passes(index + sample, fragment.z, front_facing, tile))
+        {
+            return;
+        }
+
+        let zero = fragment.varyings.scale(0.0);
+        let input = FragmentInput {
+            position: Vec4::new(
+                fragment.x as f32 + 0.5,
+                fragment.y as f32 + 0.5,
+                fragment.z,
+                fragment.inv_w,
+            ),
+            front_facing,
+            varyings: fragment.varyings,
+            ddx: zero,
+            ddy: zero,
+            point_coord,
+        };
+
+        let output = match self.state.debug {
+            Some(mode) => Some(FragmentOutput::new(mode.shade(
+                &input,
+                self.debug_fields,
+                id,
+                1,
+            ))),
+            None => self.fragment_shader.shade(&input, self.uniforms),
+        };
+        let Some(output) = output else {
+            return;
+        };
+        debug_assert!(
+            F::WRITES_DEPTH || output.depth.is_none(),
+            "shader returned a depth with `WRITES_DEPTH` unset"
+        );
+
+        let depth = output.depth.unwrap_or(fragment.z);
+        self.write_fragment(index, coverage, depth, &output, front_facing, tile);
+    }
+
+    fn write_fragment(
+        &self,
+        index: usize,
+        coverage: u8,
+        depth: f32,
+        output: &FragmentOutput,
+        front_facing: bool,
+        tile: &mut Tile,
+    ) {
+        for sample in 0..tile.samples.count() {
+            if coverage & (1 << sample) != 0 {
+                self.write_sample(index + sample, depth, output, front_facing, tile);
+            }
+        }
+    }
+
+    fn stencil_passes(&self, index: usize, front_facing: bool, tile: &Tile) -> bool {
+        tile.stencil
+            .as_ref()
+            .is_none_or(|stencil| self.state.stencil.test(front_facing, stencil[index]))
+    }
+
+    /// Runs the stencil and depth tests for a sample without writing anything.
+    fn passes(&self, index: usize, depth: f32, front_facing: bool, tile: &Tile) -> bool {
+        self.stencil_passes(index, front_facing, tile)
+            && self.state.depth.compare.test(depth, tile.depth[index])
+    }
+
+    fn write_sample(
+        &self,
+        index: usize,
+        depth: f32,
+        output: &FragmentOutput,
+        front_facing: bool,
+        tile: &mut Tile,
+    ) {
+        let stencil_passes = self.stencil_passes(index, front_facing, tile);
+        let depth_passes =
+            stencil_passes && self.state.depth.compare.test(depth, tile.depth[index]);
+
+        if let Some(stencil) = tile.stencil.as_mut() {
+            let face = self.state.stencil.face(front_facing);
+            let op = match (stencil_passes, depth_passes) {
+                (false, _) => face.fail,
+                (true, false) => face.depth_fail,
+                (true, true) => face.pass,
+            };
+            stencil[index] = self.state.stencil.update(op, stencil[index]);
+        }
+
+        if !depth_passes {
+            return;
+        }
+
+        if self.state.depth.write {
+            tile.depth[index] = depth;
+        }
+
+        // Debug colors are written as they are.
+        let pixel = tile.pixel(index);
+        let blends = self
+            .state
+            .blend
+            .map(|blend| blend.filter(|_| self.state.debug.is_none()));
+        for ((target, color), blend) in tile.colors.iter_mut().zip(output.colors()).zip(&blends) {
+            let color = match blend {
+                Some(blend) => blend.blend(color, target.read(index)),
+                None => color,
+            };
+            target.write(index, pixel, color);
+        }
+    }
+}
+
+/// Counts a fragment for `DebugMode::Overdraw` in the first color attachment.
+fn count_fragment(index: usize, tile: &mut Tile) {
+    if let Some(counts) = tile.colors.first_mut() {
+        counts.increment(index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffer::{
+            ops::{Fill, GetPixel, ToArray},
+            Buffer, HdrFormat,
+        },
+        mesh::Topology,
+    };
+
+    /// Passes a clip-space position and one varying through.
+    struct Passthrough;
+
+    impl VertexShader for Passthrough {
+        type Vertex = (Vec4, f32);
+        type Uniforms = ();
+        type Varyings = f32;
+
+        fn shade(&self, vertex: &(Vec4, f32), _: &()) -> VertexOutput<f32> {
+            VertexOutput {
+                position: vertex.0,
+                varyings: vertex.1,
+            }
+        }
+    }
+
+    /// Like `Passthrough`, counting invocations and offsetting each instance
+    /// to the right.
+    #[derive(Default)]
+    struct Counting(std::cell::Cell<u32>);
+
+    impl VertexShader for Counting {
+        type Vertex = (Vec4, f32);
+        type Uniforms = ();
+        type Varyings = f32;
+
+        fn shade(&self, vertex: &(Vec4, f32), uniforms: &()) -> VertexOutput<f32> {
+            self.shade_instance(vertex, 0, uniforms)
+        }
+
+        fn shade_instance(&self, vertex: &(Vec4, f32), instance: u32, _: &()) -> VertexOutput<f32> {
+            self.0.set(self.0.get() + 1);
+            VertexOutput {
+                position: vertex.0 + Vec4::new(instance as f32, 0.0, 0.0, 0.0),
+                varyings: vertex.1,
+            }
+        }
+    }
+
+    /// Writes the varying to red and the facing to green.
+    struct Shade;
+
+    impl FragmentShader for Shade {
+        type Uniforms = ();
+        type Varyings = f32;
+
+        const WRITES_DEPTH: bool = false;
+
+        fn shade(&self, input: &FragmentInput<f32>, _: &()) -> Option<FragmentOutput> {
+            let facing = if input.front_facing { 1.0 } else { 0.0 };
+            Some(FragmentOutput::new(Vec4::new(
+                input.varyings,
+                facing,
+                0.0,
+                1.0,
+            )))
+        }
+    }
+
+    struct Discard;
+
+    impl FragmentShader for Discard {
+        type Uniforms = ();
+        type Varyings = f32;
+
+        const WRITES_DEPTH: bool = false;
+
+        fn shade(&self, _: &FragmentInput<f32>, _: &()) -> Option<FragmentOutput> {
+            None
+        }
+    }
+
+    fn vertex(x: f32, y: f32, z: f32, value: f32) -> (Vec4, f32) {
+        (Vec4::new(x, y, z, 1.0), value)
+    }
+
+    /// A triangle covering the whole target at depth `z`.
+    fn fullscreen(z: f32, value: f32) -> Mesh<(Vec4, f32)> {
+        Mesh::new(
+            vec![
+                vertex(-1.0, -1.0, z, value),
+                vertex(3.0, -1.0, z, value),
+                vertex(-1.0, 3.0, z, value),
+            ],
+            Topology::TriangleList,
+        )
+    }
+
+    fn red(buffer: &FrameBuffer, x: usize, y: usize) -> u32 {
+        (buffer.get_pixel(x, y).unwrap() >> 16) & 0xff
+    }
+
+    #[test]
+    fn triangles_interpolate_varyings() {
+        let mut color = FrameBuffer::new(16, 8);
+        let mut depth = DepthBuffer::new(16, 8);
+        let mesh = Mesh::new(
+            vec![
+                vertex(-1.0, -1.0, 0.0, 0.0),
+                vertex(1.0, -1.0, 0.0, 1.0),
+                vertex(-1.0, 1.0, 0.0, 0.0),
+                vertex(1.0, 1.0, 0.0, 1.0),
+            ],
+            Topology::TriangleStrip,
+        );
+        Pipeline::new(Passthrough, Shade)
+            .draw(&mesh, &(), &mut RenderTarget::new(&mut color, &mut depth))
+            .unwrap();
+
+        assert!(color.to_array().unwrap().iter().all(|&p| p != 0));
+        assert!(red(&color, 0, 4) < 0x10);
+        assert!(red(&color, 15, 4) > 0xf0);
+        assert!(red(&color, 4, 4) < red(&color, 8, 4));
+        assert!(depth.to_array().unwrap().iter().all(|&z| z == 0.5));
+    }
+
+    #[test]
+    fn depth_test_keeps_nearest() {
+        let pipeline = Pipeline::new(Passthrough, Shade);
+
+        for order in [[0.2, 0.6], [0.6, 0.2]] {
+            let mut color = FrameBuffer::new(4, 4);
+            let mut depth = DepthBuffer::new(4, 4);
+            for z in order {
+                let mesh = fullscreen(z, z);
+                pipeline
+                    .draw(&mesh, &(), &mut RenderTarget::new(&mut color, &mut depth))
+                    .unwrap();
+            }
+
+            assert_eq!(red(&color, 1, 1), 0x33);
+            assert_eq!(depth.get_pixel(1, 1).unwrap(), 0.6);
+        }
+    }
+
+    #[test]
+    fn discarded_fragments_leave_target_untouched() {
+        let mut color = FrameBuffer::new(4, 4);
+        let mut depth = DepthBuffer::new(4, 4);
+        Pipeline::new(Passthrough, Discard)
+            .draw(
+                &fullscreen(0.5, 1.0),
+                &(),
+                &mut RenderTarget::new(&mut color, &mut depth),
+            )
+            .unwrap();
+
+        assert!(color.to_array().unwrap().iter().all(|&p| p == 0));
+        assert!(depth
+            .to_array()
+            .unwrap()
+            .iter()
+            .all(|&z| z == f32::INFINITY));
+    }
+
+    fn quad() -> Vec<(Vec4, f32)> {
+        vec![
+            vertex(-1.0, -1.0, 0.0, 1.0),
+            vertex(0.0, -1.0, 0.0, 1.0),
+            vertex(-1.0, 1.0, 0.0, 1.0),
+            vertex(0.0, 1.0, 0.0, 1.0),
+        ]
+    }
+
+    #[test]
+    fn indexed_and_instanced_draws() {
+        let pipeline = Pipeline::new(Counting::default(), Shade);
+        let mut color = FrameBuffer::new(8, 4);
+        let mut depth = DepthBuffer::new(8, 4);
+        let lit = |color: &FrameBuffer| {
+            color
+                .to_array()
+                .unwrap()
+                .iter()
+                .filter(|&&p| p != 0)
+                .count()
+        };
+
+        let mesh = Mesh::indexed(quad(), vec![0u16, 1, 2, 2, 1, 3], Topology::TriangleList);
+        pipeline
+            .draw_indexed(&mesh, &(), &mut RenderTarget::new(&mut color, &mut depth))
+            .unwrap();
+        assert_eq!(lit(&color), 16);
+        // Shared vertices come from the cache.
+        assert_eq!(pipeline.vertex_shader.0.replace(0), 4);
+
+        color.clear();
+        depth.clear();
+        pipeline
+            .draw_instanced(
+                &mesh,
+                2,
+                &(),
+                &mut RenderTarget::new(&mut color, &mut depth),
+            )
+            .unwrap();
+        assert_eq!(lit(&color), 32);
+        assert_eq!(pipeline.vertex_shader.0.replace(0), 8);
+
+        // Without indices, only strips and fans reuse vertices.
+        let strip = Mesh::new(quad(), Topology::TriangleStrip);
+        pipeline
+            .draw(&strip, &(), &mut RenderTarget::new(&mut color, &mut depth))
+            .unwrap();
+        assert_eq!(pipeline.vertex_shader.0.replace(0), 4);
+
+        let list = Mesh::new(quad()[..3].repeat(2), Topology::TriangleList);
+        pipeline
+            .draw(&list, &(), &mut RenderTarget::new(&mut color, &mut depth))
+            .unwrap();
+        assert_eq!(pipeline.vertex_shader.0.replace(0), 6);
+    }
+
+    #[test]
+    fn invalid_draws_are_rejected() {
+        let pipeline = Pipeline::new(Passthrough, Shade);
+        let mut color = FrameBuffer::new(4, 4);
+        let mut depth = DepthBuffer::new(4, 4);
+        let mut target = RenderTarget::new(&mut color, &mut depth);
+
+        let mesh = Mesh::new(quad(), Topology::TriangleList);
+        assert_eq!(
+            pipeline.draw_indexed(&mesh, &(), &mut target),
+            Err(DrawError::MissingIndices)
+        );
+
+        let mesh = Mesh::indexed(quad(), vec![0u32, 1, 4], Topology::TriangleList);
+        assert_eq!(
+            pipeline.draw_indexed(&mesh, &(), &mut target),
+            Err(DrawError::IndexOutOfBounds)
+        );
+
+        let mut small = DepthBuffer::new(2, 2);
+        let mut color = FrameBuffer::new(4, 4);
+        assert_eq!(
+            pipeline.draw(&mesh, &(), &mut RenderTarget::new(&mut color, &mut small)),
+            Err(DrawError::Target(BufferError::SizeMismatch))
+        );
+        assert!(color.to_array().unwrap().iter().all(|&p| p == 0));
+    }
+
+    #[test]
+    fn multisampled_edges_resolve_to_partial_coverage() {
+        let mesh = Mesh::new(
+            vec![
+                vertex(-0.9, -0.8, 0.0, 1.0),
+                vertex(0.9, -0.3, 0.0, 1.0),
+                vertex(-0.2, 0.9, 0.0, 1.0),
+            ],
+            Topology::TriangleList,
+        );
+        let mut pipeline = Pipeline::new(Passthrough, Shade);
+
+        let mut render = |tiling| {
+            pipeline.set_tiling(tiling);
+            let mut color = FrameBuffer::multisampled(32, 16, SampleCount::X4);
+            let mut depth = DepthBuffer::multisampled(32, 16, SampleCount::X4);
+            pipeline
+                .draw(&mesh, &(), &mut RenderTarget::new(&mut color, &mut depth))
+                .unwrap();
+            color
+        };
+        let color = render(None);
+        let tiled = render(Some(Tiling {
+            tile_size: 8,
+            threads:   2,
+        }));
+        assert_eq!(color.to_array().unwrap(), tiled.to_array().unwrap());
+
+        let mut resolved = FrameBuffer::new(32, 16);
+        color.resolve(&mut resolved).unwrap();
+        let levels: HashSet<u32> = (0..16)
+            .flat_map(|y| (0..32).map(move |x| (x, y)))
+            .map(|(x, y)| red(&resolved, x, y))
+            .collect();
+        assert!(levels.contains(&0) && levels.contains(&0xff));
+        assert!(levels.len() >= 4, "{levels:?}");
+
+        let mut multisampled = FrameBuffer::multisampled(32, 16, SampleCount::X2);
+        assert_eq!(
+            color.resolve(&mut multisampled),
+            Err(BufferError::SampleCountMismatch)
+        );
+    }
+
+    #[test]
+    fn sample_counts_must_match_across_the_target() {
+        let pipeline = Pipeline::new(Passthrough, Shade);
+        let mut color = FrameBuffer::multisampled(4, 4, SampleCount::X4);
+        let mut depth = DepthBuffer::multisampled(4, 4, SampleCount::X4);
+        let mut stencil = StencilBuffer::new(4, 4);
+
+        assert_eq!(
+            pipeline.draw(
+                &fullscreen(0.0, 1.0),
+                &(),
+                &mut RenderTarget::with_stencil(&mut color, &mut depth, &mut stencil),
+            ),
+            Err(DrawError::Target(BufferError::SampleCountMismatch))
+        );
+        assert!(color.to_array().unwrap().iter().all(|&p| p == 0));
+    }
+
+    #[test]
+    fn viewport_and_scissor_confine_drawing() {
+        for tiling in [
+            None,
+            Some(Tiling {
+                tile_size: 8,
+                threads:   2,
+            }),
+        ] {
+            let mut pipeline = Pipeline::new(Passthrough, Shade);
+            pipeline.set_tiling(tiling);
+            pipeline.state.viewport = Some(Viewport {
+                min_depth: 0.2,
+                max_depth: 0.6,
+                ..Viewport::new(12.0, 0.0, 12.0, 12.0)
+            });
+            pipeline.state.scissor = Some(Rect::new(0, 2, 20, 4));
+
+            let mut color = FrameBuffer::new(24, 12);
+            let mut depth = DepthBuffer::new(24, 12);
+            pipeline
+                .draw(
+                    &fullscreen(0.0, 1.0),
+                    &(),
+                    &mut RenderTarget::new(&mut color, &mut depth),
+                )
+                .unwrap();
+
+            for y in 0..12 {
+                for x in 0..24 {
+                    let inside = (12..20).contains(&x) && (2..6).contains(&y);
+                    assert_eq!(red(&color, x, y) != 0, inside, "{tiling:?} {x} {y}");
+                    if inside {
+                        assert!((depth.get_pixel(x, y).unwrap() - 0.4).abs() < 1e-6);
+                    }
+                }
+            }
+
+            // A scissor rectangle off the target leaves nothing to draw.
+            color.clear();
+            pipeline.state.scissor = Some(Rect::new(30, 0, 4, 4));
+            pipeline
+                .draw(
+                    &fullscreen(-0.5, 1.0),
+                    &(),
+                    &mut RenderTarget::new(&mut color, &mut depth),
+                )
+                .unwrap();
+            assert!(color.to_array().unwrap().iter().all(|&p| p == 0));
+        }
+    }
+
+    /// Writes the varying to the first attachment, green to the second and
+    /// an out-of-range value to the third.
+    struct Layers;
+
+    impl FragmentShader for Layers {
+        type Uniforms = ();
+        type Varyings = f32;
+
+        const WRITES_DEPTH: bool = false;
+
+        fn shade(&self, input: &FragmentInput<f32>, _: &()) -> Option<FragmentOutput> {
+            Some(
+                FragmentOutput::new(Vec4::new(input.varyings, 0.0, 0.0, 1.0)).with_attachments(&[
+                    Vec4::new(0.0, 1.0, 0.0, 1.0),
+                    Vec4::new(2.0, 0.0, 0.0, 1.0),
+                ]),
+            )
+        }
+    }
+
+    #[test]
+    fn attachments_get_their_own_colors_and_blending() {
+        let mut first = FrameBuffer::new(4, 4);
+        let mut second = FrameBuffer::new(4, 4);
+        let mut third = HdrBuffer::new(4, 4);
+        let mut depth = DepthBuffer::new(4, 4);
+        third.fill(Vec4::new(1.0, 0.0, 0.0, 1.0));
+
+        let mut pipeline = Pipeline::new(Passthrough, Layers);
+        pipeline.state.blend[2] = Some(BlendState::ADDITIVE);
+        pipeline
+            .draw(
+                &fullscreen(0.0, 0.6),
+                &(),
+                &mut RenderTarget::with_attachments(
+                    vec![
+                        (&mut first).into(),
+                        (&mut second).into(),
+                        (&mut third).into(),
+                    ],
+                    &mut depth,
+                ),
+            )
+            .unwrap();
+
+        assert_eq!(red(&first, 1, 1), 0x99);
+        assert_eq!(second.get_pixel(1, 1).unwrap(), 0xff00ff00);
+        assert_eq!(third.resolve_pixel(1, 1).unwrap().x, 3.0);
+
+        // Depth only.
+        let mut depth = DepthBuffer::new(4, 4);
+        pipeline
+            .draw(
+                &fullscreen(0.0, 0.6),
+                &(),
+                &mut RenderTarget::with_attachments(Vec::new(), &mut depth),
+            )
+            .unwrap();
+        assert_eq!(depth.get_pixel(1, 1).unwrap(), 0.5);
+    }
+
+    #[test]
+    fn srgb_targets_blend_in_linear_light() {
+        for tiling in [
+            None,
+            Some(Tiling {
+                tile_size: 4,
+                threads:   3,
+            }),
+        ] {
+            let mut color = FrameBuffer::new(20, 10);
+            let mut depth = DepthBuffer::new(20, 10);
+            color.srgb = true;
+
+            let mut pipeline = Pipeline::new(Passthrough, Shade);
+            pipeline.state.blend[0] = Some(BlendState::ADDITIVE);
+            pipeline.state.depth.compare = CompareFunction::Always;
+            pipeline.set_tiling(tiling);
+
+            for _ in 0..2 {
+                pipeline
+                    .draw(
+                        &fullscreen(0.0, 0.25),
+                        &(),
+                        &mut RenderTarget::new(&mut color, &mut depth),
+                    )
+                    .unwrap();
+            }
+
+            // 0.25 + 0.25 in linear light, then encoded. Adding the encoded
+            // values would saturate instead.
+            assert_eq!(red(&color, 5, 5), 0xbc);
+            let decoded = color.decode(color.get_pixel(5, 5).unwrap());
+            assert!((decoded.x - 0.5).abs() < 3e-3);
+        }
+    }
+
+    /// Writes a color brighter than an 8-bit target can hold.
+    struct Bright;
+
+    impl FragmentShader for Bright {
+        type Uniforms = ();
+        type Varyings = f32;
+
+        const WRITES_DEPTH: bool = false;
+
+        fn shade(&self, _: &FragmentInput<f32>, _: &()) -> Option<FragmentOutput> {
+            Some(FragmentOutput::new(Vec4::new(3.0, 0.5, 0.0, 1.0)))
+        }
+    }
+
+    #[test]
+    fn hdr_targets_accumulate_past_one() {
+        for format in [HdrFormat::Rgba32F, HdrFormat::Rgba16F] {
+            for tiling in [
+                None,
+                Some(Tiling {
+                    tile_size: 4,
+                    threads:   3,
+                }),
+            ] {
+                let mut color = HdrBuffer::multisampled(20, 10, SampleCount::X4, format);
+                let mut depth = DepthBuffer::multisampled(20, 10, SampleCount::X4);
+                let mut pipeline = Pipeline::new(Passthrough, Bright);
+                pipeline.state.blend[0] = Some(BlendState::ADDITIVE);
+                pipeline.state.depth.compare = CompareFunction::Always;
+                pipeline.set_tiling(tiling);
+
+                for _ in 0..2 {
+                    pipeline
+                        .draw(
+                            &fullscreen(0.0, 0.0),
+                            &(),
+                            &mut RenderTarget::new(&mut color, &mut depth),
+                        )
+                        .unwrap();
+                }
+
+                assert_eq!(color.format(), format);
+                assert_eq!(
+                    color.resolve_pixel(5, 5).unwrap(),
+                    Vec4::new(6.0, 1.0, 0.0, 2.0)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn single_colors_leave_later_attachments_untouched() {
+        for (polygon_mode, debug) in [
+            (PolygonMode::Fill, None),
+            (PolygonMode::Overlay, None),
+            (PolygonMode::Fill, Some(DebugMode::PrimitiveId)),
+            (PolygonMode::Line, Some(DebugMode::QuadOccupancy)),
+        ] {
+            let mut first = FrameBuffer::new(8, 8);
+            let mut second = FrameBuffer::new(8, 8);
+            let mut depth = DepthBuffer::new(8, 8);
+            second.fill(0xff123456);
+
+            let mut pipeline = Pipeline::new(Passthrough, Shade);
+            pipeline.state.raster.polygon_mode = polygon_mode;
+            pipeline.state.debug = debug;
+            pipeline
+                .draw(
+                    &Mesh::new(quad(), Topology::TriangleStrip),
+                    &(),
+                    &mut RenderTarget::with_attachments(
+                        vec![(&mut first).into(), (&mut second).into()],
+                        &mut depth,
+                    ),
+                )
+                .unwrap();
+
+            let case = format!("{polygon_mode:?} {debug:?}");
+            assert!(first.to_array().unwrap().iter().any(|&p| p != 0), "{case}");
+            assert!(
+                second.to_array().unwrap().iter().all(|&p| p == 0xff123456),
+                "{case}"
+            );
+        }
+    }
+
+    #[test]
+    fn culling_follows_winding() {
+        // Winding is taken in normalized device coordinates, with y up.
+        let ccw = Mesh::new(
+            vec![
+                vertex(-1.0, -1.0, 0.0, 0.0),
+                vertex(3.0, -1.0, 0.0, 0.0),
+                vertex(-1.0, 3.0, 0.0, 0.0),
+            ],
+            Topology::TriangleList,
+        );
+
+        for (cull_mode, front_face, drawn, front) in [
+            (CullMode::None, FrontFace::Ccw, true, true),
+            (CullMode::None, FrontFace::Cw, true, false),
+            (CullMode::Back, FrontFace::Ccw, true, true),
+            (CullMode::Front, FrontFace::Ccw, false, true),
+            (CullMode::Front, FrontFace::Cw, true, false),
+            (CullMode::Back, FrontFace::Cw, false, false),
+        ] {
+            let mut pipeline = Pipeline::new(Passthrough, Shade);
+            pipeline.state.raster.cull_mode = cull_mode;
+            pipeline.state.raster.front_face = front_face;
+
+            let mut color = FrameBuffer::new(4, 4);
+            let mut depth = DepthBuffer::new(4, 4);
+            pipeline
+                .draw(&ccw, &(), &mut RenderTarget::new(&mut color, &mut depth))
+                .unwrap();
+
+            let pixels = color.to_array().unwrap();
+            let case = format!("{cull_mode:?} {front_face:?}");
+            assert!(pixels.iter().all(|&p| (p != 0) == drawn), "{case}");
+            if drawn {
+                let green = (pixels[0] >> 8) & 0xff;
+                assert_eq!(green == 0xff, front, "{case}");
+            }
+        }
+    }
+
+    #[test]
+    fn stencil_ops_saturate_or_wrap() {
+        for (op, value, expected) in [
+            (StencilOp::Keep, 7, 7),
+            (StencilOp::Zero, 7, 0),
+            (StencilOp::Replace, 7, 0x42),
+            (StencilOp::IncrementClamp, 0xff, 0xff),
+            (StencilOp::DecrementClamp, 0, 0),
+            (StencilOp::Invert, 0x0f, 0xf0),
+            (StencilOp::IncrementWrap, 0xff, 0),
+            (StencilOp::DecrementWrap, 0, 0xff),
+        ] {
+            assert_eq!(op.apply(value, 0x42), expected, "{op:?}");
+        }
+    }
+
+    #[test]
+    fn stencil_tests_and_updates_follow_state() {
+        for tiling in [
+            None,
+            Some(Tiling {
+                tile_size: 2,
+                threads:   2,
+            }),
+        ] {
+            let mut color = FrameBuffer::new(8, 4);
+            let mut depth = DepthBuffer::new(8, 4);
+            let mut stencil = StencilBuffer::new(8, 4);
+            stencil.fill(0x50);
+
+            let mut draw = |mesh: &Mesh<(Vec4, f32)>, stencil_state| {
+                let mut pipeline = Pipeline::new(Passthrough, Shade);
+                pipeline.state.stencil = stencil_state;
+                pipeline.set_tiling(tiling);
+                pipeline
+                    .draw(
+                        mesh,
+                        &(),
+                        &mut RenderTarget::with_stencil(&mut color, &mut depth, &mut stencil),
+                    )
+                    .unwrap();
+            };
+            let both = |face: StencilFaceState| StencilState {
+                front: face,
+                back: face,
+                ..StencilState::default()
+            };
+
+            // Mark the left half.
+            draw(
+                &Mesh::new(quad(), Topology::TriangleStrip),
+                StencilState {
+                    reference: 3,
+                    ..both(StencilFaceState {
+                        pass: StencilOp::Replace,
+                        ..StencilFaceState::default()
+                    })
+                },
+            );
+            // Draw over the marked half only, comparing the low bits.
+            draw(
+                &fullscreen(-0.2, 0.6),
+                StencilState {
+                    reference: 0x13,
+                    read_mask: 0x0f,
+                    ..both(StencilFaceState {
+                        compare: CompareFunction::Equal,
+                        pass: StencilOp::IncrementClamp,
+                        ..StencilFaceState::default()
+                    })
+                },
+            );
+            // Behind the left half and in front of the empty right half,
+            // updating the low bits only.
+            draw(
+                &fullscreen(0.8, 1.0),
+                StencilState {
+                    write_mask: 0x0f,
+                    ..both(StencilFaceState {
+                        depth_fail: StencilOp::Invert,
+                        pass: StencilOp::Zero,
+                        ..StencilFaceState::default()
+                    })
+                },
+            );
+
+            let case = format!("{tiling:?}");
+            assert_eq!(stencil.get_pixel(1, 1).unwrap(), 0x0b, "{case}");
+            assert_eq!(stencil.get_pixel(6, 1).unwrap(), 0x50, "{case}");
+            assert_eq!(red(&color, 1, 1), 0x99, "{case}");
+            assert_eq!(red(&color, 6, 1), 0xff, "{case}");
+        }
+    }
+
+    #[test]
+    fn edges_win_against_sloped_surfaces() {
+        let mesh = Mesh::new(
+            vec![
+                vertex(-0.8, -0.8, 0.9, 0.0),
+                vertex(0.8, -0.8, -0.9, 0.0),
+                vertex(-0.8, 0.8, 0.0, 0.0),
+            ],
+            Topology::TriangleList,
+        );
+
+        let edge_pixels = |offset: f32, slope: f32| {
+            let mut pipeline = Pipeline::new(Passthrough, Shade);
+            pipeline.state.raster.polygon_mode = PolygonMode::Overlay;
+            pipeline.state.raster.overlay_color = Vec4::new(0.0, 0.0, 1.0, 1.0);
+            pipeline.state.raster.line_depth_offset = offset;
+            pipeline.state.raster.line_depth_slope = slope;
+
+            let mut color = FrameBuffer::new(32, 32);
+            let mut depth = DepthBuffer::new(32, 32);
+            pipeline
+                .draw(&mesh, &(), &mut RenderTarget::new(&mut color, &mut depth))
+                .unwrap();
+            let pixels = color.to_array().unwrap();
+            pixels.iter().filter(|&&p| p & 0xff == 0xff).count()
+        };
+
+        let all = edge_pixels(1.0, 0.0);
+        // Edge pixels are tested against the surface up to a pixel away, so
+        // a constant offset loses wherever the surface slopes towards them.
+        assert!(edge_pixels(1e-5, 0.0) < all);
+        let raster = RasterState::default();
+        assert_eq!(
+            edge_pixels(raster.line_depth_offset, raster.line_depth_slope),
+            all
+        );
+    }
+
+    #[test]
+    fn shared_edges_are_drawn_once() {
+        let v = |x, y| vertex(x, y, 0.0, 0.25);
+        let quad = vec![
+            v(-0.8, -0.8),
+            v(0.8, -0.8),
+            v(0.8, 0.8),
+            v(-0.8, -0.8),
+            v(0.8, 0.8),
+            v(-0.8, 0.8),
+        ];
+
+        for mode in [PolygonMode::Line, PolygonMode::Overlay] {
+            let mut pipeline = Pipeline::new(Passthrough, Shade);
+            pipeline.state.raster.polygon_mode = mode;
+            pipeline.state.raster.overlay_color = Vec4::new(0.25, 0.0, 0.0, 1.0);
+            pipeline.state.blend[0] = Some(crate::blend::BlendState::ADDITIVE);
+            pipeline.state.depth.compare = CompareFunction::Always;
+            pipeline.state.depth.write = false;
+
+            let mut color = FrameBuffer::new(16, 16);
+            let mut depth = DepthBuffer::new(16, 16);
+            pipeline
+                .draw(
+                    &Mesh::new(quad.clone(), Topology::TriangleList),
+                    &(),
+                    &mut RenderTarget::new(&mut color, &mut depth),
+                )
+                .unwrap();
+
+            // The diagonal is drawn, but only by one of the triangles.
+            let diagonal = (2..14).filter(|&i| red(&color, i, 15 - i) != 0).count();
+            assert!(diagonal > 8, "{mode:?}");
+            let edges = color.to_array().unwrap();
+            let brightest = edges.iter().map(|p| (p >> 16) & 0xff).max();
+            let expected = match mode {
+                // Edges over the unblended fill.
+                PolygonMode::Overlay => 0x80,
+                _ => 0x40,
+            };
+            assert_eq!(brightest, Some(expected), "{mode:?}");
+        }
+    }
+
+    /// Triangles scattered in and around the view volume, from a fixed seed.
+    fn scattered(count: usize, seed: u32) -> Mesh<(Vec4, f32)> {
+        let mut state = seed;
+        let mut random = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32 * 2.4 - 1.2
+        };
+
+        let vertices = (0..count * 3)
+            .map(|_| {
+                let w = 1.0 + random().abs();
+                let position = Vec4::new(random() * w, random() * w, random() * w, w);
+                (position, random().abs())
+            })
+            .collect();
+        Mesh::new(vertices, Topology::TriangleList)
+    }
+
+    #[test]
+    fn tiled_rendering_matches_serial() {
+        let mesh = scattered(400, 12345);
+
+        for mode in [PolygonMode::Fill, PolygonMode::Overlay, PolygonMode::Point] {
+            let mut pipeline = Pipeline::new(Passthrough, Shade);
+            pipeline.state.raster.polygon_mode = mode;
+            pipeline.state.raster.point_size = 5.0;
+
+            let mut render = |tiling| {
+                pipeline.set_tiling(tiling);
+                let mut color = FrameBuffer::new(101, 67);
+                let mut depth = DepthBuffer::new(101, 67);
+                pipeline
+                    .draw(&mesh, &(), &mut RenderTarget::new(&mut color, &mut depth))
+                    .unwrap();
+                (color, depth)
+            };
+
+            let (color, depth) = render(None);
+            let (tiled_color, tiled_depth) = render(Some(Tiling {
+                tile_size: 16,
+                threads:   3,
+            }));
+
+            assert_eq!(
+                color.to_array().unwrap(),
+                tiled_color.to_array().unwrap(),
+                "{mode:?}"
+            );
+            assert_eq!(depth.to_array().unwrap(), tiled_depth.to_array().unwrap());
+            let lit = color
+                .to_array()
+                .unwrap()
+                .iter()
+                .filter(|&&p| p != 0)
+                .count();
+            assert!(lit > 1000, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn depth_hierarchy_leaves_results_unchanged() {
+        let meshes: Vec<_> = (0..3).map(|seed| scattered(200, 777 + seed)).collect();
+
+        for compare in [CompareFunction::Less, CompareFunction::GreaterEqual] {
+            for tiling in [
+                None,
+                Some(Tiling {
+                    tile_size: 32,
+                    threads:   3,
+                }),
+            ] {
+                let mut pipeline = Pipeline::new(Passthrough, Shade);
+                pipeline.state.depth.compare = compare;
+                pipeline.set_tiling(tiling);
+
+                let render = |hierarchy: bool| {
+                    let mut color = FrameBuffer::new(97, 71);
+                    let mut depth = DepthBuffer::new(97, 71);
+                    if compare == CompareFunction::GreaterEqual {
+                        depth.fill(f32::NEG_INFINITY);
+                    }
+                    if hierarchy {
+                        depth.enable_hierarchy();
+                    }
+                    for mesh in &meshes {
+                        pipeline
+                            .draw(mesh, &(), &mut RenderTarget::new(&mut color, &mut depth))
+                            .unwrap();
+                    }
+                    (
+                        color.to_array().unwrap().to_vec(),
+                        depth.to_array().unwrap().to_vec(),
+                    )
+                };
+
+                assert!(render(false) == render(true), "{compare:?} {tiling:?}");
+            }
+        }
+    }
+
+    /// Writes a fixed depth, whatever the interpolated one.
+    struct FixedDepth(f32);
+
+    impl FragmentShader for FixedDepth {
+        type Uniforms = ();
+        type Varyings = f32;
+
+        fn shade(&self, input: &FragmentInput<f32>, _: &()) -> Option<FragmentOutput> {
+            let mut output = FragmentOutput::new(Vec4::new(input.varyings, 0.0, 0.0, 1.0));
+            output.depth = Some(self.0);
+            Some(output)
+        }
+    }
+
+    #[test]
+    fn shader_depth_replaces_interpolated_depth_in_tests() {
+        let mut color = FrameBuffer::new(16, 16);
+        let mut depth = DepthBuffer::new(16, 16);
+        depth.enable_hierarchy();
+
+        let mut draw = |shader, z, value| {
+            Pipeline::new(Passthrough, shader)
+                .draw(
+                    &fullscreen(z, value),
+                    &(),
+                    &mut RenderTarget::new(&mut color, &mut depth),
+                )
+                .unwrap();
+        };
+
+        draw(FixedDepth(0.5), 0.0, 0.2);
+        // Interpolated depth 0.9 is hidden, but the shader brings it forward.
+        draw(FixedDepth(0.1), 0.8, 0.6);
+        // Interpolated depth 0.05 is in front, but the shader pushes it back.
+        draw(FixedDepth(0.7), -0.9, 1.0);
+
+        assert_eq!(red(&color, 3, 3), 0x99);
+        assert_eq!(depth.get_pixel(3, 3).unwrap(), 0.1);
+    }
+
+    /// A varying that sets the point size when positive.
+    #[derive(Debug, Clone, Copy)]
+    struct PointSize(f32);
+
+    impl Varyings for PointSize {
+        fn add(self, other: PointSize) -> PointSize {
+            PointSize(self.0 + other.0)
+        }
+
+        fn scale(self, factor: f32) -> PointSize {
+            PointSize(self.0 * factor)
+        }
+
+        fn point_size(&self) -> Option<f32> {
+            (self.0 > 0.0).then_some(self.0)
+        }
+    }
+
+    struct Sized;
+
+    impl VertexShader for Sized {
+        type Vertex = (Vec4, f32);
+        type Uniforms = ();
+        type Varyings = PointSize;
+
+        fn shade(&self, vertex: &(Vec4, f32), _: &()) -> VertexOutput<PointSize> {
+            VertexOutput {
+                position: vertex.0,
+                varyings: PointSize(vertex.1),
+            }
+        }
+    }
+
+    /// Records the window position and point coordinate of each fragment.
+    #[derive(Default)]
+    struct Sprites(std::cell::RefCell<Vec<(Vec2, Vec2)>>);
+
+    impl FragmentShader for Sprites {
+        type Uniforms = ();
+        type Varyings = PointSize;
+
+        fn shade(&self, input: &FragmentInput<PointSize>, _: &()) -> Option<FragmentOutput> {
+            let position = Vec2::new(input.position.x, input.position.y);
+            self.0.borrow_mut().push((position, input.point_coord));
+            None
+        }
+    }
+
+    /// Draws `points` on a 16×16 target, returning what `Sprites` recorded.
+    fn sprites(raster: RasterState, points: Vec<(Vec4, f32)>) -> Vec<(Vec2, Vec2)> {
+        let mut pipeline = Pipeline::new(Sized, Sprites::default());
+        pipeline.state.raster = raster;
+        let mut color = FrameBuffer::new(16, 16);
+        let mut depth = DepthBuffer::new(16, 16);
+        pipeline
+            .draw(
+                &Mesh::new(points, Topology::PointList),
+                &(),
+                &mut RenderTarget::new(&mut color, &mut depth),
+            )
+            .unwrap();
+        pipeline.fragment_shader.0.take()
+    }
+
+    #[test]
+    fn point_coords_run_right_and_down_across_the_point() {
+        let fragments = sprites(
+            RasterState {
+                point_size: 4.0,
+                ..Default::default()
+            },
+            vec![vertex(0.0, 0.0, 0.0, 0.0)],
+        );
+
+        // The point spans pixels 6..10 around the window centre, (8, 8).
+        assert_eq!(fragments.len(), 16);
+        for (position, point_coord) in fragments {
+            let expected = Vec2::new(
+                (position.x - 8.0) / 4.0 + 0.5,
+                (position.y - 8.0) / 4.0 + 0.5,
+            );
+            assert!((point_coord - expected).magnitude() < 1e-6, "{position:?}");
+            assert!((0.0..1.0).contains(&point_coord.x) && (0.0..1.0).contains(&point_coord.y));
+        }
+    }
+
+    #[test]
+    fn world_sized_points_shrink_with_distance() {
+        let raster = RasterState {
+            point_size: 0.5,
+            point_sizing: PointSizing::World { scale: 1.0 },
+            ..Default::default()
+        };
+
+        // Half a unit across fills a quarter of the 16-pixel-high view at
+        // w = 1, and half that at w = 2.
+        let near = sprites(raster, vec![(Vec4::new(0.0, 0.0, 0.0, 1.0), 0.0)]);
+        let far = sprites(raster, vec![(Vec4::new(0.0, 0.0, 0.0, 2.0), 0.0)]);
+        assert_eq!(near.len(), 4 * 4);
+        assert_eq!(far.len(), 2 * 2);
+    }
+
+    #[test]
+    fn varyings_override_the_point_size() {
+        let fragments = sprites(
+            RasterState {
+                point_size: 2.0,
+                ..Default::default()
+            },
+            vec![vertex(-0.5, 0.0, 0.0, 0.0), vertex(0.5, 0.0, 0.0, 6.0)],
+        );
+
+        let left = fragments.iter().filter(|(p, _)| p.x < 8.0).count();
+        let right = fragments.iter().filter(|(p, _)| p.x >= 8.0).count();
+        assert_eq!((left, right), (2 * 2, 6 * 6));
+    }
+
+    /// Keeps per-pipeline state that can't be shared between threads.
+    #[derive(Default)]
+    struct Tally(std::cell::Cell<u32>);
+
+    impl FragmentShader for Tally {
+        type Uniforms = ();
+        type Varyings = f32;
+
+        fn shade(&self, _: &FragmentInput<f32>, _: &()) -> Option<FragmentOutput> {
+            self.0.set(self.0.get() + 1);
+            None
+        }
+    }
+
+    #[test]
+    fn serial_pipelines_need_not_be_sync() {
+        let pipeline = Pipeline::new(Passthrough, Tally::default());
+        let mut color = FrameBuffer::new(4, 4);
+        let mut depth = DepthBuffer::new(4, 4);
+        pipeline
+            .draw(
+                &fullscreen(0.5, 1.0),
+                &(),
+                &mut RenderTarget::new(&mut color, &mut depth),
+            )
+            .unwrap();
+
+        assert_eq!(pipeline.fragment_shader.0.get(), 16);
+        assert_eq!(pipeline.tiling(), None);
+    }
+}
@@ -0,0 +1,725 @@
+use math::Vec2;
+
+use crate::{
+    buffer::Rect,
+    lanes::{F32Lanes, I64Lanes},
+    msaa::SampleCount,
+    pipeline::Viewport,
+    shader::{Varyings, VertexOutput},
+};
+
+// Vertex positions are snapped to a fixed-point grid so edge functions can be
+// evaluated exactly and the fill rule never double-covers a shared edge.
+const SUBPIXEL_BITS: u32 = 8;
+const SUBPIXEL_ONE: i64 = 1 << SUBPIXEL_BITS;
+const SUBPIXEL_HALF: i64 = SUBPIXEL_ONE / 2;
+
+/// Half-open pixel rectangle the rasterizer is restricted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Bounds {
+    pub min_x: usize,
+    pub min_y: usize,
+    pub max_x: usize,
+    pub max_y: usize,
+}
+
+impl Bounds {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            min_x: 0,
+            min_y: 0,
+            max_x: width,
+            max_y: height,
+        }
+    }
+
+    /// Builds bounds from signed pixel coordinates, clamping at zero.
+    pub fn from_signed(min_x: i64, min_y: i64, max_x: i64, max_y: i64) -> Self {
+        Self {
+            min_x: min_x.max(0) as usize,
+            min_y: min_y.max(0) as usize,
+            max_x: max_x.max(0) as usize,
+            max_y: max_y.max(0) as usize,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min_x >= self.max_x || self.min_y >= self.max_y
+    }
+
+    pub fn intersect(&self, other: &Bounds) -> Option<Bounds> {
+        let bounds = Bounds {
+            min_x: self.min_x.max(other.min_x),
+            min_y: self.min_y.max(other.min_y),
+            max_x: self.max_x.min(other.max_x),
+            max_y: self.max_y.min(other.max_y),
+        };
+
+        (!bounds.is_empty()).then_some(bounds)
+    }
+
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds {
+            min_x: self.min_x.min(other.min_x),
+            min_y: self.min_y.min(other.min_y),
+            max_x: self.max_x.max(other.max_x),
+            max_y: self.max_y.max(other.max_y),
+        }
+    }
+
+    pub fn contains(&self, x: i64, y: i64) -> bool {
+        x >= self.min_x as i64
+            && y >= self.min_y as i64
+            && x < self.max_x as i64
+            && y < self.max_y as i64
+    }
+}
+
+impl From<Rect> for Bounds {
+    fn from(rect: Rect) -> Self {
+        Self {
+            min_x: rect.x,
+            min_y: rect.y,
+            max_x: rect.x + rect.width,
+            max_y: rect.y + rect.height,
+        }
+    }
+}
+
+/// A post-clip vertex in window coordinates.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ScreenVertex<V> {
+    pub x:        f32,
+    pub y:        f32,
+    pub z:        f32,
+    pub inv_w:    f32,
+    pub varyings: V,
+}
+
+impl<V: Copy> ScreenVertex<V> {
+    pub fn from_clip(vertex: &VertexOutput<V>, viewport: &Viewport) -> Self {
+        let inv_w = 1.0 / vertex.position.w;
+        let ndc_x = vertex.position.x * inv_w;
+        let ndc_y = vertex.position.y * inv_w;
+        let ndc_z = vertex.position.z * inv_w;
+
+        Self {
+            x: viewport.x + (ndc_x * 0.5 + 0.5) * viewport.width,
+            y: viewport.y + (0.5 - ndc_y * 0.5) * viewport.height,
+            z: viewport.min_depth + (ndc_z * 0.5 + 0.5) * (viewport.max_depth - viewport.min_depth),
+            inv_w,
+            varyings: vertex.varyings,
+        }
+    }
+}
+
+/// A covered pixel produced by the rasterizer.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Fragment<V> {
+    pub x:        usize,
+    pub y:        usize,
+    pub z:        f32,
+    pub inv_w:    f32,
+    pub varyings: V,
+}
+
+fn snap(v: f32) -> i64 {
+    (v * SUBPIXEL_ONE as f32).round() as i64
+}
+
+// Twice the signed area of `a b c`; positive when the triangle winds
+// clockwise as displayed.
+fn edge(a: (i64, i64), b: (i64, i64), c: (i64, i64)) -> i64 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+// Top-left fill rule for an edge of a positively oriented triangle.
+fn is_top_left(a: (i64, i64), b: (i64, i64)) -> bool {
+    let dx = b.0 - a.0;
+    let dy = b.1 - a.1;
+    (dy == 0 && dx > 0) || dy < 0
+}
+
+/// Pixel offsets of the four lanes of a quad: top-left, top-right,
+/// bottom-left, bottom-right.
+pub(crate) const LANE_X: [usize; 4] = [0, 1, 0, 1];
+pub(crate) const LANE_Y: [usize; 4] = [0, 0, 1, 1];
+
+/// A 2×2 block of pixels with its top-left corner at `(x, y)`. Lanes outside
+/// the primitive are cleared in `mask` but still carry varyings, extrapolated
+/// from the primitive's plane, so the quad has derivatives.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Quad<V> {
+    pub x:        usize,
+    pub y:        usize,
+    pub mask:     u8,
+    /// Covered samples of each lane; nonzero exactly for the lanes in `mask`.
+    pub coverage: [u8; 4],
+    /// Depth at the pixel centres, and its slope per pixel for finding the
+    /// depth of the other samples.
+    pub z:        [f32; 4],
+    pub dz_dx:    f32,
+    pub dz_dy:    f32,
+    pub inv_w:    [f32; 4],
+    pub varyings: [V; 4],
+}
+
+/// A triangle ready for scan conversion.
+pub(crate) struct Triangle<V> {
+    vertices: [ScreenVertex<V>; 3],
+    fixed:    [(i64, i64); 3],
+    area:     i64,
+    samples:  SampleCount,
+    /// Whether the triangle winds counter-clockwise in normalized device
+    /// coordinates (y up).
+    pub ccw:  bool,
+}
+
+impl<V: Varyings> Triangle<V> {
+    /// Snaps the vertices and orients the triangle. Returns `None` for
+    /// degenerate triangles that cannot cover any pixel.
+    pub fn setup(vertices: [ScreenVertex<V>; 3], samples: SampleCount) -> Option<Self> {
+        let mut vertices = vertices;
+        let mut fixed = vertices.map(|v| (snap(v.x), snap(v.y)));
+        let mut area = edge(fixed[0], fixed[1], fixed[2]);
+
+        if area == 0 {
+            return None;
+        }
+
+        let ccw = area < 0;
+        if area < 0 {
+            vertices.swap(1, 2);
+            fixed.swap(1, 2);
+            area = -area;
+        }
+
+        Some(Self {
+            vertices,
+            fixed,
+            area,
+            samples,
+            ccw,
+        })
+    }
+
+    pub fn depth_range(&self) -> (f32, f32) {
+        let [a, b, c] = &self.vertices;
+        (a.z.min(b.z).min(c.z), a.z.max(b.z).max(c.z))
+    }
+
+    /// Change in depth per pixel to the right and per pixel down.
+    pub fn depth_slope(&self) -> (f32, f32) {
+        let [a, b, c] = self.fixed;
+        let edges = [(b, c), (c, a), (a, b)];
+        let slope = |step: &dyn Fn((i64, i64), (i64, i64)) -> i64| {
+            (0..3)
+                .map(|e| {
+                    let (p, q) = edges[e];
+                    self.vertices[e].z * (step(p, q) * SUBPIXEL_ONE) as f32
+                })
+                .sum::<f32>()
+                / self.area as f32
+        };
+
+        (slope(&|p, q| -(q.1 - p.1)), slope(&|p, q| q.0 - p.0))
+    }
+
+    /// Pixels with a sample within the snapped bounding box.
+    pub fn bounds(&self) -> Bounds {
+        let [a, b, c] = self.fixed;
+
+        let min_x = a.0.min(b.0).min(c.0);
+        let max_x = a.0.max(b.0).max(c.0);
+        let min_y = a.1.min(b.1).min(c.1);
+        let max_y = a.1.max(b.1).max(c.1);
+
+        // Pixel `p` has its centre at `p + 0.5`. Samples lie within half a
+        // pixel of the centre, so one extra pixel on each side covers them.
+        let margin = (self.samples != SampleCount::X1) as i64;
+        let to_pixel_min =
+            |v: i64| ((v - SUBPIXEL_HALF + SUBPIXEL_ONE - 1) >> SUBPIXEL_BITS) - margin;
+        let to_pixel_max = |v: i64| ((v - SUBPIXEL_HALF) >> SUBPIXEL_BITS) + 1 + margin;
+
+        Bounds::from_signed(
+            to_pixel_min(min_x),
+            to_pixel_min(min_y),
+            to_pixel_max(max_x),
+            to_pixel_max(max_y),
+        )
+    }
+
+    /// Invokes `emit` for every 2×2 quad, aligned to even pixel coordinates,
+    /// with at least one sample inside both the triangle and `bounds`. Quads
+    /// are visited in row-major order.
+    pub fn rasterize<F>(&self, bounds: Bounds, mut emit: F)
+    where
+        F: FnMut(Quad<V>),
+    {
+        let Some(covered) = self.bounds().intersect(&bounds) else {
+            return;
+        };
+
+        let [a, b, c] = self.fixed;
+        let edges = [(b, c), (c, a), (a, b)];
+        let bias = edges.map(|(p, q)| if is_top_left(p, q) { 0 } else { -1 });
+        let step_x = edges.map(|(p, q)| -(q.1 - p.1) * SUBPIXEL_ONE);
+        let step_y = edges.map(|(p, q)| (q.0 - p.0) * SUBPIXEL_ONE);
+
+        let qx0 = covered.min_x & !1;
+        let qy0 = covered.min_y & !1;
+        let origin = (
+            ((qx0 as i64) << SUBPIXEL_BITS) + SUBPIXEL_HALF,
+            ((qy0 as i64) << SUBPIXEL_BITS) + SUBPIXEL_HALF,
+        );
+
+        // Edge values for the four lanes of the first quad, and per-quad steps.
+        let mut row: [I64Lanes; 3] = std::array::from_fn(|e| {
+            let (p, q) = edges[e];
+            let w = edge(p, q, origin);
+            I64Lanes(LANE_X.map(|lx| lx as i64 * step_x[e]))
+                + I64Lanes(LANE_Y.map(|ly| ly as i64 * step_y[e]))
+                + I64Lanes::splat(w)
+        });
+        let quad_x = step_x.map(|s| I64Lanes::splat(s * 2));
+        let quad_y = step_y.map(|s| I64Lanes::splat(s * 2));
+
+        // Edge value offsets of each sample from the pixel centre. Sample
+        // positions are in sixteenths of a pixel.
+        let positions = self.samples.positions();
+        let multisampled = positions.len() > 1;
+        let sample_offsets: [[i64; 8]; 3] = std::array::from_fn(|e| {
+            let mut offsets = [0; 8];
+            for (offset, &(sx, sy)) in offsets.iter_mut().zip(positions) {
+                *offset = (step_x[e] * sx as i64 + step_y[e] * sy as i64) / 16;
+            }
+            offsets
+        });
+
+        let inv_area = F32Lanes::splat(1.0 / self.area as f32);
+        let [v0, v1, v2] = &self.vertices;
+        let (dz_dx, dz_dy) = self.depth_slope();
+        let min_inv_w = MIN_INV_W_FRACTION * v0.inv_w.min(v1.inv_w).min(v2.inv_w);
+
+        for y in (qy0..covered.max_y).step_by(2) {
+            let mut w = row;
+
+            for x in (qx0..covered.max_x).step_by(2) {
+                let mut coverage = if multisampled {
+                    std::array::from_fn(|lane| {
+                        (0..positions.len()).fold(0, |coverage, s| {
+                            let inside =
+                                (0..3).all(|e| w[e].0[lane] + sample_offsets[e][s] + bias[e] >= 0);
+                            coverage | ((inside as u8) << s)
+                        })
+                    })
+                } else {
+                    let mask = (w[0] + I64Lanes::splat(bias[0])).non_negative_mask()
+                        & (w[1] + I64Lanes::splat(bias[1])).non_negative_mask()
+                        & (w[2] + I64Lanes::splat(bias[2])).non_negative_mask();
+                    std::array::from_fn(|lane| (mask >> lane) & 1)
+                };
+
+                let mut mask = 0;
+                for lane in 0..4 {
+                    let (lx, ly) = (x + LANE_X[lane], y + LANE_Y[lane]);
+                    if !covered.contains(lx as i64, ly as i64) {
+                        coverage[lane] = 0;
+                    }
+                    mask |= ((coverage[lane] != 0) as u8) << lane;
+                }
+
+                if mask != 0 {
+                    let l = w.map(|e| e.to_f32() * inv_area);
+                    let z = l[0] * F32Lanes::splat(v0.z)
+                        + l[1] * F32Lanes::splat(v1.z)
+                        + l[2] * F32Lanes::splat(v2.z);
+
+                    // Lanes centred outside the triangle, helper lanes among
+                    // them, extrapolate linearly so derivatives match across
+                    // edges. Only where `1 / w` would extrapolate to about
+                    // zero or below, near silhouettes, are their weights
+                    // clamped onto the triangle to keep varyings finite.
+                    let inv_w_of = |l: &[F32Lanes; 3]| {
+                        l[0] * F32Lanes::splat(v0.inv_w)
+                            + l[1] * F32Lanes::splat(v1.inv_w)
+                            + l[2] * F32Lanes::splat(v2.inv_w)
+                    };
+                    let mut inv_w = inv_w_of(&l);
+                    let mut l = l;
+                    if inv_w.0.iter().any(|&inv_w| inv_w <= min_inv_w) {
+                        l = clamp_weights(l, inv_w.0.map(|inv_w| inv_w <= min_inv_w));
+                        inv_w = inv_w_of(&l);
+                    }
+
+                    // Perspective-correct weights, for helper lanes as well
+                    // so the quad has derivatives.
+                    let pw = [
+                        l[0] * F32Lanes::splat(v0.inv_w) / inv_w,
+                        l[1] * F32Lanes::splat(v1.inv_w) / inv_w,
+                        l[2] * F32Lanes::splat(v2.inv_w) / inv_w,
+                    ];
+
+                    emit(Quad {
+                        x,
+                        y,
+                        mask,
+                        coverage,
+                        z: z.0,
+                        dz_dx,
+                        dz_dy,
+                        inv_w: inv_w.0,
+                        varyings: std::array::from_fn(|lane| {
+                            V::barycentric(
+                                v0.varyings,
+                                v1.varyings,
+                                v2.varyings,
+                                [pw[0].0[lane], pw[1].0[lane], pw[2].0[lane]],
+                            )
+                        }),
+                    });
+                }
+
+                for e in 0..3 {
+                    w[e] = w[e] + quad_x[e];
+                }
+            }
+
+            for e in 0..3 {
+                row[e] = row[e] + quad_y[e];
+            }
+        }
+    }
+}
+
+/// Smallest `1 / w` a lane may extrapolate to, as a fraction of the
+/// smallest among the triangle's vertices, before it's clamped.
+const MIN_INV_W_FRACTION: f32 = 1e-3;
+
+/// Pulls the barycentric weights of the lanes set in `lanes` back onto the
+/// triangle, by dropping negative weights and renormalizing the rest.
+fn clamp_weights(weights: [F32Lanes; 3], lanes: [bool; 4]) -> [F32Lanes; 3] {
+    let mut clamped = weights;
+
+    for lane in (0..4).filter(|&lane| lanes[lane]) {
+        let lane_weights = weights.map(|w| w.0[lane].max(0.0));
+        // The weights sum to one, so at least one is positive.
+        let sum: f32 = lane_weights.iter().sum();
+        for (w, lane_weight) in clamped.iter_mut().zip(lane_weights) {
+            w.0[lane] = lane_weight / sum;
+        }
+    }
+
+    clamped
+}
+
+/// Conservative pixel bounds of a line.
+pub(crate) fn line_bounds<V>(a: &ScreenVertex<V>, b: &ScreenVertex<V>) -> Bounds {
+    Bounds::from_signed(
+        a.x.min(b.x).floor() as i64 - 1,
+        a.y.min(b.y).floor() as i64 - 1,
+        a.x.max(b.x).floor() as i64 + 2,
+        a.y.max(b.y).floor() as i64 + 2,
+    )
+}
+
+/// Rasterizes a line with a DDA along its major axis. The pixel containing
+/// the end point is left out so connected strips don't touch it twice.
+pub(crate) fn rasterize_line<V, F>(
+    a: &ScreenVertex<V>,
+    b: &ScreenVertex<V>,
+    bounds: Bounds,
+    mut emit: F,
+) where
+    V: Varyings,
+    F: FnMut(Fragment<V>),
+{
+    let dx = b.x - a.x;
+    let dy = b.y - a.y;
+    let x_major = dx.abs() >= dy.abs();
+    let (start, end, delta) = if x_major {
+        (a.x, b.x, dx)
+    } else {
+        (a.y, b.y, dy)
+    };
+
+    if delta == 0.0 {
+        return;
+    }
+
+    // Pixel centres `p + 0.5` on the major axis between the two end points.
+    let (first, last) = if delta > 0.0 {
+        ((start - 0.5).ceil() as i64, (end - 0.5).ceil() as i64)
+    } else {
+        (
+            (end - 0.5).floor() as i64 + 1,
+            (start - 0.5).floor() as i64 + 1,
+        )
+    };
+
+    for major in first..last {
+        let t = (major as f32 + 0.5 - start) / delta;
+        let minor = if x_major { a.y + t * dy } else { a.x + t * dx }.floor() as i64;
+        let (x, y) = if x_major {
+            (major, minor)
+        } else {
+            (minor, major)
+        };
+
+        if !bounds.contains(x, y) {
+            continue;
+        }
+
+        let inv_w = a.inv_w + (b.inv_w - a.inv_w) * t;
+        emit(Fragment {
+            x: x as usize,
+            y: y as usize,
+            z: a.z + (b.z - a.z) * t,
+            inv_w,
+            varyings: a.varyings.lerp(b.varyings, t * b.inv_w / inv_w),
+        });
+    }
+}
+
+/// Pixels covered by a point of `size` pixels: every pixel whose centre falls
+/// inside the screen-aligned square centred on the vertex.
+pub(crate) fn point_bounds<V>(v: &ScreenVertex<V>, size: f32) -> Bounds {
+    let half = size.max(1.0) * 0.5;
+
+    Bounds::from_signed(
+        (v.x - half - 0.5).ceil() as i64,
+        (v.y - half - 0.5).ceil() as i64,
+        (v.x + half - 0.5).ceil() as i64,
+        (v.y + half - 0.5).ceil() as i64,
+    )
+}
+
+/// Rasterizes a point as a screen-aligned square of `size` pixels, emitting
+/// each fragment with its sample coverage and its position across the
+/// square. With `round` sample positions, only the samples inside the
+/// inscribed disc are covered; otherwise every sample is. The disc reaches
+/// at least the nearest pixel centre wherever the point lies, so small round
+/// points don't vanish.
+pub(crate) fn rasterize_point<V, F>(
+    v: &ScreenVertex<V>,
+    size: f32,
+    round: Option<SampleCount>,
+    bounds: Bounds,
+    mut emit: F,
+) where
+    V: Varyings,
+    F: FnMut(Fragment<V>, u8, Vec2),
+{
+    let Some(covered) = point_bounds(v, size).intersect(&bounds) else {
+        return;
+    };
+
+    let size = size.max(1.0);
+    let half = size * 0.5;
+    // No pixel centre is farther than `sqrt(0.5)` from the point.
+    let radius_squared = (half * half).max(0.5);
+
+    for y in covered.min_y..covered.max_y {
+        for x in covered.min_x..covered.max_x {
+            let (cx, cy) = (x as f32 + 0.5, y as f32 + 0.5);
+
+            let coverage = match round {
+                Some(samples) => (0..samples.count()).fold(0, |coverage, s| {
+                    let (dx, dy) = samples.offset(s);
+                    let (px, py) = (cx + dx - v.x, cy + dy - v.y);
+                    coverage | (((px * px + py * py <= radius_squared) as u8) << s)
+                }),
+                None => u8::MAX,
+            };
+            if coverage == 0 {
+                continue;
+            }
+
+            let point_coord = Vec2::new((cx - v.x) / size + 0.5, (cy - v.y) / size + 0.5);
+            emit(
+                Fragment {
+                    x,
+                    y,
+                    z: v.z,
+                    inv_w: v.inv_w,
+                    varyings: v.varyings,
+                },
+                coverage,
+                point_coord,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(x: f32, y: f32, inv_w: f32) -> ScreenVertex<f32> {
+        ScreenVertex {
+            x,
+            y,
+            z: 0.5,
+            inv_w,
+            varyings: x,
+        }
+    }
+
+    fn coverage(triangle: &Triangle<f32>, bounds: Bounds) -> Vec<u8> {
+        let mut covered = vec![0; bounds.max_x * bounds.max_y];
+        triangle.rasterize(bounds, |quad| {
+            for lane in 0..4 {
+                if quad.mask & (1 << lane) != 0 {
+                    covered[(quad.y + LANE_Y[lane]) * bounds.max_x + quad.x + LANE_X[lane]] += 1;
+                }
+            }
+        });
+        covered
+    }
+
+    #[test]
+    fn shared_edges_are_covered_once() {
+        let bounds = Bounds::new(16, 16);
+        let corners = [
+            vertex(1.3, 0.7, 1.0),
+            vertex(14.6, 2.1, 1.0),
+            vertex(12.2, 15.4, 1.0),
+            vertex(0.4, 13.9, 1.0),
+        ];
+        let [a, b, c, d] = corners;
+
+        let first = Triangle::setup([a, b, c], SampleCount::X1).unwrap();
+        let second = Triangle::setup([a, c, d], SampleCount::X1).unwrap();
+        let total: Vec<u8> = coverage(&first, bounds)
+            .iter()
+            .zip(coverage(&second, bounds))
+            .map(|(a, b)| a + b)
+            .collect();
+
+        assert!(total.iter().all(|&n| n <= 1));
+        assert!(total.iter().filter(|&&n| n == 1).count() > 150);
+    }
+
+    #[test]
+    fn quads_outside_triangle_keep_finite_varyings() {
+        // Two far vertices, as at a silhouette seen edge-on. Past the long
+        // edge, `1 / w` extrapolates below zero.
+        let triangle = Triangle::setup(
+            [
+                vertex(1.0, 1.0, 1.0),
+                vertex(3.0, 1.0, 1e-6),
+                vertex(1.0, 3.0, 1e-6),
+            ],
+            SampleCount::X1,
+        )
+        .unwrap();
+
+        let mut quads = 0;
+        triangle.rasterize(Bounds::new(4, 4), |quad| {
+            quads += 1;
+            for lane in 0..4 {
+                assert!(quad.inv_w[lane] > 0.0, "{quad:?}");
+                assert!(quad.varyings[lane].is_finite(), "{quad:?}");
+                assert!((0.999..=3.001).contains(&quad.varyings[lane]), "{quad:?}");
+            }
+        });
+        assert!(quads > 0);
+    }
+
+    #[test]
+    fn derivatives_are_constant_across_interior_edges() {
+        // Two triangles of an affine square, with the screen position as
+        // varyings. Every quad, including those straddling the diagonal,
+        // steps by exactly one pixel per lane.
+        let corner = |x: f32, y: f32| ScreenVertex {
+            x,
+            y,
+            z: 0.5,
+            inv_w: 0.5,
+            varyings: Vec2::new(x, y),
+        };
+        let [a, b, c, d] = [
+            corner(0.0, 0.0),
+            corner(64.0, 0.0),
+            corner(64.0, 64.0),
+            corner(0.0, 64.0),
+        ];
+
+        let mut quads = 0;
+        for vertices in [[a, b, c], [a, c, d]] {
+            let triangle = Triangle::setup(vertices, SampleCount::X1).unwrap();
+            triangle.rasterize(Bounds::new(64, 64), |quad| {
+                quads += 1;
+                let v = quad.varyings;
+                for (ddx, ddy) in [(v[1] - v[0], v[2] - v[0]), (v[3] - v[2], v[3] - v[1])] {
+                    assert!((ddx - Vec2::new(1.0, 0.0)).magnitude() < 1e-4, "{quad:?}");
+                    assert!((ddy - Vec2::new(0.0, 1.0)).magnitude() < 1e-4, "{quad:?}");
+                }
+            });
+        }
+        assert!(quads > 32 * 32);
+    }
+
+    fn point_coverage(x: f32, y: f32, size: f32, round: Option<SampleCount>) -> Vec<u8> {
+        let bounds = Bounds::new(20, 20);
+        let mut covered = vec![0; 20 * 20];
+        rasterize_point(
+            &vertex(x, y, 1.0),
+            size,
+            round,
+            bounds,
+            |fragment, coverage, _| {
+                covered[fragment.y * 20 + fragment.x] = coverage;
+            },
+        );
+        covered
+    }
+
+    #[test]
+    fn round_points_cover_the_inscribed_disc() {
+        let square = point_coverage(8.0, 8.0, 4.0, None);
+        let round = point_coverage(8.0, 8.0, 4.0, Some(SampleCount::X4));
+        let full = SampleCount::X4.full_mask();
+
+        // The same 4×4 pixels, fully covered in the middle and partly in
+        // the corners.
+        for y in 0..20 {
+            for x in 0..20 {
+                let i = y * 20 + x;
+                assert_eq!(square[i] != 0, round[i] != 0, "({x}, {y})");
+                match (x, y) {
+                    (7..=8, 6..=9) | (6..=9, 7..=8) => assert_eq!(round[i], full),
+                    (6 | 9, 6 | 9) => assert!(round[i] != 0 && round[i] != full),
+                    _ => {}
+                }
+            }
+        }
+        assert!(square.iter().all(|&c| c == 0 || c == u8::MAX));
+    }
+
+    #[test]
+    fn small_round_points_cover_what_square_ones_do() {
+        for size in [0.5, 1.0, 1.2, 1.5] {
+            for (x, y) in [(10.0, 10.0), (10.5, 10.5), (10.3, 10.9)] {
+                let square = point_coverage(x, y, size, None);
+                let round = point_coverage(x, y, size, Some(SampleCount::X1));
+                let count = |covered: &[u8]| covered.iter().filter(|&&c| c != 0).count();
+                assert!(count(&round) >= 1, "{size} at ({x}, {y})");
+                assert_eq!(count(&round), count(&square), "{size} at ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn depth_slope_is_per_pixel() {
+        let mut a = vertex(0.0, 0.0, 1.0);
+        let mut b = vertex(8.0, 0.0, 1.0);
+        let mut c = vertex(0.0, 4.0, 1.0);
+        (a.z, b.z, c.z) = (0.0, 0.5, 1.0);
+
+        let (dz_dx, dz_dy) = Triangle::setup([a, b, c], SampleCount::X1)
+            .unwrap()
+            .depth_slope();
+        assert!((dz_dx - 0.0625).abs() < 1e-6 && (dz_dy - 0.25).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,192 @@
+use math::Vec4;
+
+/// Reconstruction filter used when resizing an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kernel {
+    /// Takes the source texel under each destination texel's centre.
+    Nearest,
+    /// Averages the source texels under each destination texel.
+    Box,
+    /// Tent filter; interpolates linearly between texels when enlarging.
+    Bilinear,
+    /// Catmull-Rom cubic, sharper than bilinear without ringing much.
+    Bicubic,
+    /// Kaiser-windowed sinc, three lobes wide.
+    Kaiser,
+    /// Lanczos-windowed sinc, three lobes wide.
+    Lanczos,
+}
+
+const LOBES: f32 = 3.0;
+const KAISER_ALPHA: f32 = 4.0;
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        return 1.0;
+    }
+
+    let x = x * std::f32::consts::PI;
+    x.sin() / x
+}
+
+// Zeroth-order modified Bessel function of the first kind, by its series.
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x * 0.5;
+
+    for k in 1..32 {
+        term *= (half / k as f32) * (half / k as f32);
+        sum += term;
+        if term < sum * 1e-7 {
+            break;
+        }
+    }
+
+    sum
+}
+
+impl Kernel {
+    /// Half-width of the kernel, in destination texels.
+    fn support(self) -> f32 {
+        match self {
+            Kernel::Nearest | Kernel::Box => 0.5,
+            Kernel::Bilinear => 1.0,
+            Kernel::Bicubic => 2.0,
+            Kernel::Kaiser | Kernel::Lanczos => LOBES,
+        }
+    }
+
+    fn weight(self, t: f32) -> f32 {
+        match self {
+            Kernel::Nearest | Kernel::Box => (t.abs() <= 0.5) as u8 as f32,
+            Kernel::Bilinear => (1.0 - t.abs()).max(0.0),
+            Kernel::Bicubic => {
+                let t = t.abs();
+                if t < 1.0 {
+                    (1.5 * t - 2.5) * t * t + 1.0
+                } else if t < 2.0 {
+                    ((-0.5 * t + 2.5) * t - 4.0) * t + 2.0
+                } else {
+                    0.0
+                }
+            }
+            Kernel::Kaiser => {
+                let r = t / LOBES;
+                if r.abs() >= 1.0 {
+                    return 0.0;
+                }
+                sinc(t) * bessel_i0(KAISER_ALPHA * (1.0 - r * r).sqrt()) / bessel_i0(KAISER_ALPHA)
+            }
+            Kernel::Lanczos => {
+                if t.abs() >= LOBES {
+                    return 0.0;
+                }
+                sinc(t) * sinc(t / LOBES)
+            }
+        }
+    }
+}
+
+/// Weighted source texels for every destination texel along one axis.
+fn weights(kernel: Kernel, src: usize, dst: usize) -> Vec<Vec<(usize, f32)>> {
+    // Kernels are evaluated in destination texels when shrinking so they
+    // filter out frequencies the destination can't hold.
+    let scale = src as f32 / dst as f32;
+    let stretch = scale.max(1.0);
+    let radius = kernel.support() * stretch;
+
+    (0..dst)
+        .map(|i| {
+            let centre = (i as f32 + 0.5) * scale;
+            if kernel == Kernel::Nearest {
+                return vec![((centre as usize).min(src - 1), 1.0)];
+            }
+
+            let first = (centre - radius).floor() as i64;
+            let last = (centre + radius).ceil() as i64;
+
+            let mut taps: Vec<(usize, f32)> = (first..=last)
+                .map(|j| {
+                    let t = (j as f32 + 0.5 - centre) / stretch;
+                    (j.clamp(0, src as i64 - 1) as usize, kernel.weight(t))
+                })
+                .filter(|&(_, w)| w != 0.0)
+                .collect();
+
+            let total: f32 = taps.iter().map(|&(_, w)| w).sum();
+            if total != 0.0 {
+                for (_, w) in &mut taps {
+                    *w /= total;
+                }
+            }
+
+            taps
+        })
+        .collect()
+}
+
+/// Resizes a row-major `width`×`height` image with a separable kernel. Texels
+/// outside the image repeat the edge.
+pub fn resample(
+    src: &[Vec4],
+    width: usize,
+    height: usize,
+    dst_width: usize,
+    dst_height: usize,
+    kernel: Kernel,
+) -> Vec<Vec4> {
+    let horizontal = weights(kernel, width, dst_width);
+    let vertical = weights(kernel, height, dst_height);
+
+    let mut rows = vec![Vec4::ZERO; dst_width * height];
+    for y in 0..height {
+        let row = &src[y * width..(y + 1) * width];
+        for (x, taps) in horizontal.iter().enumerate() {
+            rows[y * dst_width + x] = taps
+                .iter()
+                .fold(Vec4::ZERO, |sum, &(j, w)| sum + row[j] * w);
+        }
+    }
+
+    let mut dst = vec![Vec4::ZERO; dst_width * dst_height];
+    for (y, taps) in vertical.iter().enumerate() {
+        for x in 0..dst_width {
+            dst[y * dst_width + x] = taps
+                .iter()
+                .fold(Vec4::ZERO, |sum, &(j, w)| sum + rows[j * dst_width + x] * w);
+        }
+    }
+
+    dst
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernels_preserve_flat_images() {
+        let flat = vec![Vec4::new(0.25, 0.5, 0.75, 1.0); 6 * 5];
+
+        for kernel in [
+            Kernel::Nearest,
+            Kernel::Box,
+            Kernel::Bilinear,
+            Kernel::Bicubic,
+            Kernel::Kaiser,
+            Kernel::Lanczos,
+        ] {
+            for (width, height) in [(13, 11), (3, 2), (1, 1)] {
+                let resized = resample(&flat, 6, 5, width, height, kernel);
+                assert_eq!(resized.len(), width * height);
+                assert!(
+                    resized
+                        .iter()
+                        .all(|&c| Vec4::dot(c - flat[0], c - flat[0]) < 1e-10),
+                    "{kernel:?} {width}×{height}"
+                );
+            }
+        }
+    }
+}
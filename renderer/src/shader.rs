@@ -0,0 +1,261 @@
+use math::{Vec2, Vec3, Vec4};
+
+/// Per-vertex values written by a [`VertexShader`] and interpolated across a
+/// primitive before being handed to the [`FragmentShader`].
+///
+/// Interpolation only needs a vector space, so implementors provide addition
+/// and scaling and get linear and barycentric blends for free.
+pub trait Varyings: Copy {
+    fn add(self, other: Self) -> Self;
+    fn scale(self, factor: f32) -> Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.add(other.scale(-1.0))
+    }
+
+    fn lerp(self, other: Self, t: f32) -> Self {
+        self.scale(1.0 - t).add(other.scale(t))
+    }
+
+    fn barycentric(a: Self, b: Self, c: Self, weights: [f32; 3]) -> Self {
+        a.scale(weights[0])
+            .add(b.scale(weights[1]))
+            .add(c.scale(weights[2]))
+    }
+
+    /// The surface normal among these varyings, for
+    /// [`DebugMode::Normals`](crate::debug::DebugMode::Normals) unless the
+    /// pipeline's [`DebugFields`](crate::debug::DebugFields) say otherwise.
+    fn debug_normal(&self) -> Option<Vec3> {
+        None
+    }
+
+    /// The texture coordinates among these varyings, for the debug modes
+    /// that show them, under the same rule.
+    fn debug_uv(&self) -> Option<Vec2> {
+        None
+    }
+
+    /// Size of the point these varyings are output for, replacing
+    /// [`RasterState::point_size`](crate::pipeline::RasterState::point_size)
+    /// so each point of a draw can have its own. Ignored for other
+    /// primitives.
+    fn point_size(&self) -> Option<f32> {
+        None
+    }
+}
+
+impl Varyings for () {
+    fn add(self, _: ()) {}
+
+    fn scale(self, _: f32) {}
+}
+
+impl Varyings for f32 {
+    fn add(self, other: f32) -> f32 {
+        self + other
+    }
+
+    fn scale(self, factor: f32) -> f32 {
+        self * factor
+    }
+}
+
+macro_rules! impl_varyings_vec {
+    ($($t:ty),*) => {
+        $(
+            impl Varyings for $t {
+                fn add(self, other: $t) -> $t {
+                    self + other
+                }
+
+                fn scale(self, factor: f32) -> $t {
+                    self * factor
+                }
+            }
+        )*
+    };
+}
+
+impl_varyings_vec!(Vec2, Vec3, Vec4);
+
+macro_rules! impl_varyings_tuple {
+    ($($name:ident $idx:tt),*) => {
+        impl<$($name: Varyings),*> Varyings for ($($name,)*) {
+            fn add(self, other: Self) -> Self {
+                ($(self.$idx.add(other.$idx),)*)
+            }
+
+            fn scale(self, factor: f32) -> Self {
+                ($(self.$idx.scale(factor),)*)
+            }
+        }
+    };
+}
+
+impl_varyings_tuple!(A 0);
+impl_varyings_tuple!(A 0, B 1);
+impl_varyings_tuple!(A 0, B 1, C 2);
+impl_varyings_tuple!(A 0, B 1, C 2, D 3);
+impl_varyings_tuple!(A 0, B 1, C 2, D 3, E 4);
+impl_varyings_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+
+impl<T: Varyings, const N: usize> Varyings for [T; N] {
+    fn add(self, other: Self) -> Self {
+        std::array::from_fn(|i| self[i].add(other[i]))
+    }
+
+    fn scale(self, factor: f32) -> Self {
+        self.map(|v| v.scale(factor))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct VertexOutput<V> {
+    /// Clip-space position, divided by `w` after clipping.
+    pub position: Vec4,
+    pub varyings: V,
+}
+
+/// Transforms a single input vertex into clip space.
+pub trait VertexShader {
+    type Vertex;
+    type Uniforms;
+    type Varyings: Varyings;
+
+    fn shade(
+        &self,
+        vertex: &Self::Vertex,
+        uniforms: &Self::Uniforms,
+    ) -> VertexOutput<Self::Varyings>;
+
+    /// Shades a vertex of the given instance of an instanced draw. Shaders
+    /// that don't read the instance index can rely on the default.
+    fn shade_instance(
+        &self,
+        vertex: &Self::Vertex,
+        _instance: u32,
+        uniforms: &Self::Uniforms,
+    ) -> VertexOutput<Self::Varyings> {
+        self.shade(vertex, uniforms)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FragmentInput<V> {
+    /// Window-space position of the pixel centre; `z` is the interpolated
+    /// depth and `w` is `1 / w_clip`.
+    pub position:     Vec4,
+    pub front_facing: bool,
+    pub varyings:     V,
+    /// Screen-space derivatives of `varyings` across the pixel's 2×2 quad.
+    ///
+    /// Pass them to
+    /// [`Texture2D::sample_grad`](crate::texture::Texture2D::sample_grad) to
+    /// pick a mip level; `sample` always reads the base level. Lines and
+    /// points aren't rasterized in quads, so theirs are zero; shaders drawing
+    /// textured lines or points pick a level with `sample_level` instead.
+    pub ddx:          V,
+    pub ddy:          V,
+    /// Where the pixel centre lies across a point sprite, from `(0, 0)` at
+    /// its top-left corner to `(1, 1)` at its bottom-right, stepping by one
+    /// over the point's size per pixel. Zero for triangles and lines.
+    pub point_coord:  Vec2,
+}
+
+/// Most color attachments a render target can have.
+pub const MAX_COLOR_ATTACHMENTS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FragmentOutput {
+    /// Linear RGBA color in `[0, 1]`, written to the first color attachment.
+    pub color: Vec4,
+    /// Replaces the interpolated depth when set.
+    pub depth: Option<f32>,
+    /// Colors for the attachments after the first, of which only the first
+    /// `written` are used.
+    others:    [Vec4; MAX_COLOR_ATTACHMENTS - 1],
+    written:   u8,
+}
+
+impl FragmentOutput {
+    /// Writes `color` to the first color attachment and leaves any others
+    /// untouched.
+    pub fn new(color: Vec4) -> Self {
+        Self {
+            color,
+            depth: None,
+            others: [Vec4::ZERO; MAX_COLOR_ATTACHMENTS - 1],
+            written: 0,
+        }
+    }
+
+    /// Also writes `colors` to the attachments after the first, in order.
+    /// Attachments past the end of `colors` keep their contents, and colors
+    /// past the last attachment are ignored.
+    pub fn with_attachments(mut self, colors: &[Vec4]) -> Self {
+        let written = colors.len().min(self.others.len());
+        self.others[..written].copy_from_slice(&colors[..written]);
+        self.written = written as u8;
+        self
+    }
+
+    /// The colors written, in attachment order.
+    pub fn colors(&self) -> impl Iterator<Item = Vec4> + '_ {
+        std::iter::once(self.color).chain(self.others[..self.written as usize].iter().copied())
+    }
+}
+
+/// Computes the color of a covered pixel. Returning `None` discards the
+/// fragment, leaving the color and depth targets untouched.
+pub trait FragmentShader {
+    type Uniforms;
+    type Varyings: Varyings;
+
+    /// Whether `shade` can return a depth. Shaders that never do should set
+    /// it to `false`, so their fragments are depth tested before shading and
+    /// skipped when hidden.
+    const WRITES_DEPTH: bool = true;
+
+    fn shade(
+        &self,
+        input: &FragmentInput<Self::Varyings>,
+        uniforms: &Self::Uniforms,
+    ) -> Option<FragmentOutput>;
+
+    /// Shades the lanes of a 2×2 quad whose bits are set in `mask`, in the
+    /// order top-left, top-right, bottom-left, bottom-right. Override to
+    /// shade all four lanes at once.
+    fn shade_quad(
+        &self,
+        inputs: &[FragmentInput<Self::Varyings>; 4],
+        mask: u8,
+        uniforms: &Self::Uniforms,
+    ) -> [Option<FragmentOutput>; 4] {
+        std::array::from_fn(|lane| {
+            if mask & (1 << lane) != 0 {
+                self.shade(&inputs[lane], uniforms)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compound_varyings_interpolate_per_field() {
+        let a = (1.0, Vec2::new(0.0, 2.0), [4.0, 8.0]);
+        let b = (3.0, Vec2::new(2.0, 0.0), [0.0, 0.0]);
+
+        assert_eq!(a.lerp(b, 0.5), (2.0, Vec2::new(1.0, 1.0), [2.0, 4.0]));
+        assert_eq!(a.sub(b), (-2.0, Vec2::new(-2.0, 2.0), [4.0, 8.0]));
+        assert_eq!(
+            Varyings::barycentric(a, b, b, [0.5, 0.25, 0.25]),
+            (2.0, Vec2::new(1.0, 1.0), [2.0, 4.0])
+        );
+    }
+}
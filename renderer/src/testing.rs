@@ -0,0 +1,45 @@
+//! Shaders and meshes shared by the unit tests.
+
+use math::{Mat4, Vec4};
+
+use crate::{
+    mesh::{Mesh, Topology},
+    shader::{VertexOutput, VertexShader},
+};
+
+/// Passes clip-space positions through, also as the varyings.
+pub(crate) struct Positions;
+
+impl VertexShader for Positions {
+    type Vertex = Vec4;
+    type Uniforms = ();
+    type Varyings = Vec4;
+
+    fn shade(&self, vertex: &Vec4, _: &()) -> VertexOutput<Vec4> {
+        VertexOutput {
+            position: *vertex,
+            varyings: *vertex,
+        }
+    }
+}
+
+pub(crate) fn identity() -> Mat4 {
+    Mat4 {
+        c0: Vec4::new(1.0, 0.0, 0.0, 0.0),
+        c1: Vec4::new(0.0, 1.0, 0.0, 0.0),
+        c2: Vec4::new(0.0, 0.0, 1.0, 0.0),
+        c3: Vec4::new(0.0, 0.0, 0.0, 1.0),
+    }
+}
+
+/// A triangle covering the whole target at depth 0.
+pub(crate) fn fullscreen() -> Mesh<Vec4> {
+    Mesh::new(
+        vec![
+            Vec4::new(-1.0, -1.0, 0.0, 1.0),
+            Vec4::new(3.0, -1.0, 0.0, 1.0),
+            Vec4::new(-1.0, 3.0, 0.0, 1.0),
+        ],
+        Topology::TriangleList,
+    )
+}
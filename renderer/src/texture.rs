@@ -0,0 +1,486 @@
+use math::{Vec2, Vec4};
+
+use crate::{
+    buffer::{
+        ops::{ToArray, ToArrayMut},
+        Buffer, BufferError, FrameBuffer,
+    },
+    color,
+    msaa::SampleCount,
+    resample::{self, Kernel},
+};
+
+/// How coordinates outside `[0, 1]` are mapped onto the texture.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AddressMode {
+    #[default]
+    Repeat,
+    MirrorRepeat,
+    ClampToEdge,
+    /// Texels outside the texture read the sampler's `border_color`.
+    ClampToBorder,
+}
+
+impl AddressMode {
+    /// Maps texel coordinate `i` into `0..size`, or `None` for the border.
+    fn resolve(self, i: i64, size: usize) -> Option<usize> {
+        let size = size as i64;
+
+        match self {
+            AddressMode::Repeat => Some(i.rem_euclid(size) as usize),
+            AddressMode::MirrorRepeat => {
+                let i = i.rem_euclid(size * 2);
+                Some(if i < size { i } else { size * 2 - 1 - i } as usize)
+            }
+            AddressMode::ClampToEdge => Some(i.clamp(0, size - 1) as usize),
+            AddressMode::ClampToBorder => (0..size).contains(&i).then_some(i as usize),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FilterMode {
+    #[default]
+    Nearest,
+    /// Bilinear interpolation between the four closest texels.
+    Linear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampler {
+    pub address_u:      AddressMode,
+    pub address_v:      AddressMode,
+    /// Filter used when a texel covers more than a pixel.
+    pub mag_filter:     FilterMode,
+    /// Filter used when a pixel covers more than a texel.
+    pub min_filter:     FilterMode,
+    /// Filter between mip levels; `Linear` gives trilinear filtering.
+    pub mipmap_filter:  FilterMode,
+    /// Added to the level of detail computed from derivatives.
+    pub lod_bias:       f32,
+    pub min_lod:        f32,
+    pub max_lod:        f32,
+    /// Most samples taken along the major axis of an anisotropic footprint.
+    /// One disables anisotropic filtering.
+    pub max_anisotropy: u32,
+    pub border_color:   Vec4,
+}
+
+impl Sampler {
+    pub fn new(filter: FilterMode, address: AddressMode) -> Self {
+        Self {
+            address_u:      address,
+            address_v:      address,
+            mag_filter:     filter,
+            min_filter:     filter,
+            mipmap_filter:  filter,
+            lod_bias:       0.0,
+            min_lod:        0.0,
+            max_lod:        f32::MAX,
+            max_anisotropy: 1,
+            border_color:   Vec4::ZERO,
+        }
+    }
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self::new(FilterMode::Nearest, AddressMode::Repeat)
+    }
+}
+
+/// An image sampled by shaders, with an optional chain of mip levels each
+/// half the size of the previous one. `(0, 0)` is the top-left corner of the
+/// first row of the image and `(1, 1)` the bottom-right corner of the last.
+/// Images flagged `srgb` are decoded to linear light as they're sampled.
+#[derive(Debug)]
+pub struct Texture2D {
+    levels: Vec<FrameBuffer>,
+}
+
+impl Texture2D {
+    /// Wraps a single-sampled, non-empty image.
+    pub fn new(image: FrameBuffer) -> Result<Self, BufferError> {
+        if image.samples != SampleCount::X1 {
+            return Err(BufferError::SampleCountMismatch);
+        }
+        if image.width == 0 || image.height == 0 {
+            return Err(BufferError::SizeMismatch);
+        }
+
+        Ok(Self {
+            levels: vec![image],
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> usize {
+        self.levels[0].height
+    }
+
+    pub fn image(&self) -> &FrameBuffer {
+        &self.levels[0]
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn level(&self, level: usize) -> Option<&FrameBuffer> {
+        self.levels.get(level)
+    }
+
+    /// Rebuilds the mip chain down to 1×1 from the base image. Colors are
+    /// filtered in linear light, and levels share the base image's `srgb`
+    /// flag.
+    pub fn generate_mipmaps(&mut self, kernel: Kernel) {
+        self.levels.truncate(1);
+
+        // Each level is filtered from the base image rather than the level
+        // above, so error doesn't accumulate down the chain.
+        let base = &self.levels[0];
+        let (width, height) = (base.width, base.height);
+        let texels: Vec<Vec4> = base
+            .to_array()
+            .unwrap()
+            .iter()
+            .map(|&c| base.decode(c))
+            .collect();
+        let srgb = base.srgb;
+
+        let (mut w, mut h) = (width, height);
+        while w > 1 || h > 1 {
+            (w, h) = ((w / 2).max(1), (h / 2).max(1));
+
+            let mut level = FrameBuffer::new(w, h);
+            level.srgb = srgb;
+            let resampled = resample::resample(&texels, width, height, w, h, kernel);
+            for (dst, color) in level.to_array_mut().unwrap().iter_mut().zip(resampled) {
+                *dst = color::encode(color, srgb);
+            }
+
+            self.levels.push(level);
+        }
+    }
+
+    fn texel(&self, level: usize, sampler: &Sampler, x: i64, y: i64) -> Vec4 {
+        let image = &self.levels[level];
+        let x = sampler.address_u.resolve(x, image.width);
+        let y = sampler.address_v.resolve(y, image.height);
+
+        match (x, y) {
+            (Some(x), Some(y)) => image.decode(image.to_array().unwrap()[y * image.width + x]),
+            _ => sampler.border_color,
+        }
+    }
+
+    fn filter(&self, level: usize, sampler: &Sampler, filter: FilterMode, uv: Vec2) -> Vec4 {
+        let image = &self.levels[level];
+        let x = uv.x * image.width as f32;
+        let y = uv.y * image.height as f32;
+
+        match filter {
+            FilterMode::Nearest => self.texel(level, sampler, x.floor() as i64, y.floor() as i64),
+            FilterMode::Linear => {
+                // Texel `i` has its centre at `i + 0.5`.
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let texel = |x, y| self.texel(level, sampler, x, y);
+                let top = texel(x0, y0) * (1.0 - tx) + texel(x0 + 1, y0) * tx;
+                let bottom = texel(x0, y0 + 1) * (1.0 - tx) + texel(x0 + 1, y0 + 1) * tx;
+
+                top * (1.0 - ty) + bottom * ty
+            }
+        }
+    }
+
+    /// Samples the base level with the sampler's magnification filter, as
+    /// there is no footprint to decide between magnification and
+    /// minification.
+    pub fn sample(&self, sampler: &Sampler, uv: Vec2) -> Vec4 {
+        self.filter(0, sampler, sampler.mag_filter, uv)
+    }
+
+    /// Samples at an explicit level of detail, where level `n` is reduced by
+    /// `2^n`. Levels of detail at or below zero magnify the base level.
+    pub fn sample_level(&self, sampler: &Sampler, uv: Vec2, lod: f32) -> Vec4 {
+        // Not `clamp`, which panics on an inverted range.
+        let lod = lod.max(sampler.min_lod).min(sampler.max_lod);
+        if lod.is_nan() || lod <= 0.0 {
+            return self.filter(0, sampler, sampler.mag_filter, uv);
+        }
+
+        let lod = lod.min((self.levels.len() - 1) as f32);
+
+        match sampler.mipmap_filter {
+            FilterMode::Nearest => {
+                self.filter(lod.round() as usize, sampler, sampler.min_filter, uv)
+            }
+            FilterMode::Linear => {
+                let level = lod.floor() as usize;
+                let t = lod - level as f32;
+                let near = self.filter(level, sampler, sampler.min_filter, uv);
+
+                if t == 0.0 {
+                    return near;
+                }
+
+                let far = self.filter(level + 1, sampler, sampler.min_filter, uv);
+                near * (1.0 - t) + far * t
+            }
+        }
+    }
+
+    /// Samples with the level of detail chosen from the screen-space
+    /// derivatives of `uv`, as passed to fragment shaders in
+    /// `FragmentInput::ddx` and `ddy`.
+    pub fn sample_grad(&self, sampler: &Sampler, uv: Vec2, ddx: Vec2, ddy: Vec2) -> Vec4 {
+        let (width, height) = (self.width() as f32, self.height() as f32);
+        let texels = |d: Vec2| Vec2::new(d.x * width, d.y * height).magnitude();
+        let (len_x, len_y) = (texels(ddx), texels(ddy));
+
+        let (major, minor, axis) = if len_x >= len_y {
+            (len_x, len_y, ddx)
+        } else {
+            (len_y, len_x, ddy)
+        };
+
+        // An anisotropic footprint is covered by several samples along its
+        // major axis, each filtered for the minor axis only.
+        let count = if sampler.max_anisotropy > 1 && minor > 0.0 {
+            (major / minor).ceil().min(sampler.max_anisotropy as f32) as u32
+        } else {
+            1
+        };
+
+        let lod = (major / count as f32).log2() + sampler.lod_bias;
+        if count == 1 {
+            return self.sample_level(sampler, uv, lod);
+        }
+
+        let sum = (0..count).fold(Vec4::ZERO, |sum, i| {
+            let offset = (i as f32 + 0.5) / count as f32 - 0.5;
+            sum + self.sample_level(sampler, uv + axis * offset, lod)
+        });
+
+        sum * (1.0 / count as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::ops::{Fill, SetPixel};
+
+    /// Red and green over blue and white.
+    fn quadrants() -> Texture2D {
+        let mut image = FrameBuffer::new(2, 2);
+        for (x, y, [r, g, b]) in [
+            (0, 0, [1.0, 0.0, 0.0]),
+            (1, 0, [0.0, 1.0, 0.0]),
+            (0, 1, [0.0, 0.0, 1.0]),
+            (1, 1, [1.0, 1.0, 1.0]),
+        ] {
+            image
+                .set_pixel(x, y, color::pack(Vec4::new(r, g, b, 1.0)))
+                .unwrap();
+        }
+        Texture2D::new(image).unwrap()
+    }
+
+    #[test]
+    fn address_modes_map_texel_coordinates() {
+        let resolve = |mode: AddressMode| [-5, -1, 0, 3, 4, 6].map(|i| mode.resolve(i, 4));
+
+        assert_eq!(resolve(AddressMode::Repeat), [3, 3, 0, 3, 0, 2].map(Some));
+        assert_eq!(
+            resolve(AddressMode::MirrorRepeat),
+            [3, 0, 0, 3, 3, 1].map(Some)
+        );
+        assert_eq!(
+            resolve(AddressMode::ClampToEdge),
+            [0, 0, 0, 3, 3, 3].map(Some)
+        );
+        assert_eq!(
+            resolve(AddressMode::ClampToBorder),
+            [None, None, Some(0), Some(3), None, None]
+        );
+    }
+
+    #[test]
+    fn filters_pick_or_blend_texels() {
+        let texture = quadrants();
+        let (red, green) = (Vec4::new(1.0, 0.0, 0.0, 1.0), Vec4::new(0.0, 1.0, 0.0, 1.0));
+
+        let sampler = Sampler::new(FilterMode::Nearest, AddressMode::Repeat);
+        assert_eq!(texture.sample(&sampler, Vec2::new(0.75, 0.25)), green);
+        assert_eq!(texture.sample(&sampler, Vec2::new(1.75, -0.75)), green);
+
+        let sampler = Sampler::new(FilterMode::Nearest, AddressMode::MirrorRepeat);
+        assert_eq!(texture.sample(&sampler, Vec2::new(1.25, 0.25)), green);
+
+        let mut sampler = Sampler::new(FilterMode::Nearest, AddressMode::ClampToBorder);
+        sampler.border_color = Vec4::new(0.5, 0.5, 0.5, 0.5);
+        assert_eq!(
+            texture.sample(&sampler, Vec2::new(1.2, 0.5)),
+            sampler.border_color
+        );
+
+        let sampler = Sampler::new(FilterMode::Linear, AddressMode::ClampToEdge);
+        assert_eq!(
+            texture.sample(&sampler, Vec2::new(0.5, 0.25)),
+            Vec4::new(0.5, 0.5, 0.0, 1.0)
+        );
+        assert_eq!(
+            texture.sample(&sampler, Vec2::new(0.5, 0.5)),
+            Vec4::new(0.5, 0.5, 0.5, 1.0)
+        );
+        // Clamping keeps edge texels unblended with the opposite side.
+        assert_eq!(texture.sample(&sampler, Vec2::new(0.0, 0.0)), red);
+    }
+
+    #[test]
+    fn only_single_sampled_images_are_textures() {
+        let multisampled = FrameBuffer::multisampled(2, 2, SampleCount::X4);
+        assert_eq!(
+            Texture2D::new(multisampled).unwrap_err(),
+            BufferError::SampleCountMismatch
+        );
+        assert_eq!(
+            Texture2D::new(FrameBuffer::new(0, 4)).unwrap_err(),
+            BufferError::SizeMismatch
+        );
+    }
+
+    /// A texture whose texels are black or white by `white(x, y)`.
+    fn pattern(width: usize, height: usize, white: impl Fn(usize, usize) -> bool) -> Texture2D {
+        let mut image = FrameBuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let v = if white(x, y) { 1.0 } else { 0.0 };
+                image
+                    .set_pixel(x, y, color::pack(Vec4::new(v, v, v, 1.0)))
+                    .unwrap();
+            }
+        }
+        Texture2D::new(image).unwrap()
+    }
+
+    #[test]
+    fn mip_chains_average_down_to_one_texel() {
+        let mut texture = pattern(64, 32, |x, y| (x + y) % 2 == 1);
+
+        for kernel in [Kernel::Box, Kernel::Kaiser, Kernel::Lanczos] {
+            texture.generate_mipmaps(kernel);
+
+            let sizes: Vec<_> = (0..texture.level_count())
+                .map(|i| {
+                    (
+                        texture.level(i).unwrap().width,
+                        texture.level(i).unwrap().height,
+                    )
+                })
+                .collect();
+            assert_eq!(
+                sizes,
+                [(64, 32), (32, 16), (16, 8), (8, 4), (4, 2), (2, 1), (1, 1)]
+            );
+
+            let last = texture.level(6).unwrap();
+            let gray = last.decode(last.to_array().unwrap()[0]);
+            assert!((gray.x - 0.5).abs() < 0.02, "{kernel:?} {gray:?}");
+        }
+
+        // Filtering happens in linear light.
+        let mut image = texture.image().clone();
+        image.srgb = true;
+        let mut texture = Texture2D::new(image).unwrap();
+        texture.generate_mipmaps(Kernel::Box);
+        let level = texture.level(1).unwrap();
+        assert!(level.srgb);
+        assert_eq!(level.to_array().unwrap()[0] & 0xff, 0xbc);
+    }
+
+    #[test]
+    fn srgb_images_decode_when_sampled() {
+        let mut image = FrameBuffer::new(4, 4);
+        image.fill(0xffbcbcbc);
+        let sampler = Sampler::new(FilterMode::Linear, AddressMode::Repeat);
+        let uv = Vec2::new(0.5, 0.5);
+
+        let encoded = Texture2D::new(image.clone()).unwrap().sample(&sampler, uv);
+        assert!((encoded.x - 0xbc as f32 / 255.0).abs() < 1e-6);
+
+        image.srgb = true;
+        let decoded = Texture2D::new(image).unwrap().sample(&sampler, uv);
+        assert!((decoded.x - 0.5).abs() < 3e-3, "{decoded:?}");
+        assert_eq!(decoded.w, 1.0);
+    }
+
+    #[test]
+    fn derivatives_select_the_level_of_detail() {
+        let mut texture = pattern(64, 32, |x, y| (x + y) % 2 == 1);
+        texture.generate_mipmaps(Kernel::Box);
+        let sampler = Sampler::new(FilterMode::Linear, AddressMode::Repeat);
+        let uv = Vec2::new(0.3, 0.3);
+
+        // Eight texels per pixel reads a level where the checks have blurred.
+        let far = texture.sample_grad(
+            &sampler,
+            uv,
+            Vec2::new(8.0 / 64.0, 0.0),
+            Vec2::new(0.0, 8.0 / 32.0),
+        );
+        assert!((far.x - 0.5).abs() < 0.02, "{far:?}");
+
+        // Magnification reads the base level.
+        let near = texture.sample_grad(
+            &sampler,
+            Vec2::new(0.5 / 64.0, 0.5 / 32.0),
+            Vec2::new(0.1 / 64.0, 0.0),
+            Vec2::new(0.0, 0.1 / 32.0),
+        );
+        assert_eq!(near.x, 0.0);
+
+        // Trilinear filtering blends neighbouring levels.
+        let blended = texture.sample_level(&sampler, uv, 0.5);
+        let levels = [0.0, 1.0].map(|lod| texture.sample_level(&sampler, uv, lod));
+        assert!((blended.x - (levels[0].x + levels[1].x) / 2.0).abs() < 1e-6);
+
+        // Inverted ranges don't panic, and the maximum wins; NaN reads the
+        // base level.
+        let inverted = Sampler {
+            min_lod: 1.0,
+            max_lod: 0.0,
+            ..sampler
+        };
+        assert_eq!(texture.sample_level(&inverted, uv, 0.5), levels[0]);
+        assert_eq!(texture.sample_level(&sampler, uv, f32::NAN), levels[0]);
+    }
+
+    #[test]
+    fn anisotropic_filtering_keeps_detail_across_the_footprint() {
+        // Horizontal stripes, sampled with a footprint stretched along them.
+        let mut texture = pattern(64, 64, |_, y| y % 2 == 1);
+        texture.generate_mipmaps(Kernel::Box);
+        let mut sampler = Sampler::new(FilterMode::Linear, AddressMode::Repeat);
+        let (uv, ddx, ddy) = (
+            Vec2::new(0.3, 1.5 / 64.0),
+            Vec2::new(16.0 / 64.0, 0.0),
+            Vec2::new(0.0, 1.0 / 64.0),
+        );
+
+        let isotropic = texture.sample_grad(&sampler, uv, ddx, ddy);
+        sampler.max_anisotropy = 16;
+        let anisotropic = texture.sample_grad(&sampler, uv, ddx, ddy);
+
+        assert!((isotropic.x - 0.5).abs() < 0.02, "{isotropic:?}");
+        assert_eq!(anisotropic.x, 1.0);
+    }
+}
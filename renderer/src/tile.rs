@@ -0,0 +1,287 @@
+use std::{
+    ops::{Index, IndexMut},
+    sync::Mutex,
+    thread,
+};
+
+use math::Vec4;
+
+use crate::{
+    buffer::{ops::ToArrayMut, HdrTexels, Rect},
+    color,
+    dither::Dither,
+    msaa::SampleCount,
+    pipeline::{ColorAttachment, RenderTarget},
+    raster::Bounds,
+    view::ViewMut,
+    workers,
+};
+
+/// Configuration for binned, multithreaded rasterization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tiling {
+    /// Width and height of a tile in pixels.
+    pub tile_size: usize,
+    pub threads:   usize,
+}
+
+impl Default for Tiling {
+    fn default() -> Self {
+        Self {
+            tile_size: 64,
+            threads:   thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+/// A tile's rows of one buffer, addressed by [`Tile::index`].
+pub(crate) struct Texels<'a, T> {
+    rows:    Vec<&'a mut [T]>,
+    /// Elements per row.
+    row_len: usize,
+}
+
+impl<'a, T> Texels<'a, T> {
+    fn new(view: ViewMut<'a, T>, samples: usize) -> Self {
+        Self {
+            row_len: view.width() * samples,
+            rows:    view.into_rows().collect(),
+        }
+    }
+}
+
+impl<T> Index<usize> for Texels<'_, T> {
+    type Output = T;
+
+    fn index(&self, i: usize) -> &T {
+        &self.rows[i / self.row_len][i % self.row_len]
+    }
+}
+
+impl<T> IndexMut<usize> for Texels<'_, T> {
+    fn index_mut(&mut self, i: usize) -> &mut T {
+        &mut self.rows[i / self.row_len][i % self.row_len]
+    }
+}
+
+/// One color attachment's storage, in the attachment's own format.
+pub(crate) enum ColorTexels<'a> {
+    /// Packed colors, sRGB encoded when the flag is set and rounded with
+    /// the dither.
+    Rgba8(Texels<'a, u32>, bool, Dither),
+    Rgba32F(Texels<'a, Vec4>),
+    Rgba16F(Texels<'a, [u16; 4]>),
+}
+
+impl ColorTexels<'_> {
+    pub fn read(&self, i: usize) -> Vec4 {
+        match self {
+            ColorTexels::Rgba8(texels, srgb, _) => color::decode(texels[i], *srgb),
+            ColorTexels::Rgba32F(texels) => texels[i],
+            ColorTexels::Rgba16F(texels) => color::unpack_half(texels[i]),
+        }
+    }
+
+    /// Writes texel `i`, which belongs to pixel `(x, y)` of the target.
+    pub fn write(&mut self, i: usize, (x, y): (usize, usize), value: Vec4) {
+        match self {
+            ColorTexels::Rgba8(texels, srgb, dither) => {
+                texels[i] = dither.encode(value, *srgb, x, y)
+            }
+            ColorTexels::Rgba32F(texels) => texels[i] = value,
+            ColorTexels::Rgba16F(texels) => texels[i] = color::pack_half(value),
+        }
+    }
+
+    /// Adds one to every color channel of a texel used as a counter, whatever
+    /// its encoding, and makes it opaque.
+    pub fn increment(&mut self, i: usize) {
+        let add = |color: Vec4| Vec4::new(color.x + 1.0, color.y + 1.0, color.z + 1.0, 1.0);
+
+        match self {
+            ColorTexels::Rgba8(texels, ..) => {
+                let texel = texels[i];
+                let channel = |shift: u32| (((texel >> shift) & 0xff) + 1).min(0xff) << shift;
+                texels[i] = 0xff000000 | channel(16) | channel(8) | channel(0);
+            }
+            ColorTexels::Rgba32F(texels) => texels[i] = add(texels[i]),
+            ColorTexels::Rgba16F(texels) => {
+                texels[i] = color::pack_half(add(color::unpack_half(texels[i])))
+            }
+        }
+    }
+}
+
+/// The region of the render target a primitive is rasterized into, borrowed
+/// in place. Texels are indexed row-major over `bounds`, with the samples of
+/// a pixel adjacent.
+pub(crate) struct Tile<'a> {
+    pub bounds:  Bounds,
+    pub samples: SampleCount,
+    /// One set of texels per color attachment.
+    pub colors:  Vec<ColorTexels<'a>>,
+    pub depth:   Texels<'a, f32>,
+    pub stencil: Option<Texels<'a, u8>>,
+}
+
+impl Tile<'_> {
+    /// Index of the first sample of pixel `(x, y)`.
+    pub fn index(&self, x: usize, y: usize) -> usize {
+        let width = self.bounds.max_x - self.bounds.min_x;
+        ((y - self.bounds.min_y) * width + (x - self.bounds.min_x)) * self.samples.count()
+    }
+
+    /// The pixel holding the sample at `index`; the inverse of
+    /// [`index`](Self::index).
+    pub fn pixel(&self, index: usize) -> (usize, usize) {
+        let width = self.bounds.max_x - self.bounds.min_x;
+        let i = index / self.samples.count();
+        (self.bounds.min_x + i % width, self.bounds.min_y + i / width)
+    }
+}
+
+/// Splits the whole of `target` into disjoint tiles of at most `size`×`size`
+/// pixels, in row-major order.
+fn split<'a>(target: &'a mut RenderTarget, size: usize) -> Vec<Tile<'a>> {
+    let (width, height) = (target.depth.width, target.depth.height);
+    let samples = target.depth.samples;
+    let count = samples.count();
+
+    let mut colors: Vec<Box<dyn Iterator<Item = ColorTexels<'a>>>> = target
+        .colors
+        .iter_mut()
+        .map(|attachment| -> Box<dyn Iterator<Item = ColorTexels<'a>>> {
+            match attachment {
+                ColorAttachment::Rgba8(buffer) => {
+                    let (srgb, dither) = (buffer.srgb, buffer.dither);
+                    let texels = buffer.to_array_mut().unwrap();
+                    Box::new(
+                        split_buffer(texels, width, height, count, size)
+                            .into_iter()
+                            .map(move |view| {
+                                ColorTexels::Rgba8(Texels::new(view, count), srgb, dither)
+                            }),
+                    )
+                }
+                ColorAttachment::Hdr(buffer) => match &mut buffer.texels {
+                    HdrTexels::Rgba32F(texels) => Box::new(
+                        split_buffer(texels, width, height, count, size)
+                            .into_iter()
+                            .map(move |view| ColorTexels::Rgba32F(Texels::new(view, count))),
+                    ),
+                    HdrTexels::Rgba16F(texels) => Box::new(
+                        split_buffer(texels, width, height, count, size)
+                            .into_iter()
+                            .map(move |view| ColorTexels::Rgba16F(Texels::new(view, count))),
+                    ),
+                },
+            }
+        })
+        .collect();
+    let mut stencil = target.stencil.as_deref_mut().map(|stencil| {
+        split_buffer(stencil.to_array_mut().unwrap(), width, height, count, size).into_iter()
+    });
+
+    split_buffer(target.depth.pixels_mut(), width, height, count, size)
+        .into_iter()
+        .map(|depth| {
+            let rect = depth.rect();
+            Tile {
+                bounds: Bounds {
+                    min_x: rect.x,
+                    min_y: rect.y,
+                    max_x: rect.x + rect.width,
+                    max_y: rect.y + rect.height,
+                },
+                samples,
+                colors: colors
+                    .iter_mut()
+                    .map(|tiles| tiles.next().unwrap())
+                    .collect(),
+                depth: Texels::new(depth, count),
+                stencil: stencil
+                    .as_mut()
+                    .map(|tiles| Texels::new(tiles.next().unwrap(), count)),
+            }
+        })
+        .collect()
+}
+
+/// Splits the storage of a `width`×`height` buffer the way [`split`] does.
+fn split_buffer<T>(
+    data: &mut [T],
+    width: usize,
+    height: usize,
+    samples: usize,
+    size: usize,
+) -> Vec<ViewMut<'_, T>> {
+    ViewMut::new(data, width, height, samples, Rect::new(0, 0, width, height))
+        .unwrap()
+        .into_tiles(size, size)
+}
+
+/// Rasterizes every primitive, in submission order, over the whole target on
+/// the calling thread.
+pub(crate) fn render_serial<P, R>(primitives: &[P], target: &mut RenderTarget, rasterize: R)
+where
+    R: Fn(&P, &mut Tile),
+{
+    let size = target.depth.width.max(target.depth.height);
+
+    for mut tile in split(target, size) {
+        for primitive in primitives {
+            rasterize(primitive, &mut tile);
+        }
+    }
+}
+
+/// Bins primitives into tiles by their pixel bounds and rasterizes the tiles
+/// in parallel, in place. Every tile sees its primitives in submission order,
+/// so the result is identical to [`render_serial`].
+pub(crate) fn render_tiled<P, B, R>(
+    tiling: &Tiling,
+    primitives: &[P],
+    target: &mut RenderTarget,
+    bounds_of: B,
+    rasterize: R,
+) where
+    P: Sync,
+    B: Fn(&P) -> Bounds,
+    R: Fn(&P, &mut Tile) + Sync,
+{
+    let (width, height) = (target.depth.width, target.depth.height);
+    let frame = Bounds::new(width, height);
+    let size = tiling.tile_size.max(1);
+    let tiles_x = width.div_ceil(size);
+    let tiles_y = height.div_ceil(size);
+
+    let mut bins = vec![Vec::new(); tiles_x * tiles_y];
+    for (i, primitive) in primitives.iter().enumerate() {
+        let Some(bounds) = bounds_of(primitive).intersect(&frame) else {
+            continue;
+        };
+
+        for ty in bounds.min_y / size..=(bounds.max_y - 1) / size {
+            for tx in bounds.min_x / size..=(bounds.max_x - 1) / size {
+                bins[ty * tiles_x + tx].push(i);
+            }
+        }
+    }
+
+    let queue = Mutex::new(
+        split(target, size)
+            .into_iter()
+            .zip(bins)
+            .filter(|(_, bin)| !bin.is_empty()),
+    );
+
+    workers::run(tiling.threads, &|| loop {
+        let Some((mut tile, bin)) = queue.lock().unwrap().next() else {
+            break;
+        };
+
+        for i in bin {
+            rasterize(&primitives[i], &mut tile);
+        }
+    });
+}
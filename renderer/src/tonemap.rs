@@ -0,0 +1,227 @@
+use math::{Vec3, Vec4};
+
+use crate::{
+    buffer::{BufferError, FrameBuffer, HdrBuffer},
+    dither::Quantizer,
+};
+
+/// Curve compressing unbounded linear color into the `[0, 1]` a display can
+/// show.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tonemapper {
+    /// `c / (1 + c)`, which never quite reaches white.
+    Reinhard,
+    /// Reinhard rescaled so `white` and anything brighter map to 1.
+    ExtendedReinhard { white: f32 },
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve.
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2.
+    Uncharted2,
+    /// Troy Sobotka's AgX, with the polynomial fit of its base contrast
+    /// curve. Desaturates very bright colors rather than skewing their hue.
+    AgX,
+}
+
+// Hable's curve parameters: shoulder strength, linear strength, linear angle,
+// toe strength, toe numerator and toe denominator, and the linear white point.
+const HABLE_A: f32 = 0.15;
+const HABLE_B: f32 = 0.50;
+const HABLE_C: f32 = 0.10;
+const HABLE_D: f32 = 0.20;
+const HABLE_E: f32 = 0.02;
+const HABLE_F: f32 = 0.30;
+const HABLE_WHITE: f32 = 11.2;
+
+fn hable(x: f32) -> f32 {
+    (x * (HABLE_A * x + HABLE_C * HABLE_B) + HABLE_D * HABLE_E)
+        / (x * (HABLE_A * x + HABLE_B) + HABLE_D * HABLE_F)
+        - HABLE_E / HABLE_F
+}
+
+// AgX works in log2 space between these exposures relative to middle grey.
+const AGX_MIN_EV: f32 = -12.47393;
+const AGX_MAX_EV: f32 = 4.026069;
+
+// Rows of the matrices into and out of the AgX working space.
+const AGX_INSET: [[f32; 3]; 3] = [
+    [0.842_479_1, 0.078_433_6, 0.079_223_75],
+    [0.042_328_24, 0.878_468_6, 0.079_166_13],
+    [0.042_375_65, 0.078_433_6, 0.879_143],
+];
+const AGX_OUTSET: [[f32; 3]; 3] = [
+    [1.196_879, -0.098_020_88, -0.099_029_74],
+    [-0.052_896_85, 1.151_903_1, -0.098_961_18],
+    [-0.052_971_64, -0.098_043_45, 1.151_073_7],
+];
+
+fn transform(matrix: &[[f32; 3]; 3], v: Vec3) -> Vec3 {
+    let row = |r: [f32; 3]| r[0] * v.x + r[1] * v.y + r[2] * v.z;
+    Vec3::new(row(matrix[0]), row(matrix[1]), row(matrix[2]))
+}
+
+fn agx(color: Vec3) -> Vec3 {
+    let contrast = |x: f32| {
+        let x = ((x.max(1e-10).log2()).clamp(AGX_MIN_EV, AGX_MAX_EV) - AGX_MIN_EV)
+            / (AGX_MAX_EV - AGX_MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    };
+
+    let v = transform(&AGX_INSET, color);
+    let v = transform(&AGX_OUTSET, per_channel(v, contrast));
+
+    // The curve produces display-encoded values; undo the 2.2 gamma it
+    // assumes so the result is linear like the other operators'.
+    per_channel(v, |c| c.max(0.0).powf(2.2))
+}
+
+fn per_channel(color: Vec3, f: impl Fn(f32) -> f32) -> Vec3 {
+    Vec3::new(f(color.x), f(color.y), f(color.z))
+}
+
+impl Tonemapper {
+    fn apply(self, color: Vec3) -> Vec3 {
+        match self {
+            Tonemapper::Reinhard => per_channel(color, |c| c / (1.0 + c)),
+            Tonemapper::ExtendedReinhard { white } => {
+                per_channel(color, |c| c * (1.0 + c / (white * white)) / (1.0 + c))
+            }
+            Tonemapper::Aces => per_channel(color, |c| {
+                (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)
+            }),
+            // Hable's exposure bias of 2 keeps the curve's mid-tones where he
+            // tuned them.
+            Tonemapper::Uncharted2 => per_channel(color, |c| hable(c * 2.0) / hable(HABLE_WHITE)),
+            Tonemapper::AgX => agx(color),
+        }
+    }
+}
+
+/// Exposure and tone curve used to show an [`HdrBuffer`] on a display.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapping {
+    pub operator: Tonemapper,
+    /// Brightness adjustment in stops, applied before the curve. Each stop
+    /// doubles the light.
+    pub exposure: f32,
+}
+
+impl ToneMapping {
+    pub fn new(operator: Tonemapper) -> Self {
+        Self {
+            operator,
+            exposure: 0.0,
+        }
+    }
+
+    /// Maps a linear HDR color to linear `[0, 1]`. Alpha is clamped but
+    /// otherwise left alone.
+    pub fn map(&self, color: Vec4) -> Vec4 {
+        let scale = self.exposure.exp2();
+        let rgb = per_channel(Vec3::new(color.x, color.y, color.z), |c| {
+            (c * scale).max(0.0)
+        });
+        let rgb = self.operator.apply(rgb);
+
+        Vec4::new(
+            rgb.x.clamp(0.0, 1.0),
+            rgb.y.clamp(0.0, 1.0),
+            rgb.z.clamp(0.0, 1.0),
+            color.w.clamp(0.0, 1.0),
+        )
+    }
+
+    /// Tone maps `source` into `target`, which must be the same size,
+    /// encoding as the target's `srgb` flag says and rounding with its
+    /// dither. Targets shown on a display should set `srgb`. Multisampled
+    /// sources are resolved first.
+    pub fn present(&self, source: &HdrBuffer, target: &mut FrameBuffer) -> Result<(), BufferError> {
+        if source.width != target.width || source.height != target.height {
+            return Err(BufferError::SizeMismatch);
+        }
+
+        let width = target.width;
+        let colors = (0..width * target.height)
+            .map(|i| Ok(self.map(source.resolve_pixel(i % width, i / width)?)))
+            .collect::<Result<Vec<_>, BufferError>>()?;
+
+        Quantizer::new(target.dither).write(&colors, target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffer::{
+            ops::{Fill, GetPixel},
+            Buffer, HdrFormat,
+        },
+        msaa::SampleCount,
+    };
+
+    #[test]
+    fn operators_rise_monotonically_and_follow_exposure() {
+        for operator in [
+            Tonemapper::Reinhard,
+            Tonemapper::ExtendedReinhard { white: 4.0 },
+            Tonemapper::Aces,
+            Tonemapper::Uncharted2,
+            Tonemapper::AgX,
+        ] {
+            let mut mapping = ToneMapping::new(operator);
+            let grey = |mapping: &ToneMapping, x: f32| mapping.map(Vec4::new(x, x, x, 1.0)).x;
+
+            let values: Vec<f32> = [0.0, 0.01, 0.18, 1.0, 4.0, 16.0, 1000.0]
+                .iter()
+                .map(|&x| grey(&mapping, x))
+                .collect();
+            assert!(
+                values.windows(2).all(|w| w[1] >= w[0] - 1e-6),
+                "{operator:?}"
+            );
+            assert!(
+                values.iter().all(|v| (0.0..=1.0).contains(v)),
+                "{operator:?}"
+            );
+
+            let middle = values[2];
+            mapping.exposure = 1.0;
+            assert!(grey(&mapping, 0.18) > middle, "{operator:?}");
+        }
+
+        let extended = ToneMapping::new(Tonemapper::ExtendedReinhard { white: 4.0 });
+        assert_eq!(
+            extended.map(Vec4::new(4.0, 8.0, 0.0, 2.0)),
+            Vec4::new(1.0, 1.0, 0.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn presenting_resolves_and_encodes_as_the_target_says() {
+        for format in [HdrFormat::Rgba32F, HdrFormat::Rgba16F] {
+            let mut source = HdrBuffer::multisampled(4, 2, SampleCount::X4, format);
+            source.fill(Vec4::new(6.0, 1.0, 0.0, 1.0));
+            let mut target = FrameBuffer::new(4, 2);
+
+            // Green maps to 0.5, which stays 128 in a linear target and
+            // encodes to 188 in an sRGB one.
+            let mapping = ToneMapping::new(Tonemapper::Reinhard);
+            mapping.present(&source, &mut target).unwrap();
+            assert_eq!(target.get_pixel(3, 1).unwrap() & 0xff00ff00, 0xff008000);
+
+            target.srgb = true;
+            mapping.present(&source, &mut target).unwrap();
+            assert_eq!(target.get_pixel(3, 1).unwrap() & 0xff00ff00, 0xff00bc00);
+
+            let mut wrong_size = FrameBuffer::new(2, 2);
+            assert_eq!(
+                ToneMapping::new(Tonemapper::Aces).present(&source, &mut wrong_size),
+                Err(BufferError::SizeMismatch)
+            );
+        }
+    }
+}
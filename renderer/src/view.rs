@@ -0,0 +1,419 @@
+use crate::buffer::{
+    ops::{Fill, GetPixel, SetPixel},
+    BufferError, Rect,
+};
+
+/// Checks that `rect` lies inside a `width`×`height` buffer.
+fn check(rect: Rect, width: usize, height: usize) -> Result<(), BufferError> {
+    let fits = |start: usize, length: usize, size: usize| {
+        start.checked_add(length).is_some_and(|end| end <= size)
+    };
+
+    if fits(rect.x, rect.width, width) && fits(rect.y, rect.height, height) {
+        Ok(())
+    } else {
+        Err(BufferError::OutOfBounds)
+    }
+}
+
+/// A borrowed rectangle of a buffer. Pixel `(0, 0)` is the top-left corner
+/// of the rectangle; each row holds `samples` elements per pixel.
+#[derive(Debug, Clone)]
+pub struct View<'a, T> {
+    rect:    Rect,
+    samples: usize,
+    rows:    Vec<&'a [T]>,
+}
+
+/// A mutably borrowed rectangle of a buffer, which can be split into
+/// disjoint views for parallel writes.
+#[derive(Debug)]
+pub struct ViewMut<'a, T> {
+    rect:    Rect,
+    samples: usize,
+    rows:    Vec<&'a mut [T]>,
+}
+
+impl<'a, T> View<'a, T> {
+    /// Borrows `rect` of the row-major storage of a `width` pixel wide buffer.
+    pub(crate) fn new(
+        data: &'a [T],
+        width: usize,
+        height: usize,
+        samples: usize,
+        rect: Rect,
+    ) -> Result<Self, BufferError> {
+        check(rect, width, height)?;
+
+        let stride = width * samples;
+        let rows = (rect.y..rect.y + rect.height)
+            .map(|y| {
+                &data[y * stride + rect.x * samples..y * stride + (rect.x + rect.width) * samples]
+            })
+            .collect();
+
+        Ok(Self {
+            rect,
+            samples,
+            rows,
+        })
+    }
+
+    /// The view's position within the buffer it borrows from.
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    pub fn width(&self) -> usize {
+        self.rect.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.rect.height
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        self.rows.iter().copied()
+    }
+
+    /// A view of `rect`, given relative to this view.
+    pub fn view(&self, rect: Rect) -> Result<View<'a, T>, BufferError> {
+        check(rect, self.rect.width, self.rect.height)?;
+
+        let samples = self.samples;
+        Ok(View {
+            rect: Rect::new(
+                self.rect.x + rect.x,
+                self.rect.y + rect.y,
+                rect.width,
+                rect.height,
+            ),
+            samples,
+            rows: self.rows[rect.y..rect.y + rect.height]
+                .iter()
+                .map(|row| &row[rect.x * samples..(rect.x + rect.width) * samples])
+                .collect(),
+        })
+    }
+}
+
+impl<'a, T> ViewMut<'a, T> {
+    /// Borrows `rect` of the row-major storage of a `width` pixel wide buffer.
+    pub(crate) fn new(
+        data: &'a mut [T],
+        width: usize,
+        height: usize,
+        samples: usize,
+        rect: Rect,
+    ) -> Result<Self, BufferError> {
+        check(rect, width, height)?;
+
+        // An empty buffer has no rows to chunk, but a view of it still has
+        // `rect.height` empty ones.
+        let rows = if width == 0 {
+            (0..rect.height).map(|_| Default::default()).collect()
+        } else {
+            data.chunks_exact_mut(width * samples)
+                .skip(rect.y)
+                .take(rect.height)
+                .map(|row| &mut row[rect.x * samples..(rect.x + rect.width) * samples])
+                .collect()
+        };
+
+        Ok(Self {
+            rect,
+            samples,
+            rows,
+        })
+    }
+
+    /// The view's position within the buffer it borrows from.
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    pub fn width(&self) -> usize {
+        self.rect.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.rect.height
+    }
+
+    pub fn as_view(&self) -> View<'_, T> {
+        View {
+            rect:    self.rect,
+            samples: self.samples,
+            rows:    self.rows.iter().map(|row| &**row).collect(),
+        }
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        self.rows.iter().map(|row| &**row)
+    }
+
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [T]> + use<'_, 'a, T> {
+        self.rows.iter_mut().map(|row| &mut **row)
+    }
+
+    /// Gives up the view for its rows, which can be written independently.
+    pub fn into_rows(self) -> impl Iterator<Item = &'a mut [T]> {
+        self.rows.into_iter()
+    }
+
+    /// Reborrows `rect`, given relative to this view.
+    pub fn view_mut(&mut self, rect: Rect) -> Result<ViewMut<'_, T>, BufferError> {
+        check(rect, self.rect.width, self.rect.height)?;
+
+        let samples = self.samples;
+        Ok(ViewMut {
+            rect: Rect::new(
+                self.rect.x + rect.x,
+                self.rect.y + rect.y,
+                rect.width,
+                rect.height,
+            ),
+            samples,
+            rows: self.rows[rect.y..rect.y + rect.height]
+                .iter_mut()
+                .map(|row| &mut row[rect.x * samples..(rect.x + rect.width) * samples])
+                .collect(),
+        })
+    }
+
+    /// Splits into the rows above `y` and the rows from `y` down.
+    pub fn split_rows(mut self, y: usize) -> (ViewMut<'a, T>, ViewMut<'a, T>) {
+        let y = y.min(self.rect.height);
+        let bottom = self.rows.split_off(y);
+        let Rect {
+            x,
+            y: top,
+            width,
+            height,
+        } = self.rect;
+
+        (
+            ViewMut {
+                rect:    Rect::new(x, top, width, y),
+                samples: self.samples,
+                rows:    self.rows,
+            },
+            ViewMut {
+                rect:    Rect::new(x, top + y, width, height - y),
+                samples: self.samples,
+                rows:    bottom,
+            },
+        )
+    }
+
+    /// Splits into the columns left of `x` and the columns from `x` right.
+    pub fn split_columns(self, x: usize) -> (ViewMut<'a, T>, ViewMut<'a, T>) {
+        let x = x.min(self.rect.width);
+        let samples = self.samples;
+        let (left, right) = self
+            .rows
+            .into_iter()
+            .map(|row| row.split_at_mut(x * samples))
+            .unzip();
+        let Rect {
+            x: left_x,
+            y,
+            width,
+            height,
+        } = self.rect;
+
+        (
+            ViewMut {
+                rect: Rect::new(left_x, y, x, height),
+                samples,
+                rows: left,
+            },
+            ViewMut {
+                rect: Rect::new(left_x + x, y, width - x, height),
+                samples,
+                rows: right,
+            },
+        )
+    }
+
+    /// Splits into disjoint tiles of at most `tile_width`×`tile_height`
+    /// pixels, in row-major order.
+    pub fn into_tiles(self, tile_width: usize, tile_height: usize) -> Vec<ViewMut<'a, T>> {
+        let (tile_width, tile_height) = (tile_width.max(1), tile_height.max(1));
+        let mut tiles = Vec::new();
+        let mut rest = self;
+
+        while rest.rect.height > 0 {
+            let (band, below) = rest.split_rows(tile_height);
+            let mut band = band;
+
+            while band.rect.width > 0 {
+                let (tile, right) = band.split_columns(tile_width);
+                tiles.push(tile);
+                band = right;
+            }
+
+            rest = below;
+        }
+
+        tiles
+    }
+}
+
+impl<T: Copy> GetPixel<T> for View<'_, T> {
+    fn get_pixel(&self, x: usize, y: usize) -> Result<T, BufferError> {
+        if x < self.rect.width && y < self.rect.height {
+            Ok(self.rows[y][x * self.samples])
+        } else {
+            Err(BufferError::OutOfBounds)
+        }
+    }
+}
+
+impl<T: Copy> GetPixel<T> for ViewMut<'_, T> {
+    fn get_pixel(&self, x: usize, y: usize) -> Result<T, BufferError> {
+        if x < self.rect.width && y < self.rect.height {
+            Ok(self.rows[y][x * self.samples])
+        } else {
+            Err(BufferError::OutOfBounds)
+        }
+    }
+}
+
+impl<T: Copy> SetPixel<T> for ViewMut<'_, T> {
+    fn set_pixel(&mut self, x: usize, y: usize, value: T) -> Result<(), BufferError> {
+        if x < self.rect.width && y < self.rect.height {
+            let samples = self.samples;
+            self.rows[y][x * samples..(x + 1) * samples].fill(value);
+            Ok(())
+        } else {
+            Err(BufferError::OutOfBounds)
+        }
+    }
+}
+
+impl<T: Copy> Fill<T> for ViewMut<'_, T> {
+    fn fill(&mut self, value: T) {
+        for row in &mut self.rows {
+            row.fill(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffer::{Buffer, DepthBuffer, FrameBuffer},
+        msaa::SampleCount,
+    };
+
+    #[test]
+    fn tiles_cover_the_view_disjointly() {
+        let mut buffer = FrameBuffer::multisampled(10, 8, SampleCount::X4);
+        let tiles = buffer
+            .view_mut(Rect::new(2, 1, 6, 5))
+            .unwrap()
+            .into_tiles(4, 2);
+
+        let rects: Vec<_> = tiles.iter().map(ViewMut::rect).collect();
+        assert_eq!(
+            rects,
+            [
+                Rect::new(2, 1, 4, 2),
+                Rect::new(6, 1, 2, 2),
+                Rect::new(2, 3, 4, 2),
+                Rect::new(6, 3, 2, 2),
+                Rect::new(2, 5, 4, 1),
+                Rect::new(6, 5, 2, 1),
+            ]
+        );
+
+        std::thread::scope(|scope| {
+            for (i, mut tile) in tiles.into_iter().enumerate() {
+                scope.spawn(move || tile.fill(i as u32 + 1));
+            }
+        });
+
+        assert_eq!(buffer.get_pixel(2, 1).unwrap(), 1);
+        assert_eq!(buffer.get_pixel(6, 1).unwrap(), 2);
+        assert_eq!(buffer.get_pixel(7, 5).unwrap(), 6);
+        assert_eq!(buffer.get_pixel(8, 5).unwrap(), 0);
+        assert_eq!(buffer.get_pixel(1, 1).unwrap(), 0);
+    }
+
+    #[test]
+    fn nested_views_are_relative_to_their_parent() {
+        let data: Vec<u32> = (0..40).collect();
+        let view = View::new(&data, 10, 4, 1, Rect::new(2, 1, 6, 3)).unwrap();
+        let nested = view.view(Rect::new(4, 1, 2, 2)).unwrap();
+
+        assert_eq!(nested.rect(), Rect::new(6, 2, 2, 2));
+        assert_eq!(nested.get_pixel(1, 1).unwrap(), 37);
+        assert_eq!(nested.get_pixel(2, 0), Err(BufferError::OutOfBounds));
+        assert_eq!(
+            view.view(Rect::new(4, 0, 3, 1)).unwrap_err(),
+            BufferError::OutOfBounds
+        );
+        assert_eq!(view.rows().next().unwrap(), &[12, 13, 14, 15, 16, 17]);
+
+        // Rectangles whose far edge overflows are out of bounds too.
+        for rect in [
+            Rect::new(1, 0, usize::MAX, 1),
+            Rect::new(0, usize::MAX, 1, 2),
+        ] {
+            assert_eq!(view.view(rect).unwrap_err(), BufferError::OutOfBounds);
+            assert_eq!(
+                View::new(&data, 10, 4, 1, rect).unwrap_err(),
+                BufferError::OutOfBounds
+            );
+        }
+    }
+
+    #[test]
+    fn rows_and_columns_split_at_clamped_offsets() {
+        let mut data: Vec<u8> = vec![0; 2 * 4 * 3];
+        let view = ViewMut::new(&mut data, 4, 3, 2, Rect::new(0, 0, 4, 3)).unwrap();
+
+        let (top, bottom) = view.split_rows(1);
+        assert_eq!(
+            (top.rect(), bottom.rect()),
+            (Rect::new(0, 0, 4, 1), Rect::new(0, 1, 4, 2))
+        );
+
+        let (mut left, mut right) = bottom.split_columns(9);
+        assert_eq!(right.rect(), Rect::new(4, 1, 0, 2));
+        assert_eq!(right.rows_mut().next().unwrap().len(), 0);
+        left.set_pixel(3, 1, 7).unwrap();
+
+        assert_eq!(&data[20..24], &[0, 0, 7, 7]);
+    }
+
+    #[test]
+    fn writes_through_views_invalidate_the_depth_hierarchy() {
+        let mut depth = DepthBuffer::new(16, 16);
+        depth.enable_hierarchy();
+        depth
+            .view_mut(Rect::new(0, 0, 8, 8))
+            .unwrap()
+            .set_pixel(1, 1, 0.5)
+            .unwrap();
+
+        assert_eq!(depth.get_pixel(1, 1).unwrap(), 0.5);
+        depth.sync_hierarchy();
+        assert_eq!(
+            depth.hierarchy().unwrap().range(0, 0, 0),
+            Some((0.5, f32::INFINITY))
+        );
+
+        let mut empty = FrameBuffer::new(0, 3);
+        assert_eq!(
+            empty
+                .view_mut(Rect::new(0, 0, 0, 3))
+                .unwrap()
+                .rows()
+                .count(),
+            3
+        );
+    }
+}
@@ -7,7 +7,7 @@ use winit::{
 
 pub struct Window {
     event_loop: EventLoop<()>,
-    window:     winit::window::Window,
+    _window:    winit::window::Window,
 }
 
 impl Window {
@@ -18,7 +18,10 @@ impl Window {
             .build(&event_loop)
             .unwrap();
 
-        Self { event_loop, window }
+        Self {
+            event_loop,
+            _window: window,
+        }
     }
 
     pub fn run<F>(self, mut draw_fn: F) -> Result<(), EventLoopError>
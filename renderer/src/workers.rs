@@ -0,0 +1,65 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    thread,
+};
+
+/// Runs `task` on `threads` threads at once, the caller's among them,
+/// returning when every one of them has finished. A panic in the task is
+/// resumed on the caller once the others are done.
+pub(crate) fn run(threads: usize, task: &(dyn Fn() + Sync)) {
+    thread::scope(|scope| {
+        let workers: Vec<_> = (1..threads.max(1)).map(|_| scope.spawn(task)).collect();
+        let mut panicked = panic::catch_unwind(AssertUnwindSafe(task)).err();
+
+        for worker in workers {
+            if let Err(payload) = worker.join() {
+                panicked.get_or_insert(payload);
+            }
+        }
+        if let Some(payload) = panicked {
+            panic::resume_unwind(payload);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    use super::*;
+
+    #[test]
+    fn tasks_run_once_per_thread_in_parallel() {
+        let ids = Mutex::new(std::collections::HashSet::new());
+        let count = AtomicUsize::new(0);
+
+        run(3, &|| {
+            count.fetch_add(1, Ordering::Relaxed);
+            ids.lock().unwrap().insert(thread::current().id());
+        });
+
+        assert_eq!(count.into_inner(), 3);
+        let ids = ids.into_inner().unwrap();
+        assert_eq!(ids.len(), 3);
+        assert!(ids.contains(&thread::current().id()));
+    }
+
+    #[test]
+    fn panics_reach_the_caller_after_all_workers_finish() {
+        let finished = AtomicUsize::new(0);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            run(3, &|| {
+                if finished.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("first worker fails");
+                }
+            });
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(finished.load(Ordering::SeqCst), 3);
+    }
+}
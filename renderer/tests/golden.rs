@@ -0,0 +1,448 @@
+//! Renders small scenes and compares them against reference images checked
+//! in under `tests/golden`.
+//!
+//! After an intended change to the output, bless the new images with
+//!
+//! ```text
+//! BLESS=1 cargo test -p renderer --test golden
+//! ```
+//!
+//! and review them before committing. When a comparison fails, the render
+//! and an image highlighting the differences are written next to each other
+//! in Cargo's temporary directory for integration tests.
+
+mod support;
+
+use math::{Vec2, Vec3, Vec4};
+use renderer::{
+    blend::BlendState,
+    buffer::{
+        ops::{Fill, ToArray, ToArrayMut},
+        Buffer, DepthBuffer, FrameBuffer,
+    },
+    compare,
+    mesh::{Mesh, Topology},
+    msaa::SampleCount,
+    pipeline::{CullMode, Pipeline, PointShape, PointSizing, PolygonMode, RenderTarget},
+    resample::Kernel,
+    shader::{FragmentInput, FragmentOutput, FragmentShader, Varyings, VertexOutput, VertexShader},
+    texture::{AddressMode, FilterMode, Sampler, Texture2D},
+    tile::Tiling,
+};
+use support::{check_scene, within_tolerance, Tolerance};
+
+const SIZE: usize = 64;
+const BACKGROUND: u32 = 0xff202830;
+
+struct ColorVs;
+
+impl VertexShader for ColorVs {
+    type Vertex = (Vec4, Vec4);
+    type Uniforms = ();
+    type Varyings = Vec4;
+
+    fn shade(&self, vertex: &Self::Vertex, _: &()) -> VertexOutput<Vec4> {
+        VertexOutput {
+            position: vertex.0,
+            varyings: vertex.1,
+        }
+    }
+}
+
+struct ColorFs;
+
+impl FragmentShader for ColorFs {
+    type Uniforms = ();
+    type Varyings = Vec4;
+
+    const WRITES_DEPTH: bool = false;
+
+    fn shade(&self, input: &FragmentInput<Vec4>, _: &()) -> Option<FragmentOutput> {
+        Some(FragmentOutput::new(input.varyings))
+    }
+}
+
+struct TextureVs;
+
+impl VertexShader for TextureVs {
+    type Vertex = (Vec4, Vec2);
+    type Uniforms = (Texture2D, Sampler);
+    type Varyings = Vec2;
+
+    fn shade(&self, vertex: &Self::Vertex, _: &Self::Uniforms) -> VertexOutput<Vec2> {
+        VertexOutput {
+            position: vertex.0,
+            varyings: vertex.1,
+        }
+    }
+}
+
+struct TextureFs;
+
+impl FragmentShader for TextureFs {
+    type Uniforms = (Texture2D, Sampler);
+    type Varyings = Vec2;
+
+    const WRITES_DEPTH: bool = false;
+
+    fn shade(
+        &self,
+        input: &FragmentInput<Vec2>,
+        uniforms: &Self::Uniforms,
+    ) -> Option<FragmentOutput> {
+        let (texture, sampler) = uniforms;
+        Some(FragmentOutput::new(texture.sample_grad(
+            sampler,
+            input.varyings,
+            input.ddx,
+            input.ddy,
+        )))
+    }
+}
+
+/// A particle's color and size.
+#[derive(Debug, Clone, Copy)]
+struct Sprite {
+    color: Vec3,
+    size:  f32,
+}
+
+impl Varyings for Sprite {
+    fn add(self, other: Self) -> Self {
+        Sprite {
+            color: self.color + other.color,
+            size:  self.size + other.size,
+        }
+    }
+
+    fn scale(self, factor: f32) -> Self {
+        Sprite {
+            color: self.color * factor,
+            size:  self.size * factor,
+        }
+    }
+
+    fn point_size(&self) -> Option<f32> {
+        Some(self.size)
+    }
+}
+
+struct SpriteVs;
+
+impl VertexShader for SpriteVs {
+    type Vertex = (Vec4, Sprite);
+    type Uniforms = ();
+    type Varyings = Sprite;
+
+    fn shade(&self, vertex: &Self::Vertex, _: &()) -> VertexOutput<Sprite> {
+        VertexOutput {
+            position: vertex.0,
+            varyings: vertex.1,
+        }
+    }
+}
+
+/// Tints the sprite's color by its coordinates, red across and green down.
+struct SpriteFs;
+
+impl FragmentShader for SpriteFs {
+    type Uniforms = ();
+    type Varyings = Sprite;
+
+    const WRITES_DEPTH: bool = false;
+
+    fn shade(&self, input: &FragmentInput<Sprite>, _: &()) -> Option<FragmentOutput> {
+        let (color, uv) = (input.varyings.color, input.point_coord);
+        Some(FragmentOutput::new(Vec4::new(
+            0.5 * color.x + 0.5 * uv.x,
+            0.5 * color.y + 0.5 * uv.y,
+            0.5 * color.z,
+            1.0,
+        )))
+    }
+}
+
+fn vertex(x: f32, y: f32, z: f32, color: Vec3, alpha: f32) -> (Vec4, Vec4) {
+    (
+        Vec4::new(x, y, z, 1.0),
+        Vec4::new(color.x, color.y, color.z, alpha),
+    )
+}
+
+fn color_targets(samples: SampleCount) -> (FrameBuffer, DepthBuffer) {
+    let mut color = FrameBuffer::multisampled(SIZE, SIZE, samples);
+    color.fill(BACKGROUND);
+    (color, DepthBuffer::multisampled(SIZE, SIZE, samples))
+}
+
+fn draw_colors(
+    vertices: Vec<(Vec4, Vec4)>,
+    samples: SampleCount,
+    tiling: Option<Tiling>,
+    configure: impl Fn(&mut Pipeline<ColorVs, ColorFs>),
+) -> FrameBuffer {
+    let (mut color, mut depth) = color_targets(samples);
+    let mut pipeline = Pipeline::new(ColorVs, ColorFs);
+    pipeline.state.raster.cull_mode = CullMode::None;
+    pipeline.set_tiling(tiling);
+    configure(&mut pipeline);
+
+    let mesh = Mesh::new(vertices, Topology::TriangleList);
+    pipeline
+        .draw(&mesh, &(), &mut RenderTarget::new(&mut color, &mut depth))
+        .unwrap();
+
+    if samples == SampleCount::X1 {
+        color
+    } else {
+        let mut resolved = FrameBuffer::new(SIZE, SIZE);
+        color.resolve(&mut resolved).unwrap();
+        resolved
+    }
+}
+
+#[test]
+fn interpolated_triangle() {
+    check_scene("interpolated_triangle", Tolerance::DEFAULT, |tiling| {
+        let vertices = vec![
+            vertex(-0.8, -0.8, 0.0, Vec3::new(1.0, 0.0, 0.0), 1.0),
+            vertex(0.8, -0.6, 0.0, Vec3::new(0.0, 1.0, 0.0), 1.0),
+            vertex(-0.2, 0.9, 0.0, Vec3::new(0.0, 0.0, 1.0), 1.0),
+        ];
+        draw_colors(vertices, SampleCount::X1, tiling, |_| {})
+    });
+}
+
+#[test]
+fn intersecting_triangles() {
+    check_scene("intersecting_triangles", Tolerance::DEFAULT, |tiling| {
+        let (red, blue) = (Vec3::new(0.9, 0.2, 0.1), Vec3::new(0.1, 0.3, 0.9));
+        let vertices = vec![
+            vertex(-0.9, -0.7, 0.9, red, 1.0),
+            vertex(0.9, -0.7, -0.9, red, 1.0),
+            vertex(0.0, 0.9, 0.0, red, 1.0),
+            vertex(-0.9, 0.7, -0.9, blue, 1.0),
+            vertex(0.9, 0.7, 0.9, blue, 1.0),
+            vertex(0.0, -0.9, 0.0, blue, 1.0),
+        ];
+        draw_colors(vertices, SampleCount::X1, tiling, |_| {})
+    });
+}
+
+#[test]
+fn multisampled_edges() {
+    check_scene("multisampled_edges", Tolerance::DEFAULT, |tiling| {
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let vertices = vec![
+            vertex(-0.9, -0.3, 0.0, white, 1.0),
+            vertex(0.9, -0.9, 0.0, white, 1.0),
+            vertex(0.1, 0.9, 0.0, white, 1.0),
+        ];
+        draw_colors(vertices, SampleCount::X4, tiling, |_| {})
+    });
+}
+
+#[test]
+fn alpha_blending() {
+    check_scene("alpha_blending", Tolerance::DEFAULT, |tiling| {
+        let quad = |x: f32, y: f32, color: Vec3| {
+            let corner = |dx: f32, dy: f32| vertex(x + dx, y + dy, 0.0, color, 0.6);
+            vec![
+                corner(-0.5, -0.5),
+                corner(0.5, -0.5),
+                corner(0.5, 0.5),
+                corner(-0.5, -0.5),
+                corner(0.5, 0.5),
+                corner(-0.5, 0.5),
+            ]
+        };
+        let vertices = [
+            quad(-0.2, -0.2, Vec3::new(1.0, 0.0, 0.0)),
+            quad(0.2, -0.2, Vec3::new(0.0, 1.0, 0.0)),
+            quad(0.0, 0.2, Vec3::new(0.0, 0.0, 1.0)),
+        ]
+        .concat();
+
+        draw_colors(vertices, SampleCount::X1, tiling, |pipeline| {
+            pipeline.state.blend[0] = Some(BlendState::ALPHA_BLENDING);
+            pipeline.state.depth.write = false;
+        })
+    });
+}
+
+#[test]
+fn wireframe_overlay() {
+    check_scene("wireframe_overlay", Tolerance::DEFAULT, |tiling| {
+        let vertices = vec![
+            vertex(-0.8, -0.8, 0.0, Vec3::new(0.8, 0.5, 0.2), 1.0),
+            vertex(0.8, -0.8, 0.0, Vec3::new(0.2, 0.8, 0.5), 1.0),
+            vertex(0.8, 0.8, 0.0, Vec3::new(0.5, 0.2, 0.8), 1.0),
+            vertex(-0.8, -0.8, 0.0, Vec3::new(0.8, 0.5, 0.2), 1.0),
+            vertex(0.8, 0.8, 0.0, Vec3::new(0.5, 0.2, 0.8), 1.0),
+            vertex(-0.8, 0.8, 0.0, Vec3::new(0.9, 0.9, 0.9), 1.0),
+        ];
+        draw_colors(vertices, SampleCount::X1, tiling, |pipeline| {
+            pipeline.state.raster.polygon_mode = PolygonMode::Overlay;
+            pipeline.state.raster.overlay_color = Vec4::new(0.1, 0.1, 0.1, 1.0);
+        })
+    });
+}
+
+#[test]
+fn perspective_texture() {
+    let mut checker = FrameBuffer::new(32, 32);
+    for (i, pixel) in checker.to_array_mut().unwrap().iter_mut().enumerate() {
+        let (x, y) = (i % 32, i / 32);
+        *pixel = if (x / 4 + y / 4) % 2 == 0 {
+            0xffe0e0e0
+        } else {
+            0xff304060
+        };
+    }
+    let mut texture = Texture2D::new(checker).unwrap();
+    texture.generate_mipmaps(Kernel::Box);
+    let sampler = Sampler::new(FilterMode::Linear, AddressMode::Repeat);
+    let uniforms = (texture, sampler);
+
+    check_scene("perspective_texture", Tolerance::DEFAULT, |tiling| {
+        // A floor receding into the distance, the far edge at w = 4.
+        let corner = |x: f32, y: f32, w: f32, u: f32, v: f32| {
+            (Vec4::new(x * w, y * w, 0.0, w), Vec2::new(u, v))
+        };
+        let mesh = Mesh::indexed(
+            vec![
+                corner(-1.0, -1.0, 1.0, 0.0, 4.0),
+                corner(1.0, -1.0, 1.0, 4.0, 4.0),
+                corner(0.3, 0.6, 4.0, 4.0, 0.0),
+                corner(-0.3, 0.6, 4.0, 0.0, 0.0),
+            ],
+            vec![0u16, 1, 2, 0, 2, 3],
+            Topology::TriangleList,
+        );
+
+        let (mut color, mut depth) = color_targets(SampleCount::X1);
+        let mut pipeline = Pipeline::new(TextureVs, TextureFs);
+        pipeline.state.raster.cull_mode = CullMode::None;
+        pipeline.set_tiling(tiling);
+        pipeline
+            .draw_indexed(
+                &mesh,
+                &uniforms,
+                &mut RenderTarget::new(&mut color, &mut depth),
+            )
+            .unwrap();
+
+        color
+    });
+}
+
+#[test]
+fn point_sprites() {
+    check_scene("point_sprites", Tolerance::DEFAULT, |tiling| {
+        let (mut color, mut depth) = color_targets(SampleCount::X4);
+
+        // A wall across the left half, which sprites behind are hidden by.
+        let gray = Vec3::new(0.4, 0.4, 0.4);
+        let wall = vec![
+            vertex(-1.0, -1.0, 0.0, gray, 1.0),
+            vertex(0.0, -1.0, 0.0, gray, 1.0),
+            vertex(-1.0, 1.0, 0.0, gray, 1.0),
+            vertex(0.0, -1.0, 0.0, gray, 1.0),
+            vertex(0.0, 1.0, 0.0, gray, 1.0),
+            vertex(-1.0, 1.0, 0.0, gray, 1.0),
+        ];
+        let mut pipeline = Pipeline::new(ColorVs, ColorFs);
+        pipeline.set_tiling(tiling);
+        pipeline
+            .draw(
+                &Mesh::new(wall, Topology::TriangleList),
+                &(),
+                &mut RenderTarget::new(&mut color, &mut depth),
+            )
+            .unwrap();
+
+        let sprite = |x: f32, y: f32, z: f32, w: f32, color: Vec3, size: f32| {
+            (Vec4::new(x * w, y * w, z * w, w), Sprite { color, size })
+        };
+        let (red, blue) = (Vec3::new(1.0, 0.1, 0.1), Vec3::new(0.1, 0.2, 1.0));
+
+        let mut pipeline = Pipeline::new(SpriteVs, SpriteFs);
+        pipeline.set_tiling(tiling);
+
+        // Squares half a unit across, receding along the top, alternately
+        // in front of and behind the wall.
+        pipeline.state.raster.point_sizing = PointSizing::World { scale: 1.0 };
+        let squares = (0..4)
+            .map(|i| {
+                let (w, z) = (1.0 + i as f32, if i % 2 == 0 { -0.5 } else { 0.5 });
+                sprite(-0.75 + 0.45 * i as f32, 0.55, z, w, red, 0.5)
+            })
+            .collect();
+        pipeline
+            .draw(
+                &Mesh::new(squares, Topology::PointList),
+                &(),
+                &mut RenderTarget::new(&mut color, &mut depth),
+            )
+            .unwrap();
+
+        // Discs of growing pixel sizes along the bottom, the first two in
+        // front of the wall and the third behind it.
+        pipeline.state.raster.point_sizing = PointSizing::Pixels;
+        pipeline.state.raster.point_shape = PointShape::Round;
+        let discs = vec![
+            sprite(-0.8, -0.5, -0.5, 1.0, blue, 6.0),
+            sprite(-0.45, -0.5, -0.5, 1.0, blue, 11.5),
+            sprite(-0.05, -0.5, 0.5, 1.0, blue, 17.0),
+            sprite(0.6, -0.4, 0.5, 1.0, blue, 21.0),
+        ];
+        pipeline
+            .draw(
+                &Mesh::new(discs, Topology::PointList),
+                &(),
+                &mut RenderTarget::new(&mut color, &mut depth),
+            )
+            .unwrap();
+
+        let mut resolved = FrameBuffer::new(SIZE, SIZE);
+        color.resolve(&mut resolved).unwrap();
+        resolved
+    });
+}
+
+#[test]
+fn comparison_catches_changes() {
+    let mut reference = FrameBuffer::new(SIZE, SIZE);
+    for (i, pixel) in reference.to_array_mut().unwrap().iter_mut().enumerate() {
+        let v = (i % SIZE * 4) as u32;
+        *pixel = 0xff000000 | (v << 16) | (v << 8) | v;
+    }
+    assert!(within_tolerance(&reference, &reference, Tolerance::DEFAULT).is_ok());
+
+    // Off by one everywhere is within tolerance.
+    let mut shifted = reference.clone();
+    for pixel in shifted.to_array_mut().unwrap() {
+        *pixel += 0x010101;
+    }
+    assert!(within_tolerance(&reference, &shifted, Tolerance::DEFAULT).is_ok());
+
+    // A visible blemish isn't.
+    let mut blemished = reference.clone();
+    for pixel in &mut blemished.to_array_mut().unwrap()[..SIZE * 4] {
+        *pixel = 0xffff0000;
+    }
+    assert!(within_tolerance(&reference, &blemished, Tolerance::DEFAULT).is_err());
+
+    let mut diff = FrameBuffer::new(SIZE, SIZE);
+    compare::diff_image(
+        &reference,
+        &blemished,
+        Tolerance::DEFAULT.channel,
+        &mut diff,
+    )
+    .unwrap();
+    assert_eq!(diff.to_array().unwrap()[0] & 0xffff, 0);
+    assert!(diff.to_array().unwrap()[0] >> 16 & 0xff >= 0x80);
+
+    assert!(within_tolerance(&reference, &FrameBuffer::new(SIZE, 1), Tolerance::DEFAULT).is_err());
+}
@@ -0,0 +1,145 @@
+//! Checks renders against reference images, for integration tests to share
+//! by declaring `mod support;`. References live in `tests/golden` as PPMs;
+//! set `BLESS` in the environment to replace them with the renders instead.
+
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+};
+
+use renderer::{
+    buffer::{ops::ToArray, Buffer, FrameBuffer},
+    compare,
+    tile::Tiling,
+};
+
+/// How far a render may stray from its reference.
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+    /// Largest difference in any channel for a pixel to still match.
+    pub channel:   f32,
+    /// Percentage of pixels allowed not to match.
+    pub differing: f32,
+    /// Lowest structural similarity to the reference allowed.
+    pub ssim:      f32,
+}
+
+impl Tolerance {
+    /// Absorbs rounding differences between platforms' floating point
+    /// functions, but not visible changes.
+    pub const DEFAULT: Self = Self {
+        channel:   2.0 / 255.0,
+        differing: 0.5,
+        ssim:      0.99,
+    };
+}
+
+/// Compares the color channels of `actual` against `reference`, describing
+/// every way it's out of tolerance on failure.
+pub fn within_tolerance(
+    reference: &FrameBuffer,
+    actual: &FrameBuffer,
+    tolerance: Tolerance,
+) -> Result<(), String> {
+    if (reference.width, reference.height) != (actual.width, actual.height) {
+        return Err(format!(
+            "size is {}×{}, reference is {}×{}",
+            actual.width, actual.height, reference.width, reference.height
+        ));
+    }
+
+    let differing = compare::differing_pixels(reference, actual, tolerance.channel).unwrap();
+    let percent = differing as f32 * 100.0 / (actual.width * actual.height).max(1) as f32;
+    let similarity = compare::ssim(reference, actual).unwrap();
+
+    let mut problems = Vec::new();
+    if percent > tolerance.differing {
+        problems.push(format!(
+            "{differing} pixels ({percent:.2}%) differ by more than {:.0}/255, \
+             up to {:.0}/255",
+            tolerance.channel * 255.0,
+            compare::max_error(reference, actual).unwrap() * 255.0
+        ));
+    }
+    if similarity < tolerance.ssim {
+        problems.push(format!("SSIM is {similarity:.4}, below {}", tolerance.ssim));
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.join("; "))
+    }
+}
+
+pub fn reference_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.ppm"))
+}
+
+pub fn write_ppm(image: &FrameBuffer, path: &PathBuf) {
+    image
+        .write_ppm(BufWriter::new(File::create(path).unwrap()))
+        .unwrap();
+}
+
+/// Checks `actual` against the reference called `name`, or replaces the
+/// reference when blessing.
+pub fn check(name: &str, actual: &FrameBuffer, tolerance: Tolerance) {
+    let path = reference_path(name);
+
+    if std::env::var_os("BLESS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        write_ppm(actual, &path);
+        return;
+    }
+
+    let reference = match File::open(&path) {
+        Ok(file) => FrameBuffer::read_ppm(BufReader::new(file)).unwrap(),
+        Err(error) => panic!(
+            "{name}: can't open reference {}: {error}; run with BLESS=1 to create it",
+            path.display()
+        ),
+    };
+
+    if let Err(problems) = within_tolerance(&reference, actual, tolerance) {
+        let out = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        fs::create_dir_all(&out).unwrap();
+        let (actual_path, diff_path) = (
+            out.join(format!("{name}.actual.ppm")),
+            out.join(format!("{name}.diff.ppm")),
+        );
+        write_ppm(actual, &actual_path);
+        let mut diff = FrameBuffer::new(reference.width, reference.height);
+        compare::diff_image(&reference, actual, tolerance.channel, &mut diff).unwrap();
+        write_ppm(&diff, &diff_path);
+
+        panic!(
+            "{name}: {problems}\n  render: {}\n  diff:   {}",
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+/// Renders serially and in tiles, which must agree exactly, and checks the
+/// result.
+pub fn check_scene(
+    name: &str,
+    tolerance: Tolerance,
+    render: impl Fn(Option<Tiling>) -> FrameBuffer,
+) {
+    let serial = render(None);
+    let tiled = render(Some(Tiling {
+        tile_size: 16,
+        threads:   4,
+    }));
+
+    assert!(
+        serial.to_array().unwrap() == tiled.to_array().unwrap(),
+        "{name}: tiled render differs from serial"
+    );
+    check(name, &serial, tolerance);
+}